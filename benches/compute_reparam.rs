@@ -0,0 +1,49 @@
+// Benchmarks computing the reparametrizations of all factors of a grid, with and without reusing a buffer
+// Run with `cargo bench --bench compute_reparam`
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use mrf_map::{
+    alg::{
+        solver::{Solver, SolverOptions},
+        srmp::SRMP,
+    },
+    cfn::{
+        cost_function_network::CostFunctionNetwork,
+        relaxation::{ConstructRelaxation, Relaxation},
+    },
+    messages::{message_nd::MessageND, message_trait::Message},
+};
+
+fn compute_reparam(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compute_reparam");
+
+    let cfn = CostFunctionNetwork::random_grid(100, 100, 8);
+    let relaxation = Relaxation::new(&cfn);
+    let mut options = SolverOptions::default();
+    options.set_max_iterations(1);
+    let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+    let messages = srmp.messages();
+
+    group.bench_function("compute_reparam", |b| {
+        b.iter(|| {
+            for factor in relaxation.node_indices() {
+                black_box(messages.compute_reparam(factor).min());
+            }
+        })
+    });
+    group.bench_function("compute_reparam_into", |b| {
+        let mut reparam = MessageND::with_capacity(cfn.max_function_table_len());
+        b.iter(|| {
+            for factor in relaxation.node_indices() {
+                messages.compute_reparam_into(factor, &mut reparam);
+                black_box(reparam.min());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, compute_reparam);
+criterion_main!(benches);
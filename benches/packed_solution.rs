@@ -0,0 +1,141 @@
+// Benchmarks the memory used by solutions to a network with 1M variables: Solution stores an Option<usize>
+// per variable, and PackedSolution stores a single usize per variable
+// Memory is measured as the number of bytes allocated while creating and labeling a solution (via a counting global
+// allocator), so Criterion reports bytes per iteration instead of time
+// Note: PackedSolution is labeled with set(), as it has no IndexMut (see PackedSolution)
+// Run with `cargo bench --bench packed_solution`
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{
+    criterion_group, criterion_main,
+    measurement::{Measurement, ValueFormatter},
+    Criterion, Throughput,
+};
+use mrf_map::cfn::{
+    cost_function_network::CostFunctionNetwork,
+    solution::{PackedSolution, Solution},
+};
+
+// Number of variables in the benchmarked network
+const NUM_VARIABLES: usize = 1_000_000;
+
+// Allocator that counts the total number of allocated bytes (never decreased by deallocations)
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// Measures the number of bytes allocated by the benchmarked routine
+struct AllocatedBytes;
+
+impl Measurement for AllocatedBytes {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> Self::Intermediate {
+        ALLOCATED.load(Ordering::Relaxed)
+    }
+
+    fn end(&self, allocated_before: Self::Intermediate) -> Self::Value {
+        ALLOCATED.load(Ordering::Relaxed) - allocated_before
+    }
+
+    fn add(&self, v1: &Self::Value, v2: &Self::Value) -> Self::Value {
+        v1 + v2
+    }
+
+    fn zero(&self) -> Self::Value {
+        0
+    }
+
+    fn to_f64(&self, value: &Self::Value) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &BytesFormatter
+    }
+}
+
+// Formats the measured values in bytes (or in bytes per variable for throughputs)
+struct BytesFormatter;
+
+impl ValueFormatter for BytesFormatter {
+    fn scale_values(&self, _typical_value: f64, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical_value: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        let elements = match throughput {
+            Throughput::Elements(elements)
+            | Throughput::Bytes(elements)
+            | Throughput::BytesDecimal(elements) => *elements,
+        };
+        for value in values {
+            *value /= elements as f64;
+        }
+        "B/variable"
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+}
+
+fn packed_solution(c: &mut Criterion<AllocatedBytes>) {
+    let mut group = c.benchmark_group("solution_memory");
+    group.throughput(Throughput::Elements(NUM_VARIABLES as u64));
+    // The allocated memory doesn't vary between iterations, so few samples suffice
+    group.sample_size(10);
+
+    let cfn = CostFunctionNetwork::from_domain_sizes(&vec![2; NUM_VARIABLES], false, 0);
+    group.bench_function("Solution", |b| {
+        b.iter(|| {
+            let mut solution = Solution::new(&cfn);
+            for variable in 0..NUM_VARIABLES {
+                solution[variable] = Some(variable % 2);
+            }
+            black_box(solution)
+        })
+    });
+    group.bench_function("PackedSolution", |b| {
+        b.iter(|| {
+            let mut solution = PackedSolution::new(&cfn);
+            for variable in 0..NUM_VARIABLES {
+                solution.set(variable, Some(variable % 2));
+            }
+            black_box(solution)
+        })
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().with_measurement(AllocatedBytes).without_plots();
+    targets = packed_solution
+}
+criterion_main!(benches);
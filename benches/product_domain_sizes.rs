@@ -0,0 +1,64 @@
+// Benchmarks two ways of computing the product of domain sizes of given variables (as in
+// CostFunctionNetwork::product_domain_sizes()) over scopes of various lengths: mapping the variables to domain sizes
+// before folding over them, and folding over the variables directly
+// Both are defined here, so that they are inlined in the same way
+// Run with `cargo bench --bench product_domain_sizes`
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use mrf_map::cfn::cost_function_network::CostFunctionNetwork;
+
+// Computes the product of domain sizes of given variables by mapping them to domain sizes first
+fn product_domain_sizes_map_fold(cfn: &CostFunctionNetwork, variables: &[usize]) -> usize {
+    variables
+        .iter()
+        .map(|variable| cfn.domain_size(*variable))
+        .try_fold(1usize, |product, domain_size| {
+            product.checked_mul(domain_size)
+        })
+        .unwrap_or_else(|| {
+            panic!(
+                "Product of domain sizes of variables {:?} overflows usize.",
+                variables
+            )
+        })
+}
+
+// Computes the product of domain sizes of given variables by folding over them directly
+fn product_domain_sizes_fold(cfn: &CostFunctionNetwork, variables: &[usize]) -> usize {
+    variables
+        .iter()
+        .try_fold(1usize, |product, variable| {
+            product.checked_mul(cfn.domain_size(*variable))
+        })
+        .unwrap_or_else(|| {
+            panic!(
+                "Product of domain sizes of variables {:?} overflows usize.",
+                variables
+            )
+        })
+}
+
+fn product_domain_sizes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("product_domain_sizes");
+
+    let cfn = CostFunctionNetwork::from_domain_sizes(&vec![3; 16], false, 0);
+    for scope_len in [1, 2, 3, 4, 8, 16] {
+        let variables = (0..scope_len).collect::<Vec<_>>();
+        group.bench_with_input(
+            BenchmarkId::new("map_fold", scope_len),
+            &variables,
+            |b, variables| b.iter(|| product_domain_sizes_map_fold(&cfn, black_box(variables))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("fold", scope_len),
+            &variables,
+            |b, variables| b.iter(|| product_domain_sizes_fold(&cfn, black_box(variables))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, product_domain_sizes);
+criterion_main!(benches);
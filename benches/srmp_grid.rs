@@ -0,0 +1,39 @@
+// Benchmarks a full SRMP run on grids of different sizes
+// Run with `cargo bench --bench srmp_grid`
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use mrf_map::{
+    alg::{
+        solver::{Solver, SolverOptions},
+        srmp::SRMP,
+    },
+    cfn::{
+        cost_function_network::CostFunctionNetwork,
+        relaxation::{ConstructRelaxation, Relaxation},
+    },
+};
+
+// Number of labels of every variable in the benchmarked grids
+const NUM_LABELS: usize = 5;
+
+fn srmp_grid(c: &mut Criterion) {
+    let mut group = c.benchmark_group("srmp_grid");
+    group.sample_size(10);
+
+    let options = SolverOptions::default();
+    for size in [16, 32, 64] {
+        let cfn = CostFunctionNetwork::random_grid(size, size, NUM_LABELS);
+        let relaxation = Relaxation::new(&cfn);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{}x{}", size, size)),
+            &size,
+            |b, _size| b.iter(|| black_box(SRMP::solve(&relaxation, &options).unwrap())),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, srmp_grid);
+criterion_main!(benches);
@@ -0,0 +1,97 @@
+#![allow(dead_code)]
+
+use crate::{
+    cfn::relaxation::{ConstructRelaxation, Relaxation},
+    CostFunctionNetwork,
+};
+
+use super::{
+    solver::{Solver, SolverError, SolverOptions, SolverResult},
+    srmp::{MessageScratch, SRMP},
+};
+
+// Solves a sequence of cost function networks (e.g., requests to a server) by SRMP with the same options,
+// reusing the message buffers of each instance for the next one, which saves allocations
+// when instances are of similar size
+pub struct BatchSolver {
+    options: SolverOptions,  // the options used for every instance
+    scratch: MessageScratch, // the message buffers left over from the last instance
+}
+
+impl BatchSolver {
+    // Creates a batch solver with given options and no buffers
+    pub fn new(options: SolverOptions) -> Self {
+        BatchSolver {
+            options,
+            scratch: MessageScratch::new(),
+        }
+    }
+
+    // Returns the options used for every instance
+    pub fn options(&self) -> &SolverOptions {
+        &self.options
+    }
+
+    // Returns the number of message buffers available for the next instance
+    pub fn num_buffers(&self) -> usize {
+        self.scratch.num_messages()
+    }
+
+    // Solves a given cost function network and keeps the message buffers for the next one
+    pub fn solve_instance(
+        &mut self,
+        cfn: &CostFunctionNetwork,
+    ) -> Result<SolverResult, SolverError> {
+        let relaxation = Relaxation::new(cfn);
+        // The buffers are kept if the instance is rejected
+        let srmp = SRMP::init_with_scratch(&relaxation, &mut self.scratch)?.run(&self.options);
+        let result = srmp.result();
+        self.scratch = srmp.into_scratch();
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cfn::cost_function_network::tests::construct_cfn_example_1;
+
+    use super::*;
+
+    #[test]
+    fn solve_instance() {
+        let instances = [
+            CostFunctionNetwork::random_grid(4, 4, 3),
+            construct_cfn_example_1(),
+            CostFunctionNetwork::random_grid(5, 3, 2),
+        ];
+        let construct_options = || {
+            let mut options = SolverOptions::default();
+            options.set_max_iterations(20);
+            options
+        };
+
+        let mut batch_solver = BatchSolver::new(construct_options());
+        assert_eq!(batch_solver.num_buffers(), 0);
+        for cfn in instances.iter() {
+            let result = batch_solver.solve_instance(cfn).unwrap();
+            let relaxation = Relaxation::new(cfn);
+            let expected_result = SRMP::solve(&relaxation, &construct_options()).unwrap();
+
+            assert_eq!(result.lower_bound(), expected_result.lower_bound());
+            assert_eq!(result.best_cost(), expected_result.best_cost());
+            assert_eq!(result.labeling(), expected_result.labeling());
+            assert_eq!(result.iterations(), expected_result.iterations());
+            assert_eq!(batch_solver.num_buffers(), relaxation.edge_count());
+        }
+
+        // Rejected instances don't discard the buffers
+        let num_buffers = batch_solver.num_buffers();
+        assert_eq!(
+            batch_solver
+                .solve_instance(&CostFunctionNetwork::new())
+                .err(),
+            Some(SolverError::EmptyRelaxation)
+        );
+        assert_eq!(batch_solver.num_buffers(), num_buffers);
+    }
+}
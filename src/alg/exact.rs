@@ -0,0 +1,105 @@
+#![allow(dead_code)]
+
+use crate::{cfn::solution::Solution, CostFunctionNetwork};
+
+// Largest number of labelings that brute_force_min() agrees to enumerate
+pub const BRUTE_FORCE_MAX_LABELINGS: usize = 10_000_000;
+
+// Finds an optimal solution by enumerating all labelings of all variables and returns its cost and the solution.
+// Intended as a reference for validating other solvers on tiny instances.
+// Panics if the number of labelings exceeds BRUTE_FORCE_MAX_LABELINGS.
+pub fn brute_force_min(cfn: &CostFunctionNetwork) -> (f64, Solution) {
+    let num_labelings = (0..cfn.num_variables())
+        .try_fold(1usize, |product, variable| {
+            product.checked_mul(cfn.domain_size(variable))
+        })
+        .filter(|num_labelings| *num_labelings <= BRUTE_FORCE_MAX_LABELINGS);
+    assert!(
+        num_labelings.is_some(),
+        "Brute force refuses instances with more than {} labelings.",
+        BRUTE_FORCE_MAX_LABELINGS
+    );
+
+    let mut labeling = vec![0; cfn.num_variables()];
+    let mut best_cost = f64::INFINITY;
+    let mut best_labeling = labeling.clone();
+
+    loop {
+        let solution = Solution::from(
+            labeling
+                .iter()
+                .map(|label| Some(*label))
+                .collect::<Vec<_>>(),
+        );
+        let cost = solution.cost(cfn);
+        if cost < best_cost {
+            best_cost = cost;
+            best_labeling.copy_from_slice(&labeling);
+        }
+
+        // Advance to the next labeling, the last variable changing fastest
+        let mut variable = cfn.num_variables();
+        loop {
+            if variable == 0 {
+                let best_solution =
+                    Solution::from(best_labeling.into_iter().map(Some).collect::<Vec<_>>());
+                return (best_cost, best_solution);
+            }
+            variable -= 1;
+            labeling[variable] += 1;
+            if labeling[variable] < cfn.domain_size(variable) {
+                break;
+            }
+            labeling[variable] = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        alg::{
+            solver::{Solver, SolverOptions},
+            srmp::SRMP,
+        },
+        cfn::{
+            cost_function_network::tests::construct_cfn_example_1,
+            relaxation::{ConstructRelaxation, Relaxation},
+            uai::UAI,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn brute_force_frustrated_cycle() {
+        let cfn =
+            CostFunctionNetwork::read_uai("test_instances/frustrated_cycle_3.uai".into(), false)
+                .unwrap();
+        let (optimum, solution) = brute_force_min(&cfn);
+        assert_eq!(optimum, 1.);
+        assert_eq!(solution.cost(&cfn), optimum);
+    }
+
+    #[test]
+    fn srmp_bounds_brute_force() {
+        let cfn = construct_cfn_example_1();
+        let (optimum, _solution) = brute_force_min(&cfn);
+
+        let relaxation = Relaxation::new(&cfn);
+        let result = SRMP::init(&relaxation)
+            .unwrap()
+            .run(&SolverOptions::default())
+            .result();
+
+        assert!(result.lower_bound() <= optimum + 1e-9);
+        assert!(result.best_cost().unwrap() >= optimum - 1e-9);
+    }
+
+    #[test]
+    #[should_panic(expected = "Brute force refuses")]
+    fn brute_force_too_large() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![10; 8], false, 0);
+        brute_force_min(&cfn);
+    }
+}
@@ -0,0 +1,331 @@
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+use log::info;
+use petgraph::{
+    graph::{EdgeReference, NodeIndex},
+    visit::EdgeRef,
+    Direction::{Incoming, Outgoing},
+};
+
+use crate::{
+    cfn::{relaxation::Relaxation, solution::Solution},
+    messages::{
+        message_nd::{AlignmentIndexing, MessageND},
+        message_trait::Message,
+    },
+    CostFunctionNetwork,
+};
+
+use super::solver::{validate, Solver, SolverError, SolverOptions, SolverResult, StopReason};
+
+// Stores information for plain (not reweighted) loopy min-sum belief propagation (BP) on the relaxation graph.
+// Every edge from alpha to beta carries two messages over the labelings of beta:
+// - the down message from alpha to beta, i.e., the minimum over labelings of alpha consistent with a labeling
+//   of beta of the belief of alpha without the up message along this edge,
+// - the up message from beta to alpha, i.e., the belief of beta without the down message along this edge,
+// where the belief of a factor is its cost plus all its incoming down messages and all its outgoing up messages.
+// For MinimalEdges relaxations, these are the factor-to-variable and the variable-to-factor messages of BP.
+// Messages are updated synchronously: first all up messages, then all down messages, in every iteration.
+// Solutions label every variable by the minimum of its belief, and the solver stops when messages converge.
+// Note: BP doesn't provide a lower bound, so the result only reports the cost of the best solution.
+pub struct LoopyBP<'a> {
+    cfn: &'a CostFunctionNetwork,    // the cost function network
+    relaxation: &'a Relaxation<'a>,  // the relaxation graph
+    down_messages: Vec<MessageND>,   // the messages from the source to the target of every edge
+    up_messages: Vec<MessageND>,     // the messages from the target to the source of every edge
+    best_solution: Option<Solution>, // the best solution found in the last run
+    iterations: usize,               // the number of iterations performed in the last run
+    elapsed_time: Duration,          // the running time of the last run
+    stop_reason: Option<StopReason>, // the reason why the last run stopped (None if the solver hasn't run yet)
+}
+
+impl<'a> LoopyBP<'a> {
+    // Adds the down messages along incoming edges and the up messages along outgoing edges of a given factor
+    // to a given message, skipping a given edge
+    fn add_messages(
+        &self,
+        message: &mut MessageND,
+        factor: NodeIndex<usize>,
+        skip_edge: Option<EdgeReference<'_, AlignmentIndexing, usize>>,
+    ) {
+        let skip_edge = skip_edge.map(|edge| edge.id());
+        for in_edge in self
+            .relaxation
+            .edges_directed(factor, Incoming)
+            .filter(|in_edge| Some(in_edge.id()) != skip_edge)
+        {
+            message.add_assign_incoming(self.down_messages[in_edge.id().index()].as_slice());
+        }
+        for out_edge in self
+            .relaxation
+            .edges_directed(factor, Outgoing)
+            .filter(|out_edge| Some(out_edge.id()) != skip_edge)
+        {
+            message.add_assign_outgoing(
+                self.up_messages[out_edge.id().index()].as_slice(),
+                out_edge.weight(),
+            );
+        }
+    }
+
+    // Computes the belief of a given factor
+    fn compute_belief(&self, factor: NodeIndex<usize>) -> MessageND {
+        let mut belief = MessageND::clone_factor(self.cfn, self.relaxation.factor_origin(factor));
+        self.add_messages(&mut belief, factor, None);
+        belief
+    }
+
+    // Computes the down message along a given edge from the current messages
+    fn compute_down_message(&self, edge: EdgeReference<'_, AlignmentIndexing, usize>) -> MessageND {
+        let alpha = edge.source();
+        let mut belief = MessageND::clone_factor(self.cfn, self.relaxation.factor_origin(alpha));
+        self.add_messages(&mut belief, alpha, Some(edge));
+        let mut message = MessageND::zero(self.cfn, self.relaxation.factor_origin(edge.target()));
+        belief.reparam_min_into(message.as_mut_slice(), edge.weight());
+        message
+    }
+
+    // Computes the up message along a given edge from the current messages
+    fn compute_up_message(&self, edge: EdgeReference<'_, AlignmentIndexing, usize>) -> MessageND {
+        let beta = edge.target();
+        let mut message = MessageND::clone_factor(self.cfn, self.relaxation.factor_origin(beta));
+        self.add_messages(&mut message, beta, Some(edge));
+        message
+    }
+
+    // Shifts a given new message so that its smallest entry becomes 0 (if it is finite),
+    // mixes it into a given old message with a given damping, and returns the total absolute change
+    fn update_message(
+        old_message: &mut MessageND,
+        mut new_message: MessageND,
+        damping: f64,
+    ) -> f64 {
+        let min = *new_message.min();
+        if min.is_finite() {
+            new_message.add_assign_scalar(-min);
+        }
+
+        let mut delta = 0.;
+        for (value, new_value) in old_message.iter_mut().zip(new_message.iter()) {
+            let previous_value = *value;
+            // Infinite entries (hard constraints) are assigned directly to avoid `0 * inf = NaN`
+            *value = match damping == 0. || new_value.is_infinite() {
+                true => *new_value,
+                false => damping * previous_value + (1. - damping) * new_value,
+            };
+            if *value != previous_value {
+                delta += (*value - previous_value).abs();
+            }
+        }
+        delta
+    }
+
+    // Performs one synchronous iteration, i.e., recomputes all up messages, then all down messages,
+    // and returns the total absolute change of all messages
+    fn iterate(&mut self, damping: f64) -> f64 {
+        let mut delta = 0.;
+
+        let up_messages = self
+            .relaxation
+            .edge_references()
+            .map(|edge| self.compute_up_message(edge))
+            .collect::<Vec<_>>();
+        for (old_message, new_message) in self.up_messages.iter_mut().zip(up_messages) {
+            delta += Self::update_message(old_message, new_message, damping);
+        }
+
+        let down_messages = self
+            .relaxation
+            .edge_references()
+            .map(|edge| self.compute_down_message(edge))
+            .collect::<Vec<_>>();
+        for (old_message, new_message) in self.down_messages.iter_mut().zip(down_messages) {
+            delta += Self::update_message(old_message, new_message, damping);
+        }
+
+        delta
+    }
+
+    // Computes a solution by labeling every variable by the minimum of its belief
+    fn compute_solution(&self) -> Solution {
+        let mut solution = Solution::new(self.cfn);
+        for node in self
+            .relaxation
+            .node_indices()
+            .filter(|node| self.relaxation.is_unary_factor(*node))
+        {
+            let variable = self.relaxation.node_variables(node)[0];
+            solution[variable] = Some(self.compute_belief(node).index_min());
+        }
+        solution
+    }
+}
+
+impl<'a> Solver<'a> for LoopyBP<'a> {
+    fn init(relaxation: &'a Relaxation<'a>) -> Result<Self, SolverError> {
+        validate(relaxation)?;
+        let cfn = relaxation.cfn();
+        let zero_messages = || {
+            relaxation
+                .edge_references()
+                .map(|edge| MessageND::zero(cfn, relaxation.factor_origin(edge.target())))
+                .collect::<Vec<_>>()
+        };
+
+        Ok(LoopyBP {
+            cfn,
+            relaxation,
+            down_messages: zero_messages(),
+            up_messages: zero_messages(),
+            best_solution: None,
+            iterations: 0,
+            elapsed_time: Duration::ZERO,
+            stop_reason: None,
+        })
+    }
+
+    fn run(mut self, options: &SolverOptions) -> Self {
+        let time_start = Instant::now();
+        let mut iteration = 0;
+        let mut best_cost = 0.;
+        let mut best_solution: Option<Solution> = None;
+        let stop_reason;
+
+        loop {
+            let delta = self.iterate(options.damping());
+
+            if !options.lower_bound_only() {
+                let solution = self.compute_solution();
+                let cost = solution.cost(self.cfn);
+                if best_solution.is_none() || best_cost > cost {
+                    best_cost = cost;
+                    best_solution = Some(solution);
+                }
+            }
+
+            let elapsed_time = time_start.elapsed();
+            info!(
+                "Loopy BP iteration {}. Elapsed time {:?}. Message change {}. Best cost {}.",
+                iteration, elapsed_time, delta, best_cost
+            );
+
+            // Break if a stopping condition is satisfied
+            // Note: the change of messages is compared against the precision as an improvement of the lower bound
+            iteration += 1;
+            if options.eps().is_converged(0., delta) {
+                info!("Messages changed less than by epsilon. Interrupting.");
+                stop_reason = StopReason::Converged;
+                break;
+            } else if iteration >= options.max_iterations() {
+                info!("Maximum number of iterations reached. Interrupting.");
+                stop_reason = StopReason::MaxIterations;
+                break;
+            } else if elapsed_time >= options.time_max() {
+                info!("Time limit reached. Interrupting.");
+                stop_reason = StopReason::TimeLimit;
+                break;
+            }
+        }
+
+        info!(
+            "Loopy BP finished. Elapsed time {:?}. Best cost {}. Best solution {:?}.",
+            time_start.elapsed(),
+            best_cost,
+            best_solution
+        );
+
+        self.best_solution = best_solution;
+        self.iterations = iteration;
+        self.elapsed_time = time_start.elapsed();
+        self.stop_reason = Some(stop_reason);
+        self
+    }
+
+    fn result(&self) -> SolverResult {
+        // There is no valid lower bound, so the trivial one is reported
+        SolverResult::new(
+            self.cfn,
+            f64::NEG_INFINITY,
+            self.best_solution.as_ref(),
+            self.elapsed_time,
+            self.iterations,
+            self.stop_reason,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        alg::exact::brute_force_min,
+        cfn::{relaxation::ConstructRelaxation, uai::UAI},
+        factors::{factor_type::FactorType, function_table::FunctionTable},
+    };
+
+    use super::*;
+
+    // Constructs a tree of 6 variables with 3 labels each and pairwise costs without ties
+    fn construct_cfn_tree() -> CostFunctionNetwork {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3; 6], true, 5);
+        for (index, (var_a, var_b)) in [(0, 1), (1, 2), (1, 3), (3, 4), (3, 5)]
+            .into_iter()
+            .enumerate()
+        {
+            let table = (0..9)
+                .map(|labeling| {
+                    ((labeling * 7 + index * 5) % 11) as f64 + 0.1 * (labeling as f64).sqrt()
+                })
+                .collect();
+            cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn,
+                vec![var_a, var_b],
+                table,
+            )));
+        }
+        for variable in 0..6 {
+            cfn.update_unary_factor(
+                variable,
+                vec![
+                    (variable % 3) as f64,
+                    1.5,
+                    ((variable + 1) % 3) as f64 * 0.7,
+                ],
+            );
+        }
+        cfn
+    }
+
+    #[test]
+    fn exact_on_tree() {
+        let cfn = construct_cfn_tree();
+        let relaxation = Relaxation::new(&cfn);
+        let result = LoopyBP::solve(&relaxation, &SolverOptions::default()).unwrap();
+
+        // Messages are exact once they have traveled from the leaves to the farthest variable and back,
+        // i.e., after as many iterations as there are factors on the longest path (4),
+        // and the next iteration doesn't change them
+        assert_eq!(result.stop_reason(), Some(StopReason::Converged));
+        assert!(result.iterations() <= 5);
+
+        let (optimal_cost, optimal_solution) = brute_force_min(&cfn);
+        assert_eq!(result.best_cost(), Some(optimal_cost));
+        assert_eq!(result.labeling(), optimal_solution.labels());
+        assert_eq!(result.lower_bound(), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn damping_on_cycle() {
+        let cfn =
+            CostFunctionNetwork::read_uai("test_instances/frustrated_cycle_3.uai".into(), false)
+                .unwrap();
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options.set_damping(0.5).set_max_iterations(100);
+        let result = LoopyBP::solve(&relaxation, &options).unwrap();
+
+        assert_eq!(result.best_cost(), Some(brute_force_min(&cfn).0));
+        assert!(result.iterations() <= 100);
+    }
+}
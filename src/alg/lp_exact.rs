@@ -0,0 +1,86 @@
+#![allow(dead_code)]
+
+use good_lp::{
+    minilp, variable, Expression, ProblemVariables, ResolutionError, Solution as _, SolverModel,
+    Variable,
+};
+
+use crate::cfn::{linear_program::LinearProgram, relaxation::Relaxation};
+
+// Solves a given linear program exactly with the pure Rust minilp solver and returns its optimal value
+// Note: minilp returns NaN values for free variables in OSAC programs, so every variable is split
+// into the difference of two nonnegative ones
+pub fn solve_lp(lp: &LinearProgram) -> Result<f64, ResolutionError> {
+    let mut problem_variables = ProblemVariables::new();
+    let variables: Vec<(Variable, Variable)> = (0..lp.num_variables())
+        .map(|_| {
+            (
+                problem_variables.add(variable().min(0.)),
+                problem_variables.add(variable().min(0.)),
+            )
+        })
+        .collect();
+    let linear_expression = |coefficients: &Vec<(usize, f64)>| {
+        coefficients
+            .iter()
+            .map(|(variable, coefficient)| {
+                let (positive_part, negative_part) = variables[*variable];
+                *coefficient * positive_part - *coefficient * negative_part
+            })
+            .sum::<Expression>()
+    };
+
+    let objective = linear_expression(lp.objective());
+    let mut model = problem_variables.maximise(objective.clone()).using(minilp);
+    for constraint in lp.constraints() {
+        model =
+            model.with(linear_expression(constraint.coefficients()).geq(constraint.lower_bound()));
+    }
+    let solution = model.solve()?;
+    Ok(lp.constant() + solution.eval(&objective))
+}
+
+// Computes the optimal lower bound of a given relaxation, i.e., the optimal value of its LP relaxation,
+// by solving the OSAC linear program exactly (see Relaxation::to_osac_lp())
+// Intended as a reference for validating the lower bounds of message passing solvers on small instances
+pub fn lp_lower_bound(relaxation: &Relaxation) -> Result<f64, ResolutionError> {
+    solve_lp(&relaxation.to_osac_lp())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        alg::{
+            exact::brute_force_min,
+            solver::{Solver, SolverOptions},
+            srmp::SRMP,
+        },
+        cfn::{relaxation::ConstructRelaxation, uai::UAI},
+        CostFunctionNetwork,
+    };
+
+    use super::*;
+
+    #[test]
+    fn lp_lower_bound_frustrated_cycle() {
+        for filename in ["frustrated_cycle_3.uai", "frustrated_cycle_5.uai"] {
+            let cfn =
+                CostFunctionNetwork::read_uai(format!("test_instances/{}", filename).into(), false)
+                    .unwrap();
+            let relaxation = Relaxation::new(&cfn);
+            let lp_optimum = lp_lower_bound(&relaxation).unwrap();
+            let result = SRMP::solve(&relaxation, &SolverOptions::default()).unwrap();
+            assert!(
+                (result.lower_bound() - lp_optimum).abs() < 1e-6,
+                "instance {}: SRMP lower bound {} != LP optimum {}",
+                filename,
+                result.lower_bound(),
+                lp_optimum
+            );
+
+            // The relaxation of a frustrated cycle isn't tight
+            let (optimum, _solution) = brute_force_min(&cfn);
+            assert!(lp_optimum < optimum - 0.5);
+        }
+    }
+}
@@ -1,16 +1,107 @@
 #![allow(dead_code)]
 
-use std::time::Duration;
+use std::{error::Error, fmt::Display, time::Duration};
 
-use crate::{cfn::relaxation::Relaxation, CostFunctionNetwork};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cfn::{relaxation::Relaxation, solution::Solution},
+    CostFunctionNetwork,
+};
+
+// Precision for tracking lower bound improvement
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Eps {
+    AbsoluteEps(f64), // converged if the lower bound increased by less than the given value
+    RelativeEps(f64), // converged if the lower bound increased by less than the given fraction of its magnitude
+}
+
+impl Eps {
+    // Checks if the lower bound improvement from `previous` to `current` is small enough to declare convergence
+    pub fn is_converged(&self, previous: f64, current: f64) -> bool {
+        match self {
+            Eps::AbsoluteEps(eps) => current < previous + eps,
+            Eps::RelativeEps(eps) => current - previous < eps * current.abs().max(1.),
+        }
+    }
+
+    // Checks if the lower bound `current` is within the precision of a given `target` value (or exceeds it)
+    pub fn is_reached(&self, current: f64, target: f64) -> bool {
+        match self {
+            Eps::AbsoluteEps(eps) => current >= target - eps,
+            Eps::RelativeEps(eps) => current >= target - eps * target.abs().max(1.),
+        }
+    }
+}
+
+// Schemes for choosing the scaling factors (omega) of reparametrization updates in the forward and backward passes
+#[derive(Clone, Debug, PartialEq)]
+pub enum WeightScheme {
+    Srmp, // the weights from the SRMP paper, computed from the numbers of edges of each category
+    Uniform(f64), // the same given weight for every node
+    Custom(Vec<f64>), // the given weight for every node, indexed by nodes of the relaxation graph
+}
+
+// Heuristics for extracting primal solutions (labelings) from the current reparametrization
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrimalHeuristic {
+    RestrictedMin, // labels the variables of each factor in the factor sequence by the restricted minimum
+    // of its reparametrization given the already labeled variables (the original SRMP extraction)
+    BeliefArgmin, // labels each variable independently by the minimum of its belief
+    // labels the variables one by one by the minimum of their beliefs given the already labeled variables
+    GreedySweep,
+}
+
+// Reasons for a solver to stop running
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    MaxIterations, // the maximum number of iterations was reached
+    TimeLimit,     // the time limit was reached
+    Converged,     // the lower bound converged (or the best solution was proven optimal)
+    TargetReached, // the lower bound reached the target value
+}
+
+// Errors reported when initializing a solver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolverError {
+    EmptyNetwork,    // the cost function network has no factors
+    EmptyRelaxation, // the relaxation graph has no nodes
+}
+
+impl Display for SolverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolverError::EmptyNetwork => write!(f, "Cost function network has no factors."),
+            SolverError::EmptyRelaxation => write!(f, "Relaxation graph has no nodes."),
+        }
+    }
+}
+
+impl Error for SolverError {}
 
 // Stores options to a cost function network solver
 pub struct SolverOptions {
-    max_iterations: usize, // maximum number of iterations
-    time_max: Duration,    // maximum allowed time limit
-    eps: f64,              // precision for tracking lower bound improvement
+    max_iterations: usize,          // maximum number of iterations
+    time_max: Duration,             // maximum allowed time limit
+    eps: Eps,                       // precision for tracking lower bound improvement
     compute_solution_period: usize, // number of iterations between solution recomputations
-                           // if compute_solution_period = 0, the solution is never computed
+    // if compute_solution_period = 0, the solution is never computed
+    weight_scheme: WeightScheme, // scheme for choosing the scaling factors of reparametrization updates
+    track_message_delta: bool,   // if the total change of messages in every iteration is tracked
+    lower_bound_only: bool, // if only the lower bound is computed (overrides compute_solution_period)
+    hard_constraint_value: f64, // the cost of forbidden labelings, at which message entries are capped
+    renormalize_period: usize,  // number of iterations between renormalizations of all messages
+    // if renormalize_period = 0, messages are never renormalized
+    target_lower_bound: Option<f64>, // the value of the lower bound at which the solver stops (None = no target)
+    primal_heuristic: PrimalHeuristic, // heuristic for extracting solutions
+    icm_sweeps: usize, // maximum number of ICM sweeps applied to the best solution after the run (0 = no ICM)
+    damping: f64,      // the weight of old messages in message updates (0 = no damping)
+    keep_top_k: usize, // the number of best distinct solutions retained by the solver (0 = none)
+    // if messages of non-conflicting factors are sent in parallel
+    #[cfg(feature = "parallel")]
+    parallel: bool,
 }
 
 impl SolverOptions {
@@ -19,8 +110,20 @@ impl SolverOptions {
         SolverOptions {
             max_iterations: 10000,
             time_max: Duration::new(20 * 60, 0), // 20 minutes
-            eps: 1e-8,
+            eps: Eps::AbsoluteEps(1e-8),
             compute_solution_period: 1,
+            weight_scheme: WeightScheme::Srmp,
+            track_message_delta: false,
+            lower_bound_only: false,
+            hard_constraint_value: f64::INFINITY,
+            renormalize_period: 0,
+            target_lower_bound: None,
+            primal_heuristic: PrimalHeuristic::RestrictedMin,
+            icm_sweeps: 0,
+            damping: 0.,
+            keep_top_k: 0,
+            #[cfg(feature = "parallel")]
+            parallel: false,
         }
     }
 
@@ -36,9 +139,15 @@ impl SolverOptions {
         self
     }
 
-    // Sets the precision for tracking lower bound improvement
+    // Sets the (absolute) precision for tracking lower bound improvement
     pub fn set_eps(&mut self, value: f64) -> &mut Self {
-        self.eps = value;
+        self.eps = Eps::AbsoluteEps(value);
+        self
+    }
+
+    // Sets the relative precision for tracking lower bound improvement
+    pub fn set_relative_eps(&mut self, value: f64) -> &mut Self {
+        self.eps = Eps::RelativeEps(value);
         self
     }
 
@@ -48,6 +157,90 @@ impl SolverOptions {
         self
     }
 
+    // Sets the scheme for choosing the scaling factors of reparametrization updates
+    pub fn set_weight_scheme(&mut self, value: WeightScheme) -> &mut Self {
+        self.weight_scheme = value;
+        self
+    }
+
+    // Sets if the total change of messages in every iteration is tracked (for convergence diagnostics)
+    pub fn set_track_message_delta(&mut self, value: bool) -> &mut Self {
+        self.track_message_delta = value;
+        self
+    }
+
+    // Sets if only the lower bound is computed, i.e., solutions are never extracted (not even in the last iteration)
+    pub fn set_lower_bound_only(&mut self, value: bool) -> &mut Self {
+        self.lower_bound_only = value;
+        self
+    }
+
+    // Sets the cost of forbidden labelings in hard constraints (infinity by default)
+    // If the value is finite, message entries are capped at it, i.e., costs saturate at this "top" value
+    // as in many other solvers, which keeps message arithmetic finite (with infinite costs, conflicting
+    // constraints lead to `inf - inf = NaN`); the value should be larger than the cost of any feasible labeling
+    pub fn set_hard_constraint_value(&mut self, value: f64) -> &mut Self {
+        self.hard_constraint_value = value;
+        self
+    }
+
+    // Sets the number of iterations between renormalizations of all messages (0 to never renormalize),
+    // which shift every message by a constant to keep message entries bounded on long runs
+    // (see SRMPMessages::renormalize())
+    pub fn set_renormalize_period(&mut self, value: usize) -> &mut Self {
+        self.renormalize_period = value;
+        self
+    }
+
+    // Sets the value (in the sense of the cost function network, e.g., a known optimum) such that the solver stops
+    // once the lower bound reaches it up to the precision (for maximization, the bound is an upper bound
+    // and the solver stops once it drops to the target)
+    pub fn set_target_lower_bound(&mut self, value: Option<f64>) -> &mut Self {
+        self.target_lower_bound = value;
+        self
+    }
+
+    // Sets the heuristic for extracting solutions (see PrimalHeuristic)
+    pub fn set_primal_heuristic(&mut self, value: PrimalHeuristic) -> &mut Self {
+        self.primal_heuristic = value;
+        self
+    }
+
+    // Sets the maximum number of sweeps of ICM local search applied to the best solution after the run
+    // (0 to skip ICM, see CostFunctionNetwork::icm())
+    pub fn set_icm_sweeps(&mut self, value: usize) -> &mut Self {
+        self.icm_sweeps = value;
+        self
+    }
+
+    // Sets the damping of message updates, i.e., every message is updated to `damping * old + (1 - damping) * new`
+    // (a value in [0, 1), where 0 means no damping; damping counters oscillation on frustrated instances)
+    // Note: with damping, SRMP computes the lower bound from the reparametrization of every factor
+    pub fn set_damping(&mut self, value: f64) -> &mut Self {
+        assert!(
+            (0. ..1.).contains(&value),
+            "Damping must be in the range [0, 1)."
+        );
+        self.damping = value;
+        self
+    }
+
+    // Sets the number of best distinct solutions found during the run that are retained
+    // (0 to retain none, see SRMP::top_solutions())
+    pub fn set_keep_top_k(&mut self, value: usize) -> &mut Self {
+        self.keep_top_k = value;
+        self
+    }
+
+    // Sets if messages are sent in parallel: the factor sequence is partitioned into color classes
+    // of non-conflicting factors, which are processed one after another, with the factors of each class
+    // processed in parallel (note: this changes the order in which factors are processed)
+    #[cfg(feature = "parallel")]
+    pub fn set_parallel(&mut self, value: bool) -> &mut Self {
+        self.parallel = value;
+        self
+    }
+
     // Returns the maximum number of iterations
     pub fn max_iterations(&self) -> usize {
         self.max_iterations
@@ -59,7 +252,7 @@ impl SolverOptions {
     }
 
     // Returns the precision for tracking lower bound improvement
-    pub fn eps(&self) -> f64 {
+    pub fn eps(&self) -> Eps {
         self.eps
     }
 
@@ -67,13 +260,195 @@ impl SolverOptions {
     pub fn compute_solution_period(&self) -> usize {
         self.compute_solution_period
     }
+
+    // Returns the scheme for choosing the scaling factors of reparametrization updates
+    pub fn weight_scheme(&self) -> &WeightScheme {
+        &self.weight_scheme
+    }
+
+    // Returns if the total change of messages in every iteration is tracked
+    pub fn track_message_delta(&self) -> bool {
+        self.track_message_delta
+    }
+
+    // Returns if only the lower bound is computed
+    pub fn lower_bound_only(&self) -> bool {
+        self.lower_bound_only
+    }
+
+    // Returns the cost of forbidden labelings in hard constraints
+    pub fn hard_constraint_value(&self) -> f64 {
+        self.hard_constraint_value
+    }
+
+    // Returns the number of iterations between renormalizations of all messages
+    pub fn renormalize_period(&self) -> usize {
+        self.renormalize_period
+    }
+
+    // Returns the value of the lower bound at which the solver stops
+    pub fn target_lower_bound(&self) -> Option<f64> {
+        self.target_lower_bound
+    }
+
+    // Returns the heuristic for extracting solutions
+    pub fn primal_heuristic(&self) -> PrimalHeuristic {
+        self.primal_heuristic
+    }
+
+    // Returns the maximum number of ICM sweeps applied to the best solution after the run
+    pub fn icm_sweeps(&self) -> usize {
+        self.icm_sweeps
+    }
+
+    // Returns the damping of message updates
+    pub fn damping(&self) -> f64 {
+        self.damping
+    }
+
+    // Returns the number of best distinct solutions retained by the solver
+    pub fn keep_top_k(&self) -> usize {
+        self.keep_top_k
+    }
+
+    // Returns if messages are sent in parallel
+    #[cfg(feature = "parallel")]
+    pub fn parallel(&self) -> bool {
+        self.parallel
+    }
+}
+
+// Stores the outcome of a finished solver run
+// Note: the bound and the cost are reported in the optimization sense of the network,
+// so for maximization problems the "lower bound" is actually an upper bound on the best score
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug)]
+pub struct SolverResult {
+    lower_bound: f64,                // the final lower bound
+    best_cost: Option<f64>, // the cost of the best solution (None if no solution was computed)
+    elapsed_time: Duration, // the running time of the solver
+    iterations: usize,      // the number of performed iterations
+    stop_reason: Option<StopReason>, // the reason why the solver stopped (None if it hasn't run)
+    labeling: Vec<Option<usize>>, // the labels of the best solution (all None if no solution was computed)
+}
+
+impl SolverResult {
+    // Creates a new result from the given data
+    pub fn new(
+        cfn: &CostFunctionNetwork,
+        lower_bound: f64,
+        best_solution: Option<&Solution>,
+        elapsed_time: Duration,
+        iterations: usize,
+        stop_reason: Option<StopReason>,
+    ) -> Self {
+        SolverResult {
+            lower_bound: cfn.cost_to_objective(lower_bound),
+            best_cost: best_solution.map(|solution| cfn.cost_to_objective(solution.cost(cfn))),
+            elapsed_time,
+            iterations,
+            stop_reason,
+            labeling: match best_solution {
+                Some(solution) => solution.labels().clone(),
+                None => vec![None; cfn.num_variables()],
+            },
+        }
+    }
+
+    // Returns the final lower bound
+    pub fn lower_bound(&self) -> f64 {
+        self.lower_bound
+    }
+
+    // Returns the cost of the best solution (None if no solution was computed)
+    pub fn best_cost(&self) -> Option<f64> {
+        self.best_cost
+    }
+
+    // Returns the running time of the solver
+    pub fn elapsed_time(&self) -> Duration {
+        self.elapsed_time
+    }
+
+    // Returns the number of performed iterations
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    // Returns the reason why the solver stopped (None if it hasn't run)
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+
+    // Returns the labels of the best solution
+    pub fn labeling(&self) -> &Vec<Option<usize>> {
+        &self.labeling
+    }
+
+    // Encodes the result as a JSON string
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
 }
 
 // Interface for cost function network solvers
 pub trait Solver<'a> {
-    // Initializes the solver with the given relaxation
-    fn init(cfn: &'a CostFunctionNetwork, relaxation: &'a Relaxation) -> Self;
+    // Initializes the solver with the given relaxation (and the cost function network it was constructed for),
+    // returns an error if there is nothing to solve (see validate())
+    fn init(relaxation: &'a Relaxation<'a>) -> Result<Self, SolverError>
+    where
+        Self: Sized;
 
     // Executes the solver with the given options
     fn run(self, options: &SolverOptions) -> Self;
+
+    // Returns the outcome of the last run
+    fn result(&self) -> SolverResult;
+
+    // Initializes the solver with the given relaxation, executes it with the given options,
+    // and returns the outcome
+    fn solve(
+        relaxation: &'a Relaxation<'a>,
+        options: &SolverOptions,
+    ) -> Result<SolverResult, SolverError>
+    where
+        Self: Sized,
+    {
+        Ok(Self::init(relaxation)?.run(options).result())
+    }
+}
+
+// Checks that a relaxation can be solved, i.e., that its graph has nodes and its network has factors
+// (otherwise the lower bound is meaningless and weight computations may divide by zero)
+pub fn validate(relaxation: &Relaxation) -> Result<(), SolverError> {
+    if relaxation.node_count() == 0 {
+        return Err(SolverError::EmptyRelaxation);
+    }
+    if relaxation.cfn().factors_len() == 0 {
+        return Err(SolverError::EmptyNetwork);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_eps_large_bound() {
+        // On a lower bound of magnitude ~1e6, an improvement of 1e-3 is negligible in relative terms
+        let previous = 1e6;
+        let current = 1e6 + 1e-3;
+
+        let mut options = SolverOptions::default();
+        assert!(!options.eps().is_converged(previous, current));
+
+        options.set_relative_eps(1e-8);
+        assert!(options.eps().is_converged(previous, current));
+
+        // Relative precision is not scaled down for lower bounds of magnitude below 1
+        assert!(!options.eps().is_converged(0., 1e-3));
+        assert!(options.eps().is_converged(0., 1e-9));
+    }
 }
@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
-use std::{cmp::max, time::Instant};
+use std::{
+    cmp::max,
+    fmt::Debug,
+    mem,
+    time::{Duration, Instant},
+};
 
 use bitvec::{order::LocalBits, vec::BitVec};
 use log::{debug, info};
@@ -9,17 +14,23 @@ use petgraph::{
     visit::EdgeRef,
     Direction::{Incoming, Outgoing},
 };
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 use crate::{
     cfn::{factor_sequence::FactorSequence, relaxation::Relaxation, solution::Solution},
+    factors::factor_trait::Factor,
     messages::{
         message_nd::{AlignmentIndexing, MessageND},
         message_trait::Message,
     },
-    CostFunctionNetwork,
+    CostFunctionNetwork, FactorOrigin,
 };
 
-use super::solver::{Solver, SolverOptions};
+use super::solver::{
+    validate, PrimalHeuristic, Solver, SolverError, SolverOptions, SolverResult, StopReason,
+    WeightScheme,
+};
 
 type PassIterator<'a> = Box<dyn Iterator<Item = &'a NodeIndex<usize>> + 'a>;
 
@@ -49,8 +60,23 @@ impl NodeEdgeAttrs {
         }
     }
 
-    // Computes attributes based on the given relaxation and factor sequence
-    fn new<'a>(relaxation: &Relaxation, factor_sequence: &'a FactorSequence) -> NodeEdgeAttrs {
+    // Checks if the given edge is a "forward" edge, i.e., if its source factor is touched again later in the sequence
+    pub fn edge_is_forward(&self, edge: usize) -> bool {
+        self.edge_is_forward[edge]
+    }
+
+    // Checks if the given edge is a "backward" edge, i.e., if its source factor was touched earlier in the sequence
+    pub fn edge_is_backward(&self, edge: usize) -> bool {
+        self.edge_is_backward[edge]
+    }
+
+    // Computes attributes based on the given relaxation and factor sequence,
+    // with scaling factors for reparametrization updates chosen according to the given weight scheme
+    pub fn new(
+        relaxation: &Relaxation,
+        factor_sequence: &FactorSequence,
+        weight_scheme: &WeightScheme,
+    ) -> NodeEdgeAttrs {
         // Initialize node and edge attribute storage
         let mut attrs = NodeEdgeAttrs::zero(relaxation.node_count(), relaxation.edge_count());
 
@@ -135,57 +161,237 @@ impl NodeEdgeAttrs {
             attrs.node_weight_update_lb[alpha] = alpha_weight_backward - weight_in_backward;
         }
 
+        // Override scaling factors if requested
+        match weight_scheme {
+            WeightScheme::Srmp => {}
+            WeightScheme::Uniform(omega) => {
+                attrs.node_omega_forward.fill(*omega);
+                attrs.node_omega_backward.fill(*omega);
+            }
+            WeightScheme::Custom(omegas) => {
+                assert_eq!(
+                    omegas.len(),
+                    relaxation.node_count(),
+                    "Custom weights must be given for every node of the relaxation graph."
+                );
+                attrs.node_omega_forward.copy_from_slice(omegas);
+                attrs.node_omega_backward.copy_from_slice(omegas);
+            }
+        }
+
         attrs
     }
+
+    // Returns the scaling factor for the reparametrization update of the given node in the forward pass
+    pub fn node_omega_forward(&self, node: usize) -> f64 {
+        self.node_omega_forward[node]
+    }
+
+    // Returns the scaling factor for the reparametrization update of the given node in the backward pass
+    pub fn node_omega_backward(&self, node: usize) -> f64 {
+        self.node_omega_backward[node]
+    }
+}
+
+// Stores buffers of messages that outlive a relaxation, so that they can be reused for another one
+// (see SRMPMessages::with_scratch() and BatchSolver)
+pub struct MessageScratch<M: Message = MessageND> {
+    messages: Vec<M>,   // buffers for the messages along the edges
+    reparam: Option<M>, // buffer for reparametrizations (None if not allocated yet)
+}
+
+impl<M: Message> MessageScratch<M> {
+    // Creates empty scratch, i.e., without any buffers
+    pub fn new() -> Self {
+        MessageScratch {
+            messages: Vec::new(),
+            reparam: None,
+        }
+    }
+
+    // Returns the number of message buffers
+    pub fn num_messages(&self) -> usize {
+        self.messages.len()
+    }
+}
+
+impl<M: Message> Default for MessageScratch<M> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // Stores messages and facilitates computations on groups of messages, including reparametrizations
-pub struct SRMPMessages<'a> {
+// The message type is generic to allow specialized message representations, with MessageND used by default
+pub struct SRMPMessages<'a, M: Message = MessageND> {
     cfn: &'a CostFunctionNetwork,
     relaxation: &'a Relaxation<'a>,
-    alignment_indexing: Vec<AlignmentIndexing>, // todo: make generic
-    messages: Vec<MessageND>,                   // todo: make generic
+    messages: Vec<M>,
+    message_snapshot: Option<Vec<f64>>, // values of all messages at the last snapshot
+    reparam_scratch: M, // reusable buffer for reparametrizations, fits the function table of any factor
+    damping_scratch: Vec<f64>, // reusable buffer for computed messages before they are damped
+    hard_constraint_value: f64, // the value at which message entries are capped (no cap if infinite)
+    damping: f64,               // the weight of old values in message updates (0 = no damping)
 }
 
-impl<'a> SRMPMessages<'a> {
+// Note: messages are aligned using the alignment indexing stored in the edges of the relaxation graph
+impl<'a, M> SRMPMessages<'a, M>
+where
+    M: Message<OutgoingAlignment = AlignmentIndexing> + Debug,
+{
     // Creates new zero messages for every edge in a given relaxation
-    fn new(cfn: &'a CostFunctionNetwork, relaxation: &'a Relaxation) -> Self {
-        let mut messages = Vec::with_capacity(relaxation.edge_count());
-        let mut alignment_indexing = Vec::with_capacity(relaxation.edge_count());
-        for edge in relaxation.edge_references() {
-            let alpha = relaxation.factor_origin(edge.source());
-            let beta = relaxation.factor_origin(edge.target());
-            messages.push(MessageND::zero(&cfn, beta));
-            alignment_indexing.push(AlignmentIndexing::new(&cfn, &alpha, &beta));
-        }
+    pub fn new(relaxation: &'a Relaxation) -> Self {
+        let cfn = relaxation.cfn();
+        let messages = relaxation
+            .edge_references()
+            .map(|edge| M::zero(cfn, relaxation.factor_origin(edge.target())))
+            .collect();
 
         SRMPMessages {
             cfn,
             relaxation,
-            alignment_indexing,
             messages,
+            message_snapshot: None,
+            reparam_scratch: M::with_capacity(cfn.max_function_table_len()),
+            damping_scratch: Vec::new(),
+            hard_constraint_value: f64::INFINITY,
+            damping: 0.,
+        }
+    }
+
+    // Sets the value at which message entries are capped (see SolverOptions::set_hard_constraint_value())
+    pub fn set_hard_constraint_value(&mut self, value: f64) -> &mut Self {
+        self.hard_constraint_value = value;
+        self
+    }
+
+    // Sets the weight of old values in message updates (see SolverOptions::set_damping())
+    pub fn set_damping(&mut self, value: f64) -> &mut Self {
+        self.damping = value;
+        self
+    }
+
+    // Returns the value at which message entries are capped
+    pub fn hard_constraint_value(&self) -> f64 {
+        self.hard_constraint_value
+    }
+
+    // Returns the weight of old values in message updates
+    pub fn damping(&self) -> f64 {
+        self.damping
+    }
+
+    // Updates an entry of a message to a given computed value mixed with its old value with a given damping
+    // Infinite values (hard constraints) are assigned directly to avoid `inf - inf = NaN`
+    fn damp(value: &mut f64, computed_value: f64, damping: f64) {
+        *value = match damping == 0. || !value.is_finite() || !computed_value.is_finite() {
+            true => computed_value,
+            false => damping * *value + (1. - damping) * computed_value,
+        };
+    }
+
+    // Caps the entries of a normalized message at a given hard constraint value, if it is finite
+    // Note: reparametrizations of all factors still sum up to the original costs for any messages,
+    // so capping doesn't affect the validity of the lower bound
+    fn saturate(message: &mut [f64], hard_constraint_value: f64) {
+        if hard_constraint_value.is_finite() {
+            message
+                .iter_mut()
+                .for_each(|value| *value = value.min(hard_constraint_value));
+        }
+    }
+
+    // Returns the entries of the message along the edge with a given index
+    pub fn message(&self, edge_index: usize) -> &[f64] {
+        self.messages[edge_index].as_slice()
+    }
+
+    // Remembers the current values of all messages for message_delta()
+    pub fn snapshot_messages(&mut self) {
+        self.message_snapshot = Some(
+            self.messages
+                .iter()
+                .flat_map(|message| message.iter().copied())
+                .collect(),
+        );
+    }
+
+    // Returns the total absolute change of all messages since the last snapshot
+    // (None if no snapshot was taken)
+    pub fn message_delta(&self) -> Option<f64> {
+        self.message_snapshot.as_ref().map(|snapshot| {
+            self.messages
+                .iter()
+                .flat_map(|message| message.iter())
+                .zip(snapshot)
+                .map(|(value, previous_value)| (value - previous_value).abs())
+                .sum()
+        })
+    }
+
+    // Shifts every message by a constant so that its largest and smallest entries have the same absolute value,
+    // which minimizes the largest absolute entry of the message
+    // Note: adding a constant to the message along an edge adds it to the reparametrization of the target factor
+    // and subtracts it from that of the source factor, so the lower bound (the sum of smallest entries
+    // of reparametrizations) and the labelings minimizing every reparametrization don't change
+    // Messages with infinite entries are left unchanged
+    pub fn renormalize(&mut self) {
+        for message in self.messages.iter_mut() {
+            let (min, max) = message
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+                    (min.min(*value), max.max(*value))
+                });
+            let shift = (min + max) / 2.;
+            if shift.is_finite() {
+                message.iter_mut().for_each(|value| *value -= shift);
+            }
+        }
+    }
+
+    // Switches to a given relaxation with the same graph (constructed for a cost function network
+    // with the same factor scopes) and sets all messages to zero, reusing the allocated messages
+    fn rebind(&mut self, relaxation: &'a Relaxation) {
+        self.cfn = relaxation.cfn();
+        self.relaxation = relaxation;
+        self.reset();
+    }
+
+    // Sets all messages to zero, reusing the allocated messages and alignment indexing
+    pub fn reset(&mut self) {
+        for message in self.messages.iter_mut() {
+            message.iter_mut().for_each(|value| *value = 0.);
         }
     }
 
     // Creates a new reparametrization and initializes it with data from a given factor
-    fn init_reparam(&self, factor: NodeIndex<usize>) -> MessageND {
-        MessageND::clone_factor(self.cfn, self.relaxation.factor_origin(factor))
-        // todo: make generic
+    fn init_reparam(&self, factor: NodeIndex<usize>) -> M {
+        M::clone_factor(self.cfn, self.relaxation.factor_origin(factor))
+    }
+
+    // Takes the reusable reparametrization buffer (must be returned with return_reparam_scratch() after use)
+    fn take_reparam_scratch(&mut self) -> M {
+        mem::replace(&mut self.reparam_scratch, M::with_capacity(0))
+    }
+
+    // Returns the reusable reparametrization buffer taken with take_reparam_scratch()
+    fn return_reparam_scratch(&mut self, reparam: M) {
+        self.reparam_scratch = reparam;
     }
 
     // Adds messages along all incoming edges to a given reparametrization
-    fn add_all_incoming_messages(&self, reparam: &mut MessageND, factor: NodeIndex<usize>) {
+    fn add_all_incoming_messages(&self, reparam: &mut M, factor: NodeIndex<usize>) {
         for in_edge in self.relaxation.edges_directed(factor, Incoming) {
-            reparam.add_assign_incoming(&self.messages[in_edge.id().index()]);
+            reparam.add_assign_incoming(self.messages[in_edge.id().index()].as_slice());
         }
     }
 
     // Subtracts messages along all incoming edges to a given reparametrization
-    fn sub_all_outgoing_messages(&self, reparam: &mut MessageND, factor: NodeIndex<usize>) {
+    fn sub_all_outgoing_messages(&self, reparam: &mut M, factor: NodeIndex<usize>) {
         for out_edge in self.relaxation.edges_directed(factor, Outgoing) {
             reparam.sub_assign_outgoing(
-                &self.messages[out_edge.id().index()],
-                &self.alignment_indexing[out_edge.id().index()],
+                self.messages[out_edge.id().index()].as_slice(),
+                out_edge.weight(),
             );
         }
     }
@@ -193,9 +399,9 @@ impl<'a> SRMPMessages<'a> {
     // Subtracts messages along all outgoing edges excep the given one to a given reparametrization
     fn sub_all_other_outgoing_messages(
         &self,
-        reparam: &mut MessageND,
+        reparam: &mut M,
         factor: NodeIndex<usize>,
-        edge: EdgeReference<'_, (), usize>,
+        edge: EdgeReference<'_, AlignmentIndexing, usize>,
     ) {
         if true {
             for out_edge in self
@@ -204,8 +410,8 @@ impl<'a> SRMPMessages<'a> {
                 .filter(|out_edge| out_edge.id().index() != edge.id().index())
             {
                 reparam.sub_assign_outgoing(
-                    &self.messages[out_edge.id().index()],
-                    &self.alignment_indexing[out_edge.id().index()],
+                    self.messages[out_edge.id().index()].as_slice(),
+                    out_edge.weight(),
                 );
             }
         } else {
@@ -214,10 +420,7 @@ impl<'a> SRMPMessages<'a> {
             // - may be faster due to avoiding if-jumps inside for-loop and vectorization of message addition
             // todo: bench performance
             self.sub_all_outgoing_messages(reparam, factor);
-            reparam.add_assign_outgoing(
-                &self.messages[edge.id().index()],
-                &self.alignment_indexing[edge.id().index()],
-            );
+            reparam.add_assign_outgoing(self.messages[edge.id().index()].as_slice(), edge.weight());
         }
     }
 
@@ -225,18 +428,103 @@ impl<'a> SRMPMessages<'a> {
     // over a given reparametrization, then renormalizes the message so that its smallest entry becomes 0
     fn update_and_normalize(
         &mut self,
-        reparam: &MessageND,
-        edge: EdgeReference<'_, (), usize>,
+        reparam: &M,
+        edge: EdgeReference<'_, AlignmentIndexing, usize>,
     ) -> f64 {
-        let delta = self.messages[edge.id().index()]
-            .set_to_reparam_min(&reparam, &self.alignment_indexing[edge.id().index()]);
-        self.messages[edge.id().index()].add_assign_scalar(-delta);
+        let (hard_constraint_value, damping) = (self.hard_constraint_value, self.damping);
+        let message = self.messages[edge.id().index()].as_mut_slice();
+        let delta = match damping > 0. {
+            true => {
+                // The computed message is stored in the scratch buffer and mixed into the old one
+                let computed_message = &mut self.damping_scratch;
+                computed_message.resize(message.len(), 0.);
+                let delta = reparam.reparam_min_into(computed_message, edge.weight());
+                for (value, computed_value) in message.iter_mut().zip(computed_message.iter()) {
+                    Self::damp(value, computed_value - delta, damping);
+                }
+                delta
+            }
+            false => {
+                let delta = reparam.reparam_min_into(message, edge.weight());
+                message.iter_mut().for_each(|value| *value -= delta);
+                delta
+            }
+        };
+        Self::saturate(message, hard_constraint_value);
         delta
     }
 
+    // Performs the same computation as send() in linear time using the distance transform
+    // if the source factor supports it (see FactorType::distance_transform()), has no incoming edges,
+    // and all its outgoing edges point to its variables, and returns None otherwise
+    fn send_distance_transform(
+        &mut self,
+        edge: EdgeReference<'_, AlignmentIndexing, usize>,
+    ) -> Option<f64> {
+        let transform = self.distance_transform_message(edge)?;
+        let delta = transform.iter().copied().fold(f64::INFINITY, f64::min);
+        let (hard_constraint_value, damping) = (self.hard_constraint_value, self.damping);
+        let message = self.messages[edge.id().index()].as_mut_slice();
+        for (value, new_value) in message.iter_mut().zip(transform) {
+            Self::damp(value, new_value - delta, damping);
+        }
+        Self::saturate(message, hard_constraint_value);
+        Some(delta)
+    }
+
+    // Computes the (not normalized) message along a given edge using the distance transform
+    // under the same conditions as send_distance_transform(), and returns None otherwise
+    fn distance_transform_message(
+        &self,
+        edge: EdgeReference<'_, AlignmentIndexing, usize>,
+    ) -> Option<Vec<f64>> {
+        let cfn = self.cfn;
+        let alpha = edge.source();
+        let factor = match cfn.get_factor(self.relaxation.factor_origin(alpha)) {
+            Some(factor) if factor.has_distance_transform() => factor,
+            _ => return None,
+        };
+        if self.relaxation.has_edges(alpha, Incoming) {
+            return None;
+        }
+        let target_variable = match self.relaxation.factor_origin(edge.target()) {
+            FactorOrigin::Variable(variable) => *variable,
+            FactorOrigin::NonUnaryFactor(_) => return None,
+        };
+        let source_variable = factor
+            .variables()
+            .iter()
+            .copied()
+            .find(|variable| *variable != target_variable)
+            .unwrap();
+
+        // The reparametrization of alpha is the factor minus the messages along other outgoing edges,
+        // which only depend on the label of the source variable
+        let mut source_costs = vec![0.; cfn.domain_size(source_variable)];
+        for out_edge in self
+            .relaxation
+            .edges_directed(alpha, Outgoing)
+            .filter(|out_edge| out_edge.id() != edge.id())
+        {
+            match self.relaxation.factor_origin(out_edge.target()) {
+                FactorOrigin::Variable(variable) if *variable == source_variable => {
+                    for (cost, value) in source_costs
+                        .iter_mut()
+                        .zip(self.messages[out_edge.id().index()].as_slice())
+                    {
+                        *cost -= value;
+                    }
+                }
+                _ => return None,
+            }
+        }
+
+        Some(factor.distance_transform(&source_costs, cfn.domain_size(target_variable)))
+    }
+
     // Updates the message corresponding to a given edge by sending messages,
     // i.e., performs a computation from equation (17) in the SRMP paper
-    fn send(&mut self, edge: EdgeReference<'_, (), usize>) -> f64 {
+    pub fn send(&mut self, edge: EdgeReference<'_, AlignmentIndexing, usize>) -> f64 {
         debug!(
             "In send() for edge {} from {} to {}",
             edge.id().index(),
@@ -244,26 +532,99 @@ impl<'a> SRMPMessages<'a> {
             edge.target().index()
         );
 
+        if let Some(delta) = self.send_distance_transform(edge) {
+            return delta;
+        }
+
         let alpha = edge.source();
-        let mut reparam_alpha = self.init_reparam(alpha);
+        let mut reparam_alpha = self.take_reparam_scratch();
+        reparam_alpha.clone_factor_into(self.cfn, self.relaxation.factor_origin(alpha));
         self.add_all_incoming_messages(&mut reparam_alpha, alpha);
         self.sub_all_other_outgoing_messages(&mut reparam_alpha, alpha, edge);
-        self.update_and_normalize(&reparam_alpha, edge)
+        let delta = self.update_and_normalize(&reparam_alpha, edge);
+        self.return_reparam_scratch(reparam_alpha);
+        delta
     }
 
     // Computes a reparametrization for a given factor by sending messages to and from it,
     // i.e., performs a computation from line 5 in the SRMP paper
-    fn compute_reparam(&mut self, factor: NodeIndex<usize>) -> MessageND {
+    pub fn compute_reparam(&self, factor: NodeIndex<usize>) -> M {
+        let mut reparam = M::with_capacity(0);
+        self.compute_reparam_into(factor, &mut reparam);
+        reparam
+    }
+
+    // Performs the same computation as compute_reparam(), but stores the result in a given message,
+    // reusing its allocated memory
+    pub fn compute_reparam_into(&self, factor: NodeIndex<usize>, reparam: &mut M) {
         debug!("In compute_reparam() for factor {}", factor.index());
 
-        let mut reparam = self.init_reparam(factor);
-        self.add_all_incoming_messages(&mut reparam, factor);
-        self.sub_all_outgoing_messages(&mut reparam, factor);
-        reparam
+        reparam.clone_factor_into(self.cfn, self.relaxation.factor_origin(factor));
+        self.add_all_incoming_messages(reparam, factor);
+        self.sub_all_outgoing_messages(reparam, factor);
+    }
+
+    // Computes a reparametrization for a given factor as if messages were first sent along all its incoming edges,
+    // without updating the stored messages (on a tree relaxation with converged messages, this gives min-marginals)
+    pub fn compute_belief(&self, factor: NodeIndex<usize>) -> M {
+        let mut belief = self.init_reparam(factor);
+        for in_edge in self.relaxation.edges_directed(factor, Incoming) {
+            let alpha = in_edge.source();
+            let mut reparam_alpha = self.init_reparam(alpha);
+            self.add_all_incoming_messages(&mut reparam_alpha, alpha);
+            self.sub_all_other_outgoing_messages(&mut reparam_alpha, alpha, in_edge);
+
+            let mut message = M::zero(self.cfn, self.relaxation.factor_origin(factor));
+            reparam_alpha.reparam_min_into(message.as_mut_slice(), in_edge.weight());
+            belief.add_assign_incoming(message.as_slice());
+        }
+        self.sub_all_outgoing_messages(&mut belief, factor);
+        belief
+    }
+
+    // Computes a belief for a given factor as in compute_belief(), but with minimization performed only over labelings
+    // consistent with a given (partial) solution
+    pub fn compute_restricted_belief(&self, factor: NodeIndex<usize>, solution: &Solution) -> M {
+        let mut belief = self.init_reparam(factor);
+        for in_edge in self.relaxation.edges_directed(factor, Incoming) {
+            belief.add_assign_incoming(self.send_restricted(in_edge, solution).as_slice());
+        }
+        self.sub_all_outgoing_messages(&mut belief, factor);
+        belief
+    }
+
+    // Computes the message along a given edge as in send() without storing it (before damping and capping),
+    // and returns it together with the value by which it was normalized
+    #[cfg(feature = "parallel")]
+    fn compute_message(&self, edge: EdgeReference<'_, AlignmentIndexing, usize>) -> (M, f64) {
+        let mut message = M::zero(self.cfn, self.relaxation.factor_origin(edge.target()));
+        let delta = match self.distance_transform_message(edge) {
+            Some(transform) => {
+                message.as_mut_slice().copy_from_slice(&transform);
+                transform.iter().copied().fold(f64::INFINITY, f64::min)
+            }
+            None => {
+                let alpha = edge.source();
+                let mut reparam_alpha = self.init_reparam(alpha);
+                self.add_all_incoming_messages(&mut reparam_alpha, alpha);
+                self.sub_all_other_outgoing_messages(&mut reparam_alpha, alpha, edge);
+                reparam_alpha.reparam_min_into(message.as_mut_slice(), edge.weight())
+            }
+        };
+        message
+            .as_mut_slice()
+            .iter_mut()
+            .for_each(|value| *value -= delta);
+        (message, delta)
     }
 
     // Subtracts a given reparametrization from the message corresponding to a given edge
-    fn sub_assign_reparam(&mut self, reparam: &MessageND, edge: EdgeReference<'_, (), usize>) {
+    // (with damping, only the fraction `1 - damping` of it is subtracted)
+    pub fn sub_assign_reparam(
+        &mut self,
+        reparam: &M,
+        edge: EdgeReference<'_, AlignmentIndexing, usize>,
+    ) {
         debug!(
             "In sub_assign_reparam() for edge {} from {} to {}",
             edge.id().index(),
@@ -271,11 +632,17 @@ impl<'a> SRMPMessages<'a> {
             edge.target().index()
         );
 
-        self.messages[edge.id().index()].sub_assign_incoming(reparam);
+        for (value, reparam_value) in self.messages[edge.id().index()]
+            .iter_mut()
+            .zip(reparam.iter())
+        {
+            *value -= (1. - self.damping) * reparam_value;
+        }
     }
 
-    // Computes the initial reparametrization at the start of the SRMP algorithm for a given factor
-    fn send_srmp_initial(&mut self, factor: NodeIndex<usize>) -> f64 {
+    // Computes the contribution of a given factor to the initial lower bound,
+    // i.e., the smallest entry of its reparametrization
+    fn send_srmp_initial(&self, factor: NodeIndex<usize>) -> f64 {
         debug!("In send_srmp_initial() for factor {}", factor.index());
 
         let mut theta = self.init_reparam(factor);
@@ -289,9 +656,9 @@ impl<'a> SRMPMessages<'a> {
     // Refer to the "Extracting primal solution" subsection in the SRMP section for more details.
     fn send_restricted(
         &self,
-        edge: EdgeReference<'_, (), usize>,
+        edge: EdgeReference<'_, AlignmentIndexing, usize>,
         solution: &Solution,
-    ) -> MessageND {
+    ) -> M {
         debug!(
             "In send_restricted() for edge {} from {} to {}",
             edge.id().index(),
@@ -309,12 +676,9 @@ impl<'a> SRMPMessages<'a> {
             alpha.index(),
             edge.target().index()
         );
-        let restricted_min = reparam_alpha.restricted_min(
-            self.cfn,
-            solution,
-            self.relaxation.factor_origin(alpha),
-            self.relaxation.factor_origin(edge.target()),
-        );
+        let (alpha_origin, beta_origin) = self.relaxation.edge_endpoints_origins(edge.id());
+        let restricted_min =
+            reparam_alpha.restricted_min(self.cfn, solution, alpha_origin, beta_origin);
         debug!(
             "reparam_alpha after taking restricted min: {:?}",
             restricted_min
@@ -324,11 +688,7 @@ impl<'a> SRMPMessages<'a> {
 
     // Computes "restricted" reparametrization of a given factor by sending messages "restricted" by a given solution.
     // Refer to the "Extracting primal solution" subsection in the SRMP section for more details.
-    fn compute_restricted_reparam(
-        &self,
-        factor: NodeIndex<usize>,
-        solution: &Solution,
-    ) -> MessageND {
+    pub fn compute_restricted_reparam(&self, factor: NodeIndex<usize>, solution: &Solution) -> M {
         debug!(
             "In compute_restricted_reparam() for factor {}",
             factor.index()
@@ -337,24 +697,45 @@ impl<'a> SRMPMessages<'a> {
         let mut reparam_beta = self.init_reparam(factor);
         self.sub_all_outgoing_messages(&mut reparam_beta, factor);
         for in_edge in self.relaxation.edges_directed(factor, Incoming) {
-            let alpha = self.relaxation.factor_origin(in_edge.source());
-            let num_labeled = solution.num_labeled(&self.cfn.factor_variables(alpha));
-            if num_labeled > 0 && num_labeled < self.cfn.arity(alpha) {
+            let alpha_variables = self.relaxation.node_variables(in_edge.source());
+            let num_labeled = solution.num_labeled(alpha_variables.iter());
+            if num_labeled > 0 && num_labeled < alpha_variables.len() {
                 let restrected_message = self.send_restricted(in_edge, solution);
-                reparam_beta.add_assign_incoming(&restrected_message);
+                reparam_beta.add_assign_incoming(restrected_message.as_slice());
             } else {
-                reparam_beta.add_assign_incoming(&self.messages[in_edge.id().index()]);
+                reparam_beta.add_assign_incoming(self.messages[in_edge.id().index()].as_slice());
             }
         }
         reparam_beta
     }
 
-    fn get_initial_lower_bound(&mut self) -> f64 {
-        let mut initial_lower_bound = 0.;
+    // Computes the lower bound given by the current reparametrization, i.e., the constant term
+    // and the sum of smallest entries of reparametrizations of all factors
+    // Note: this is valid for any messages, as the reparametrizations of all factors sum up to the original costs
+    pub fn compute_lower_bound(&mut self) -> f64 {
+        let mut reparam = self.take_reparam_scratch();
+        let mut lower_bound = self.cfn.constant();
+        for factor in self.relaxation.node_indices() {
+            self.compute_reparam_into(factor, &mut reparam);
+            lower_bound += reparam.min();
+        }
+        self.return_reparam_scratch(reparam);
+        lower_bound
+    }
+
+    // Computes the initial lower bound, i.e., the total contribution of non-unary factors without any edges.
+    // Such factors are never visited in the forward and backward passes (they are not in the factor sequence),
+    // and their reparametrizations never change, so each of them contributes the minimum of its function table
+    // to the lower bound. Every other factor's contribution is accounted for in the backward pass.
+    // The constant term of the cost function network is included as well.
+    // Note: this only reads the messages; since such factors have no incoming edges, their reparametrizations
+    // are the same as for all-zero messages, so the result doesn't depend on the current messages.
+    fn get_initial_lower_bound(&self) -> f64 {
+        let mut initial_lower_bound = self.cfn.constant();
         for node_index in self.relaxation.node_indices().filter(|node_index| {
-            !self.relaxation.is_unary_factor(*node_index) && // question: why are these factors used for initial lower bound calculation?
-            !self.relaxation.has_edges(*node_index, Incoming) &&
-            !self.relaxation.has_edges(*node_index, Outgoing)
+            !self.relaxation.is_unary_factor(*node_index)
+                && !self.relaxation.has_edges(*node_index, Incoming)
+                && !self.relaxation.has_edges(*node_index, Outgoing)
         }) {
             initial_lower_bound += self.send_srmp_initial(node_index);
         }
@@ -362,56 +743,106 @@ impl<'a> SRMPMessages<'a> {
     }
 }
 
+impl<'a, M> SRMPMessages<'a, M>
+where
+    M: Message<OutgoingAlignment = AlignmentIndexing> + Debug,
+{
+    // Creates new zero messages for every edge in a given relaxation as in new(),
+    // reusing the buffers of given scratch (extra buffers are dropped, missing ones are allocated)
+    pub fn with_scratch(relaxation: &'a Relaxation, scratch: MessageScratch<M>) -> Self {
+        let cfn = relaxation.cfn();
+        let MessageScratch {
+            mut messages,
+            reparam,
+        } = scratch;
+        messages.truncate(relaxation.edge_count());
+        for (message, edge) in messages.iter_mut().zip(relaxation.edge_references()) {
+            message.zero_into(cfn, relaxation.factor_origin(edge.target()));
+        }
+        let num_reused = messages.len();
+        messages.extend(
+            relaxation
+                .edge_references()
+                .skip(num_reused)
+                .map(|edge| M::zero(cfn, relaxation.factor_origin(edge.target()))),
+        );
+
+        SRMPMessages {
+            cfn,
+            relaxation,
+            messages,
+            message_snapshot: None,
+            reparam_scratch: reparam
+                .unwrap_or_else(|| M::with_capacity(cfn.max_function_table_len())),
+            damping_scratch: Vec::new(),
+            hard_constraint_value: f64::INFINITY,
+            damping: 0.,
+        }
+    }
+
+    // Returns the buffers of all messages for reuse with another relaxation
+    pub fn into_scratch(self) -> MessageScratch<M> {
+        MessageScratch {
+            messages: self.messages,
+            reparam: Some(self.reparam_scratch),
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<'a, M> SRMPMessages<'a, M>
+where
+    M: Message<OutgoingAlignment = AlignmentIndexing> + Debug + Send + Sync,
+{
+    // Updates the messages along given edges as in send(), computing them in parallel
+    // Assumption: no message along one of the edges is used in computing the message along another one
+    // Returns the values by which the messages were normalized (in the order of the edges)
+    pub fn send_parallel(
+        &mut self,
+        edges: &[EdgeReference<'_, AlignmentIndexing, usize>],
+    ) -> Vec<f64> {
+        let new_messages = edges
+            .par_iter()
+            .map(|edge| self.compute_message(*edge))
+            .collect::<Vec<_>>();
+        edges
+            .iter()
+            .zip(new_messages)
+            .map(|(edge, (message, delta))| {
+                // The same order as in update_and_normalize(): damp, then cap
+                let (hard_constraint_value, damping) = (self.hard_constraint_value, self.damping);
+                let old_message = self.messages[edge.id().index()].as_mut_slice();
+                for (value, new_value) in old_message.iter_mut().zip(message.iter()) {
+                    Self::damp(value, *new_value, damping);
+                }
+                Self::saturate(old_message, hard_constraint_value);
+                delta
+            })
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        cfn::relaxation::ConstructRelaxation,
-        factors::{factor_trait::Factor, factor_type::FactorType, function_table::FunctionTable},
-        CostFunctionNetwork,
+        alg::exact::brute_force_min,
+        cfn::{
+            cost_function_network::{tests::construct_cfn_example_1, Sense},
+            relaxation::ConstructRelaxation,
+        },
+        factors::{
+            absolute_difference::AbsoluteDifference, factor_type::FactorType,
+            function_table::FunctionTable, truncated_linear::TruncatedLinear,
+        },
     };
 
     use super::*;
 
-    fn construct_cfn_example_1() -> CostFunctionNetwork {
-        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 4, 5], false, 3);
-        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
-            &cfn,
-            vec![0],
-            vec![1., 2., 3.],
-        )));
-        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
-            &cfn,
-            vec![2],
-            vec![11., 12., 13., 14., 15.],
-        )));
-        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
-            &cfn,
-            vec![0, 1],
-            vec![4.; 3 * 4],
-        )));
-        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
-            &cfn,
-            vec![0, 2],
-            vec![5.; 3 * 5],
-        )));
-        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
-            &cfn,
-            vec![1, 2],
-            vec![6.; 4 * 5],
-        )));
-        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
-            &cfn,
-            vec![0, 1, 2],
-            vec![7.; 3 * 4 * 5],
-        )));
-        cfn
-    }
-
     #[test]
     fn new() {
         let cfn = construct_cfn_example_1();
         let relaxation = Relaxation::new(&cfn);
-        let messages = SRMPMessages::new(&cfn, &relaxation);
+        let messages = SRMPMessages::<MessageND>::new(&relaxation);
 
         for (index, edge) in relaxation.edge_references().enumerate() {
             let message_vec: Vec<f64> = messages.messages[index]
@@ -426,11 +857,86 @@ mod tests {
         }
     }
 
+    #[test]
+    fn reset() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        let mut messages = SRMPMessages::<MessageND>::new(&relaxation);
+
+        for message in messages.messages.iter_mut() {
+            message.add_assign_scalar(1.);
+        }
+
+        let messages_ptr = messages.messages.as_ptr();
+        messages.reset();
+        assert_eq!(messages.messages.as_ptr(), messages_ptr);
+        for (index, edge) in relaxation.edge_references().enumerate() {
+            let factor_origin = relaxation.factor_origin(edge.target());
+            assert_eq!(
+                messages.messages[index],
+                MessageND::zero(&cfn, factor_origin)
+            );
+        }
+    }
+
+    // Performs a given number of SRMP-style forward and backward passes
+    fn run_passes<'a>(
+        relaxation: &'a Relaxation,
+        num_iterations: usize,
+    ) -> SRMPMessages<'a, MessageND> {
+        let factor_sequence = FactorSequence::new(relaxation).sort();
+        let attrs = NodeEdgeAttrs::new(relaxation, &factor_sequence, &WeightScheme::Srmp);
+        let mut messages = SRMPMessages::<MessageND>::new(relaxation);
+        let forward: Vec<_> = factor_sequence.iter().copied().collect();
+        let backward: Vec<_> = forward.iter().rev().copied().collect();
+
+        for _ in 0..num_iterations {
+            for (factors, is_forward) in [(&forward, true), (&backward, false)] {
+                for factor in factors.iter() {
+                    for in_edge in relaxation.edges_directed(*factor, Incoming) {
+                        messages.send(in_edge);
+                    }
+                    let mut reparam = messages.compute_reparam(*factor);
+                    reparam.mul_assign_scalar(match is_forward {
+                        true => attrs.node_omega_forward(factor.index()),
+                        false => attrs.node_omega_backward(factor.index()),
+                    });
+                    for in_edge in relaxation.edges_directed(*factor, Incoming) {
+                        messages.sub_assign_reparam(&reparam, in_edge);
+                    }
+                }
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn compute_reparam_into() {
+        let cfn = CostFunctionNetwork::random_grid(3, 4, 3);
+        let relaxation = Relaxation::new(&cfn);
+        let messages = run_passes(&relaxation, 2);
+
+        // Reuse the same buffer for factors of different sizes, both growing and shrinking it
+        let mut reparam = MessageND::with_capacity(0);
+        for factor in relaxation
+            .node_indices()
+            .chain(relaxation.node_indices().rev())
+        {
+            let mut expected = messages.init_reparam(factor);
+            messages.add_all_incoming_messages(&mut expected, factor);
+            messages.sub_all_outgoing_messages(&mut expected, factor);
+
+            messages.compute_reparam_into(factor, &mut reparam);
+            assert_eq!(reparam, expected);
+            assert_eq!(messages.compute_reparam(factor), expected);
+        }
+    }
+
     #[test]
     fn init_reparametrization() {
         let cfn = construct_cfn_example_1();
         let relaxation = Relaxation::new(&cfn);
-        let messages = SRMPMessages::new(&cfn, &relaxation);
+        let messages = SRMPMessages::<MessageND>::new(&relaxation);
 
         for factor in relaxation.node_indices() {
             let reparam = messages.init_reparam(factor);
@@ -452,7 +958,7 @@ mod tests {
     fn add_all_incoming_messages() {
         let cfn = construct_cfn_example_1();
         let relaxation = Relaxation::new(&cfn);
-        let mut messages = SRMPMessages::new(&cfn, &relaxation);
+        let mut messages = SRMPMessages::<MessageND>::new(&relaxation);
 
         for message in messages.messages.iter_mut() {
             message.add_assign_scalar(1.);
@@ -475,90 +981,1314 @@ mod tests {
         }
     }
 
-    // todo: add tests for remaining functions, use the stub below
+    #[test]
+    fn initial_lower_bound() {
+        // Every non-unary factor in a MinimalEdges relaxation has outgoing edges,
+        // so no factor contributes to the initial lower bound
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        let messages = SRMPMessages::<MessageND>::new(&relaxation);
+        assert_eq!(messages.get_initial_lower_bound(), 0.);
 
-    // #[test]
-    // fn sub_all_outgoing_messages() {
-    //     todo!();
-    // }
+        let mut cfn = construct_cfn_example_1();
+        cfn.add_constant(10.);
+        let relaxation = Relaxation::new(&cfn);
+        let messages = SRMPMessages::<MessageND>::new(&relaxation);
+        assert_eq!(messages.get_initial_lower_bound(), 10.);
+    }
 
-    // #[test]
-    // fn sub_all_other_outgoing_messages() {
-    //     todo!();
-    // }
+    #[test]
+    fn initial_lower_bound_isolated_factor() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 3], false, 1);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            vec![3., 1.5, 4., 2., 5., 9.],
+        )));
+        let mut relaxation = Relaxation::new(&cfn);
+        let factor_node = relaxation
+            .node_indices()
+            .find(|node| !relaxation.is_unary_factor(*node))
+            .unwrap();
+        relaxation.isolate_node(factor_node);
+
+        let messages = SRMPMessages::<MessageND>::new(&relaxation);
+        assert_eq!(messages.get_initial_lower_bound(), 1.5);
+    }
 
-    // #[test]
-    // fn subtract_all_other_outgoing_messages_alt() {
-    //     todo!();
-    // }
+    #[test]
+    fn initial_lower_bound_idempotent() {
+        let mut cfn = construct_cfn_example_1();
+        cfn.add_constant(2.);
+        let relaxation = Relaxation::new(&cfn);
+        let messages = SRMPMessages::<MessageND>::new(&relaxation);
+        let message_snapshot: Vec<Vec<f64>> = (0..relaxation.edge_count())
+            .map(|index| messages.message(index).to_vec())
+            .collect();
 
-    // #[test]
-    // fn update_and_normalize() {
-    //     todo!();
-    // }
+        let initial_lower_bound = messages.get_initial_lower_bound();
+        assert_eq!(messages.get_initial_lower_bound(), initial_lower_bound);
 
-    // #[test]
-    // fn send() {
-    //     todo!();
-    // }
+        // Messages stay all zero
+        for (index, message) in message_snapshot.iter().enumerate() {
+            assert_eq!(messages.message(index), message.as_slice());
+            assert!(message.iter().all(|value| *value == 0.));
+        }
+    }
 
-    // #[test]
-    // fn compute_reparam() {
-    //     todo!();
-    // }
+    #[test]
+    fn reinit_reuses_messages() {
+        let cfn = construct_cfn_example_1();
+        let mut cfn_updated = construct_cfn_example_1();
+        cfn_updated.update_unary_factor(0, vec![3., 2., 1.]);
 
-    // #[test]
-    // fn sub_assign_reparam() {
-    //     todo!();
-    // }
+        let relaxation = Relaxation::new(&cfn);
+        let relaxation_updated = Relaxation::new(&cfn_updated);
+
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(1);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+        let messages_ptr = srmp.messages.messages.as_ptr();
+        let srmp = srmp.reinit(&relaxation_updated);
+
+        // The solver switches to the new relaxation and network, but keeps the allocated messages
+        assert!(std::ptr::eq(srmp.relaxation, &relaxation_updated));
+        assert!(std::ptr::eq(srmp.cfn, srmp.relaxation.cfn()));
+        assert!(std::ptr::eq(srmp.messages.messages.as_ptr(), messages_ptr));
+        for (message, edge) in srmp
+            .messages
+            .messages
+            .iter()
+            .zip(relaxation.edge_references())
+        {
+            assert_eq!(
+                *message,
+                MessageND::zero(&cfn_updated, relaxation.factor_origin(edge.target()))
+            );
+        }
+        assert_eq!(
+            srmp.messages
+                .init_reparam(relaxation.node_indices().next().unwrap())
+                .iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![3., 2., 1.]
+        );
 
-    // #[test]
-    // fn send_srmp_initial() {
-    //     todo!();
-    // }
+        srmp.run(&options);
+    }
 
-    // #[test]
-    // fn send_restricted() {
-    //     todo!();
-    // }
+    #[test]
+    fn reinit_added_unary_factor() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 2], false, 1);
+        cfn.add_potts_factor(0, 1, 1.);
+        let mut cfn_updated = cfn.clone();
+        cfn_updated.update_unary_factor(0, vec![2., 3.]);
 
-    // #[test]
-    // fn compute_restricted_reparam() {
-    //     todo!();
-    // }
-}
+        let relaxation = Relaxation::new(&cfn);
+        let relaxation_updated = Relaxation::new(&cfn_updated);
+        let srmp = SRMP::init(&relaxation).unwrap();
+        let mut srmp = srmp.reinit(&relaxation_updated);
+
+        // The running costs of solutions include the new unary factor
+        for _ in 0..3 {
+            let mut forward_solution = srmp.init_solution(true);
+            srmp.forward_pass(&mut forward_solution);
+            let forward_solution = forward_solution.unwrap();
+            assert_eq!(
+                forward_solution.running_cost(),
+                Some(forward_solution.cost(&cfn_updated))
+            );
 
-// Stores information for the SRMP algorithm
-pub struct SRMP<'a> {
-    cfn: &'a CostFunctionNetwork,    // the cost function network
-    relaxation: &'a Relaxation<'a>,  // the relaxation graph
-    node_edge_attrs: NodeEdgeAttrs, // the attributes used in the computations in the forward and backward passes
-    messages: SRMPMessages<'a>,     // the messages sent along the edges of the relaxation graph
-    factor_sequence: FactorSequence, // the sequence of factors considered in the forward and backward passes
-    initial_lower_bound: f64,        // the initial lower bound
+            let mut backward_solution = srmp.init_solution(true);
+            srmp.backward_pass(&mut backward_solution);
+            let backward_solution = backward_solution.unwrap();
+            assert_eq!(
+                backward_solution.running_cost(),
+                Some(backward_solution.cost(&cfn_updated))
+            );
+        }
+    }
+
+    #[test]
+    fn send_absolute_difference() {
+        let domain_size = 10;
+        let mut cfn_fast = CostFunctionNetwork::from_domain_sizes(&vec![domain_size; 2], false, 1);
+        let factor = AbsoluteDifference::new(&cfn_fast, vec![0, 1], 1.5);
+        let dense_table = factor.clone_function_table();
+        cfn_fast.add_factor(FactorType::AbsoluteDifference(factor));
+        let mut cfn_dense = CostFunctionNetwork::from_domain_sizes(&vec![domain_size; 2], false, 1);
+        cfn_dense.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn_dense,
+            vec![0, 1],
+            dense_table,
+        )));
+
+        let relaxation_fast = Relaxation::new(&cfn_fast);
+        let relaxation_dense = Relaxation::new(&cfn_dense);
+        let mut messages_fast = SRMPMessages::<MessageND>::new(&relaxation_fast);
+        let mut messages_dense = SRMPMessages::<MessageND>::new(&relaxation_dense);
+
+        // Send messages along both edges several times, starting with non-trivial messages
+        let initial = [7., -2., 3., 0.5, 9., -4., 1., 6., -1., 2.];
+        for messages in [&mut messages_fast, &mut messages_dense] {
+            for message in messages.messages.iter_mut() {
+                message
+                    .iter_mut()
+                    .zip(initial)
+                    .for_each(|(value, initial)| *value = initial);
+            }
+        }
+        for _ in 0..3 {
+            for (edge_fast, edge_dense) in relaxation_fast
+                .edge_references()
+                .zip(relaxation_dense.edge_references())
+            {
+                let delta_fast = messages_fast.send(edge_fast);
+                let delta_dense = messages_dense.send(edge_dense);
+                assert!((delta_fast - delta_dense).abs() < 1e-9);
+
+                let index = edge_fast.id().index();
+                for (value_fast, value_dense) in messages_fast.messages[index]
+                    .iter()
+                    .zip(messages_dense.messages[index].iter())
+                {
+                    assert!((value_fast - value_dense).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn result_json() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(5);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+
+        let json = srmp.result().to_json();
+        let result: SolverResult = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(result.labeling().len(), cfn.num_variables());
+        assert_eq!(result.iterations(), srmp.iterations);
+        assert_eq!(result.lower_bound(), srmp.lower_bound);
+    }
+
+    // Constructs a chain of 4 variables, where SRMP is exact
+    fn construct_cfn_chain() -> CostFunctionNetwork {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 2, 3, 2], true, 3);
+        let unary_tables = [
+            vec![1., 0., 2.],
+            vec![0.5, 0.],
+            vec![3., 1., 0.],
+            vec![0., 2.],
+        ];
+        for (variable, table) in unary_tables.into_iter().enumerate() {
+            cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn,
+                vec![variable],
+                table,
+            )));
+        }
+        let pairwise_tables = [
+            vec![0., 2., 1., 0., 3., 1.],
+            vec![1., 0., 4., 2., 2., 0.],
+            vec![0., 1., 2., 0., 1., 3.],
+        ];
+        for (variable, table) in pairwise_tables.into_iter().enumerate() {
+            cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn,
+                vec![variable, variable + 1],
+                table,
+            )));
+        }
+        cfn
+    }
+
+    #[test]
+    fn dual_gap_tree() {
+        let cfn = construct_cfn_chain();
+        let relaxation = Relaxation::new(&cfn);
+        let srmp = SRMP::init(&relaxation).unwrap();
+        assert_eq!(srmp.dual_gap(), None);
+
+        let srmp = srmp.run(&SolverOptions::default());
+        assert!(srmp.dual_gap().unwrap().abs() < 1e-9);
+        assert!(srmp.is_optimal(1e-9));
+    }
+
+    #[test]
+    fn damping() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        let (optimal_cost, _) = brute_force_min(&cfn);
+        let run = |damping: f64| {
+            let mut options = SolverOptions::default();
+            options.set_damping(damping);
+            SRMP::init(&relaxation).unwrap().run(&options)
+        };
+
+        // No damping reproduces the default behavior exactly
+        let undamped = run(0.);
+        let default = SRMP::init(&relaxation)
+            .unwrap()
+            .run(&SolverOptions::default());
+        assert_eq!(
+            undamped.lower_bound_history(),
+            default.lower_bound_history()
+        );
+        for edge in 0..relaxation.edge_count() {
+            assert_eq!(
+                undamped.messages().message(edge),
+                default.messages().message(edge)
+            );
+        }
+
+        // Damping changes the trajectory, but every lower bound is valid and the bound still converges
+        let damped = run(0.5);
+        assert!((0..relaxation.edge_count())
+            .any(|edge| damped.messages().message(edge) != undamped.messages().message(edge)));
+        assert!(damped
+            .lower_bound_history()
+            .iter()
+            .all(|lower_bound| *lower_bound <= optimal_cost + 1e-9));
+        assert_eq!(damped.stop_reason(), Some(StopReason::Converged));
+        assert!((damped.result().lower_bound() - optimal_cost).abs() < 1e-6);
+        assert_eq!(damped.result().best_cost(), Some(optimal_cost));
+    }
+
+    #[test]
+    fn exact_on_tree() {
+        let cfn = construct_cfn_chain();
+        let relaxation = Relaxation::new(&cfn);
+        assert!(relaxation.is_tree());
+
+        let options = SolverOptions::default();
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+        assert!(srmp.is_exact());
+        assert!(srmp.dual_gap().unwrap().abs() < 1e-9);
+        assert_eq!(srmp.iterations(), 1);
+        assert_eq!(srmp.stop_reason(), Some(StopReason::Converged));
+
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        assert!(!relaxation.is_tree());
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+        assert!(!srmp.is_exact());
+    }
+
+    #[test]
+    fn exact_on_tree_one_sweep() {
+        let cfn = construct_cfn_chain();
+        let relaxation = Relaxation::new(&cfn);
+        let (optimal_cost, _) = brute_force_min(&cfn);
+
+        // The solver stops after one sweep regardless of solution extraction, weights, and damping
+        let mut lower_bound_only = SolverOptions::default();
+        lower_bound_only.set_lower_bound_only(true);
+        let mut no_solutions = SolverOptions::default();
+        no_solutions.set_compute_solution_period(0);
+        let mut belief_argmin = SolverOptions::default();
+        belief_argmin.set_primal_heuristic(PrimalHeuristic::BeliefArgmin);
+        let mut damped = SolverOptions::default();
+        damped.set_damping(0.5);
+        let mut custom_weights = SolverOptions::default();
+        custom_weights
+            .set_weight_scheme(WeightScheme::Uniform(0.5))
+            .set_icm_sweeps(0);
+        for options in [
+            lower_bound_only,
+            no_solutions,
+            belief_argmin,
+            damped,
+            custom_weights,
+        ] {
+            let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+            assert!(srmp.is_exact());
+            assert_eq!(srmp.iterations(), 1);
+            assert_eq!(srmp.stop_reason(), Some(StopReason::Converged));
+            assert!((srmp.result().lower_bound() - optimal_cost).abs() < 1e-9);
+            match options.lower_bound_only() {
+                true => assert_eq!(srmp.result().best_cost(), None),
+                false => assert_eq!(srmp.result().best_cost(), Some(optimal_cost)),
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_weight_scheme() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        let factor_sequence = FactorSequence::new(&relaxation).sort();
+        let attrs = NodeEdgeAttrs::new(&relaxation, &factor_sequence, &WeightScheme::Uniform(1.));
+        for node in relaxation.node_indices() {
+            assert_eq!(attrs.node_omega_forward(node.index()), 1.);
+            assert_eq!(attrs.node_omega_backward(node.index()), 1.);
+        }
+
+        let (optimum, _solution) = brute_force_min(&cfn);
+        let mut options = SolverOptions::default();
+        options
+            .set_max_iterations(20)
+            .set_weight_scheme(WeightScheme::Uniform(1.));
+        let result = SRMP::solve(&relaxation, &options).unwrap();
+        assert!(result.lower_bound() <= optimum + 1e-9);
+        assert!(result.best_cost().unwrap() >= optimum - 1e-9);
+    }
+
+    #[test]
+    fn stop_reason_max_iterations() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        let srmp = SRMP::init(&relaxation).unwrap();
+        assert_eq!(srmp.stop_reason(), None);
+
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(1);
+        let srmp = srmp.run(&options);
+        assert_eq!(srmp.iterations(), 1);
+        assert_eq!(srmp.stop_reason(), Some(StopReason::MaxIterations));
+    }
+
+    #[test]
+    fn message_delta_decreases() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(1);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+        assert_eq!(srmp.last_iteration_message_delta(), None);
+
+        options.set_track_message_delta(true);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+        let first_delta = srmp.last_iteration_message_delta().unwrap();
+        assert!(first_delta > 0.);
+
+        options.set_max_iterations(5).set_eps(f64::NEG_INFINITY);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+        assert_eq!(srmp.iterations(), 5);
+        let last_delta = srmp.last_iteration_message_delta().unwrap();
+        assert!(last_delta < first_delta);
+    }
+
+    #[test]
+    fn lower_bound_history() {
+        let cfn = CostFunctionNetwork::random_grid(4, 4, 3);
+        let relaxation = Relaxation::new(&cfn);
+        let srmp = SRMP::init(&relaxation).unwrap();
+        assert!(srmp.lower_bound_history().is_empty());
+
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(10).set_eps(f64::NEG_INFINITY);
+        let srmp = srmp.run(&options);
+        let history = srmp.lower_bound_history();
+        assert_eq!(history.len(), srmp.iterations());
+        assert_eq!(*history.last().unwrap(), srmp.result().lower_bound());
+        for window in history.windows(2) {
+            assert!(window[0] <= window[1] + 1e-9);
+        }
+    }
+
+    #[test]
+    fn relative_eps_large_bound() {
+        // A constant term of 1e6 shifts the lower bound without changing the improvements between iterations
+        let mut cfn = CostFunctionNetwork::random_grid(8, 8, 4);
+        cfn.add_constant(1e6);
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(200);
+        let result_absolute = SRMP::solve(&relaxation, &options).unwrap();
+        options.set_relative_eps(1e-6);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+
+        // The run stops once the lower bound improves by less than 1e-6 * 1e6 = 1,
+        // before the default absolute precision would stop it
+        assert!(srmp.result().lower_bound() > 1e6);
+        assert_eq!(srmp.stop_reason(), Some(StopReason::Converged));
+        assert!(srmp.iterations() < result_absolute.iterations());
+        let history = srmp.lower_bound_history();
+        let last_improvement = history[history.len() - 1] - history[history.len() - 2];
+        assert!(last_improvement < 1.);
+        assert!(history
+            .windows(2)
+            .rev()
+            .skip(1)
+            .all(|window| window[1] - window[0] >= 1.));
+        assert!(
+            result_absolute.lower_bound() - srmp.result().lower_bound()
+                < 1e-5 * result_absolute.lower_bound()
+        );
+    }
+
+    #[test]
+    fn time_limit_best_lower_bound() {
+        let cfn = CostFunctionNetwork::random_grid(4, 4, 3);
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options
+            .set_time_max(Duration::from_nanos(1))
+            .set_weight_scheme(WeightScheme::Uniform(1.));
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+
+        assert_eq!(srmp.stop_reason(), Some(StopReason::TimeLimit));
+        let history = srmp.lower_bound_history();
+        assert_eq!(history.len(), srmp.iterations());
+        let max_lower_bound = history.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        assert_eq!(srmp.result().lower_bound(), max_lower_bound);
+
+        // The best lower bound is reported for other stop reasons as well, even if the last one is lower
+        options
+            .set_time_max(Duration::MAX)
+            .set_max_iterations(20)
+            .set_eps(f64::NEG_INFINITY);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+
+        assert_eq!(srmp.stop_reason(), Some(StopReason::MaxIterations));
+        let history = srmp.lower_bound_history();
+        assert_eq!(history.len(), 20);
+        let max_lower_bound = history.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        assert!(*history.last().unwrap() < max_lower_bound);
+        assert_eq!(srmp.result().lower_bound(), max_lower_bound);
+    }
+
+    #[test]
+    fn top_solutions() {
+        let cfn = CostFunctionNetwork::random_grid(6, 6, 4);
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(20).set_eps(f64::NEG_INFINITY);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+        assert!(srmp.top_solutions().is_empty());
+
+        // Retain all distinct solutions found during the run (runs start from scratch to find the same solutions)
+        options.set_keep_top_k(usize::MAX);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+        let all_solutions = srmp.top_solutions().to_vec();
+        assert!(all_solutions.len() > 3);
+
+        options.set_keep_top_k(3);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+        let top_solutions = srmp.top_solutions();
+        assert_eq!(top_solutions, &all_solutions[..3]);
+        for window in top_solutions.windows(2) {
+            assert!(window[0].0 <= window[1].0);
+            assert!(window[0].1 != window[1].1);
+        }
+        for (cost, solution) in top_solutions {
+            assert_eq!(*cost, solution.cost(&cfn));
+        }
+        assert_eq!(Some(&top_solutions[0].1), srmp.best_solution.as_ref());
+    }
+
+    #[test]
+    fn running_cost() {
+        let cfn = CostFunctionNetwork::random_grid(4, 4, 3);
+        let relaxation = Relaxation::new(&cfn);
+        let mut srmp = SRMP::init(&relaxation).unwrap();
+
+        for _ in 0..3 {
+            let mut forward_solution = srmp.init_solution(true);
+            srmp.forward_pass(&mut forward_solution);
+            let forward_solution = forward_solution.unwrap();
+            assert!(
+                forward_solution.is_fully_labeled(&(0..cfn.num_variables()).collect::<Vec<_>>())
+            );
+            assert!(
+                (forward_solution.running_cost().unwrap() - forward_solution.cost(&cfn)).abs()
+                    < 1e-9
+            );
+
+            let mut backward_solution = srmp.init_solution(true);
+            srmp.backward_pass(&mut backward_solution);
+            let backward_solution = backward_solution.unwrap();
+            assert!(
+                (backward_solution.running_cost().unwrap() - backward_solution.cost(&cfn)).abs()
+                    < 1e-9
+            );
+        }
+    }
+
+    #[test]
+    fn init_empty() {
+        let cfn = CostFunctionNetwork::new();
+        let relaxation = Relaxation::new(&cfn);
+        assert_eq!(
+            SRMP::init(&relaxation).err(),
+            Some(SolverError::EmptyRelaxation)
+        );
+
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![2; 3], false, 0);
+        let relaxation = Relaxation::new(&cfn);
+        assert_eq!(
+            SRMP::init(&relaxation).err(),
+            Some(SolverError::EmptyNetwork)
+        );
+        assert_eq!(
+            SRMP::solve(&relaxation, &SolverOptions::default()).err(),
+            Some(SolverError::EmptyNetwork)
+        );
+    }
+
+    #[test]
+    fn primal_heuristic() {
+        let cfn = CostFunctionNetwork::random_grid(5, 5, 3);
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(20);
+        let result_restricted_min = SRMP::solve(&relaxation, &options).unwrap();
+        options.set_primal_heuristic(PrimalHeuristic::BeliefArgmin);
+        let result_belief_argmin = SRMP::solve(&relaxation, &options).unwrap();
+        options.set_primal_heuristic(PrimalHeuristic::GreedySweep);
+        let result_greedy_sweep = SRMP::solve(&relaxation, &options).unwrap();
+
+        // The heuristics don't affect the messages, so the lower bounds are the same
+        assert_eq!(
+            result_restricted_min.lower_bound(),
+            result_belief_argmin.lower_bound()
+        );
+        assert_eq!(
+            result_restricted_min.lower_bound(),
+            result_greedy_sweep.lower_bound()
+        );
+
+        // All heuristics produce full labelings, whose costs are bounded from below by the lower bound
+        for result in [
+            &result_restricted_min,
+            &result_belief_argmin,
+            &result_greedy_sweep,
+        ] {
+            let solution = Solution::from(result.labeling().clone());
+            assert!(solution.is_fully_labeled(&(0..cfn.num_variables()).collect::<Vec<_>>()));
+            assert!(solution
+                .labels()
+                .iter()
+                .enumerate()
+                .all(|(variable, label)| label.unwrap() < cfn.domain_size(variable)));
+            assert_eq!(result.best_cost(), Some(solution.cost(&cfn)));
+            assert!(solution.cost(&cfn) >= result.lower_bound() - 1e-9);
+        }
+    }
+
+    #[test]
+    fn primal_heuristic_chain() {
+        // On a chain, the restricted minimum and the greedy sweep find an optimal solution, since each label is chosen
+        // given the labels of its neighbors, while the beliefs alone can have ties that are broken inconsistently
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3; 6], false, 11);
+        for variable in 0..6 {
+            let table = (0..3)
+                .map(|label| ((7 * variable + 3 * label) as f64 * 0.37).sin())
+                .collect();
+            cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn,
+                vec![variable],
+                table,
+            )));
+        }
+        for variable in 0..5 {
+            let table = (0..9)
+                .map(|index| ((11 * variable + index) as f64 * 1.3).cos())
+                .collect();
+            cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn,
+                vec![variable, variable + 1],
+                table,
+            )));
+        }
+        let relaxation = Relaxation::new(&cfn);
+        let (optimum, _solution) = brute_force_min(&cfn);
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(10);
+        for primal_heuristic in [PrimalHeuristic::RestrictedMin, PrimalHeuristic::GreedySweep] {
+            options.set_primal_heuristic(primal_heuristic);
+            let result = SRMP::solve(&relaxation, &options).unwrap();
+            assert!(
+                (result.best_cost().unwrap() - optimum).abs() < 1e-9,
+                "{:?}",
+                primal_heuristic
+            );
+        }
+    }
+
+    #[test]
+    fn icm_sweeps() {
+        let cfn = CostFunctionNetwork::random_grid(5, 5, 3);
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options
+            .set_max_iterations(3)
+            .set_primal_heuristic(PrimalHeuristic::BeliefArgmin);
+        let result = SRMP::solve(&relaxation, &options).unwrap();
+        options.set_icm_sweeps(10);
+        let result_icm = SRMP::solve(&relaxation, &options).unwrap();
+
+        assert!(result_icm.best_cost().unwrap() <= result.best_cost().unwrap());
+        assert_eq!(
+            result_icm.best_cost(),
+            Some(Solution::from(result_icm.labeling().clone()).cost(&cfn))
+        );
+    }
+
+    #[test]
+    fn hard_constraint_value() {
+        // Both variables are forced to label 0 by unary constraints, but the pairwise constraint forbids equal labels
+        let construct_cfn = |hard_constraint_value: f64| {
+            let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2; 2], false, 1);
+            cfn.add_hard_constraint(vec![0], |labeling| labeling[0] == 0, hard_constraint_value);
+            cfn.add_hard_constraint(vec![1], |labeling| labeling[0] == 0, hard_constraint_value);
+            cfn.add_hard_constraint(
+                vec![0, 1],
+                |labeling| labeling[0] != labeling[1],
+                hard_constraint_value,
+            );
+            cfn
+        };
+        let has_nan = |srmp: &SRMP| {
+            srmp.lower_bound_history()
+                .iter()
+                .any(|value| value.is_nan())
+                || (0..srmp.relaxation.edge_count()).any(|index| {
+                    srmp.messages
+                        .message(index)
+                        .iter()
+                        .any(|value| value.is_nan())
+                })
+        };
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(10).set_eps(f64::NEG_INFINITY);
+
+        // With infinite costs, message passing computes inf - inf
+        let cfn = construct_cfn(f64::INFINITY);
+        let relaxation = Relaxation::new(&cfn);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+        assert!(has_nan(&srmp));
+
+        let hard_constraint_value = 1e6;
+        let cfn = construct_cfn(hard_constraint_value);
+        let relaxation = Relaxation::new(&cfn);
+        options.set_hard_constraint_value(hard_constraint_value);
+        let srmp = SRMP::init(&relaxation).unwrap().run(&options);
+        assert!(!has_nan(&srmp));
+        for index in 0..relaxation.edge_count() {
+            assert!(srmp
+                .messages
+                .message(index)
+                .iter()
+                .all(|value| value.is_finite() && *value <= hard_constraint_value));
+        }
+        // The lower bound reaches the hard constraint value, which proves that the instance is infeasible
+        assert_eq!(srmp.result().lower_bound(), hard_constraint_value);
+    }
+
+    #[test]
+    fn renormalize() {
+        let cfn = CostFunctionNetwork::random_grid(4, 4, 3);
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(10).set_eps(f64::NEG_INFINITY);
+        let mut srmp = SRMP::init(&relaxation).unwrap().run(&options);
+
+        let max_abs_message_value = |messages: &SRMPMessages| {
+            (0..relaxation.edge_count())
+                .flat_map(|index| messages.message(index).iter())
+                .fold(0., |max_abs: f64, value| max_abs.max(value.abs()))
+        };
+        // Nodes of unary factors come first in the relaxation graph, ordered by variable
+        let labeling = |messages: &SRMPMessages| {
+            (0..cfn.num_variables())
+                .map(|variable| {
+                    messages
+                        .compute_belief(NodeIndex::new(variable))
+                        .index_min()
+                })
+                .collect::<Vec<_>>()
+        };
+        let lower_bound = srmp.messages.compute_lower_bound();
+        let solution = labeling(&srmp.messages);
+        let max_abs = max_abs_message_value(&srmp.messages);
+
+        srmp.messages.renormalize();
+        assert!((srmp.messages.compute_lower_bound() - lower_bound).abs() < 1e-9);
+        assert_eq!(labeling(&srmp.messages), solution);
+        assert!(max_abs_message_value(&srmp.messages) < max_abs);
+
+        // Renormalizing during the run doesn't change the results
+        let result = SRMP::solve(&relaxation, &options).unwrap();
+        options.set_renormalize_period(1);
+        let result_renormalized = SRMP::solve(&relaxation, &options).unwrap();
+        assert!((result_renormalized.lower_bound() - result.lower_bound()).abs() < 1e-9);
+        assert_eq!(result_renormalized.labeling(), result.labeling());
+    }
+
+    #[test]
+    fn target_lower_bound() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options
+            .set_max_iterations(1000)
+            .set_compute_solution_period(0);
+        let converged_lower_bound = SRMP::solve(&relaxation, &options).unwrap().lower_bound();
+
+        // The target is checked before the lower bound improvement, so the target stops the solver
+        options.set_target_lower_bound(Some(converged_lower_bound));
+        let result = SRMP::solve(&relaxation, &options).unwrap();
+        assert_eq!(result.stop_reason(), Some(StopReason::TargetReached));
+        assert!(result.iterations() < 1000);
+        assert!(result.lower_bound() >= converged_lower_bound - 1e-9);
+
+        options.set_target_lower_bound(Some(converged_lower_bound + 1.));
+        let result = SRMP::solve(&relaxation, &options).unwrap();
+        assert_eq!(result.stop_reason(), Some(StopReason::Converged));
+    }
+
+    #[test]
+    fn variable_beliefs() {
+        let cfn = construct_cfn_chain();
+        let relaxation = Relaxation::new(&cfn);
+        let srmp = SRMP::init(&relaxation)
+            .unwrap()
+            .run(&SolverOptions::default());
+        let labeling = srmp.result().labeling().clone();
+
+        // SRMP splits costs between factors, so beliefs may have ties, but the extracted label must attain the minimum
+        let beliefs = srmp.variable_beliefs();
+        assert_eq!(beliefs.len(), cfn.num_variables());
+        for (variable, belief) in beliefs.iter().enumerate() {
+            assert_eq!(belief.len(), cfn.domain_size(variable));
+            let min_belief = belief.iter().copied().fold(f64::INFINITY, f64::min);
+            assert_eq!(belief[labeling[variable].unwrap()], min_belief);
+        }
+    }
+
+    #[test]
+    fn lower_bound_only() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options.set_max_iterations(5);
+        let result = SRMP::solve(&relaxation, &options).unwrap();
+
+        options.set_lower_bound_only(true);
+        let result_lower_bound_only = SRMP::solve(&relaxation, &options).unwrap();
+        assert_eq!(result_lower_bound_only.best_cost(), None);
+        assert!(result_lower_bound_only
+            .labeling()
+            .iter()
+            .all(|label| label.is_none()));
+        assert_eq!(result_lower_bound_only.lower_bound(), result.lower_bound());
+        assert_eq!(result_lower_bound_only.iterations(), result.iterations());
+    }
+
+    #[test]
+    fn solve_chain() {
+        let cfn = construct_cfn_chain();
+        let relaxation = Relaxation::new(&cfn);
+        let (optimum, _solution) = brute_force_min(&cfn);
+
+        let result = SRMP::solve(&relaxation, &SolverOptions::default()).unwrap();
+        assert!((result.best_cost().unwrap() - optimum).abs() < 1e-9);
+        assert!((result.lower_bound() - optimum).abs() < 1e-9);
+        assert_eq!(result.labeling().len(), cfn.num_variables());
+        assert!(result.labeling().iter().all(|label| label.is_some()));
+        assert_eq!(result.stop_reason(), Some(StopReason::Converged));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_matches_sequential() {
+        let cfn = CostFunctionNetwork::random_grid(4, 5, 3);
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options
+            .set_max_iterations(20)
+            .set_eps(f64::NEG_INFINITY)
+            .set_parallel(true);
+        let result_parallel = SRMP::solve(&relaxation, &options).unwrap();
+
+        // Processing color classes in parallel performs the same computation
+        // as the sequential passes over the factor sequence grouped by color classes
+        let mut srmp = SRMP::init(&relaxation).unwrap();
+        let color_classes = srmp.factor_sequence.color_classes(&relaxation);
+        assert!(color_classes.len() < srmp.factor_sequence.iter().len());
+        srmp.factor_sequence = FactorSequence::from_color_classes(&color_classes);
+        options.set_parallel(false);
+        let result_sequential = srmp.run(&options).result();
+        assert!((result_parallel.lower_bound() - result_sequential.lower_bound()).abs() < 1e-9);
+        assert_eq!(result_parallel.labeling(), result_sequential.labeling());
+
+        // The bound after convergence doesn't depend on the processing order much
+        options.set_max_iterations(1000).set_eps(1e-8);
+        let result_sequential = SRMP::solve(&relaxation, &options).unwrap();
+        options.set_parallel(true);
+        let result_parallel = SRMP::solve(&relaxation, &options).unwrap();
+        assert!((result_parallel.lower_bound() - result_sequential.lower_bound()).abs() < 1e-6);
+
+        // On a chain, the parallel mode is exact as well
+        let cfn = construct_cfn_chain();
+        let relaxation = Relaxation::new(&cfn);
+        let (optimum, _solution) = brute_force_min(&cfn);
+        let result = SRMP::solve(&relaxation, &options).unwrap();
+        assert!((result.lower_bound() - optimum).abs() < 1e-9);
+        assert!((result.best_cost().unwrap() - optimum).abs() < 1e-9);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_matches_sequential_damping_hard_constraints() {
+        let mut cfn = CostFunctionNetwork::random_grid(4, 5, 3);
+        for variable in [0, 6, 12] {
+            cfn.add_hard_constraint(
+                vec![variable, variable + 1],
+                |labeling| labeling[0] != labeling[1],
+                f64::INFINITY,
+            );
+        }
+        let relaxation = Relaxation::new(&cfn);
+        let mut options = SolverOptions::default();
+        options
+            .set_max_iterations(10)
+            .set_eps(f64::NEG_INFINITY)
+            .set_damping(0.5)
+            .set_hard_constraint_value(20.)
+            .set_parallel(true);
+        let srmp_parallel = SRMP::init(&relaxation).unwrap().run(&options);
+
+        let mut srmp_sequential = SRMP::init(&relaxation).unwrap();
+        let color_classes = srmp_sequential.factor_sequence.color_classes(&relaxation);
+        srmp_sequential.factor_sequence = FactorSequence::from_color_classes(&color_classes);
+        options.set_parallel(false);
+        let srmp_sequential = srmp_sequential.run(&options);
+
+        for index in 0..relaxation.edge_count() {
+            let message_parallel = srmp_parallel.messages.message(index);
+            let message_sequential = srmp_sequential.messages.message(index);
+            assert!(
+                message_parallel
+                    .iter()
+                    .zip(message_sequential)
+                    .all(|(lhs, rhs)| lhs == rhs || (lhs - rhs).abs() < 1e-9),
+                "Message {}: {:?} != {:?}",
+                index,
+                message_parallel,
+                message_sequential
+            );
+        }
+    }
+
+    #[test]
+    fn isolated_variable_labeled() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 2, 4], true, 1);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            vec![0., 2., 1., 0., 3., 1.],
+        )));
+        let relaxation = Relaxation::new(&cfn);
+
+        let result = SRMP::solve(&relaxation, &SolverOptions::default()).unwrap();
+        assert_eq!(result.labeling()[2], Some(0));
+        assert!(result.labeling().iter().all(|label| label.is_some()));
+        assert!(result.best_cost().unwrap().is_finite());
+    }
+
+    #[test]
+    fn maximize() {
+        let cfn_min = construct_cfn_chain();
+
+        // same problem phrased as maximizing scores, i.e., with all tables negated by hand
+        let mut cfn_max = CostFunctionNetwork::from_domain_sizes(&vec![3, 2, 3, 2], true, 3);
+        cfn_max.set_sense(Sense::Maximize);
+        for factor in cfn_min.factors_iter() {
+            let scores = factor.entries().map(|cost| -cost).collect();
+            cfn_max.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn_max,
+                factor.variables().clone(),
+                scores,
+            )));
+        }
+        cfn_max.add_constant(-5.);
+        let mut cfn_min_shifted = construct_cfn_chain();
+        cfn_min_shifted.add_constant(5.);
+
+        let options = SolverOptions::default();
+        let result_min = SRMP::solve(&Relaxation::new(&cfn_min_shifted), &options).unwrap();
+        let result_max = SRMP::solve(&Relaxation::new(&cfn_max), &options).unwrap();
+        assert_eq!(result_max.labeling(), result_min.labeling());
+        assert!((result_max.best_cost().unwrap() + result_min.best_cost().unwrap()).abs() < 1e-9);
+        assert!((result_max.lower_bound() + result_min.lower_bound()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn maximize_structured_factors() {
+        // A 2x3 grid with absolute difference and truncated linear factors, whose sense is flipped after construction
+        let (rows, columns) = (2, 3);
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![4; rows * columns], true, 7);
+        for variable in 0..rows * columns {
+            let table = (0..4)
+                .map(|label| ((variable * 5 + label * 3) % 7) as f64 * 0.3)
+                .collect();
+            cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn,
+                vec![variable],
+                table,
+            )));
+        }
+        for variable in 0..rows * columns {
+            if variable % columns + 1 < columns {
+                let factor = AbsoluteDifference::new(&cfn, vec![variable, variable + 1], 0.7);
+                cfn.add_factor(FactorType::AbsoluteDifference(factor));
+            }
+            if variable + columns < rows * columns {
+                let factor =
+                    TruncatedLinear::new(&cfn, vec![variable, variable + columns], 0.9, 1.5);
+                cfn.add_factor(FactorType::TruncatedLinear(factor));
+            }
+        }
+        cfn.set_sense(Sense::Maximize);
+
+        // The same network with all factors expanded into function tables
+        let mut cfn_dense =
+            CostFunctionNetwork::from_domain_sizes(&vec![4; rows * columns], true, 7);
+        cfn_dense.set_sense(Sense::Maximize);
+        for factor in cfn.factors_iter() {
+            let scores = factor.entries().map(|cost| -cost).collect();
+            cfn_dense.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn_dense,
+                factor.variables().clone(),
+                scores,
+            )));
+        }
+
+        let optimum = cfn.cost_to_objective(brute_force_min(&cfn).0);
+        let options = SolverOptions::default();
+        let result = SRMP::solve(&Relaxation::new(&cfn), &options).unwrap();
+        let result_dense = SRMP::solve(&Relaxation::new(&cfn_dense), &options).unwrap();
+        // For maximization, the reported bound is an upper bound on the best score
+        assert!(result.lower_bound() >= optimum - 1e-9);
+        assert!(result.best_cost().unwrap() <= optimum + 1e-9);
+        assert!((result.lower_bound() - result_dense.lower_bound()).abs() < 1e-6);
+    }
+
+    // todo: add tests for remaining functions, use the stub below
+
+    // #[test]
+    // fn sub_all_outgoing_messages() {
+    //     todo!();
+    // }
+
+    // #[test]
+    // fn sub_all_other_outgoing_messages() {
+    //     todo!();
+    // }
+
+    // #[test]
+    // fn subtract_all_other_outgoing_messages_alt() {
+    //     todo!();
+    // }
+
+    // #[test]
+    // fn update_and_normalize() {
+    //     todo!();
+    // }
+
+    // #[test]
+    // fn send() {
+    //     todo!();
+    // }
+
+    // #[test]
+    // fn compute_reparam() {
+    //     todo!();
+    // }
+
+    // #[test]
+    // fn sub_assign_reparam() {
+    //     todo!();
+    // }
+
+    // #[test]
+    // fn send_srmp_initial() {
+    //     todo!();
+    // }
+
+    // #[test]
+    // fn send_restricted() {
+    //     todo!();
+    // }
+
+    // #[test]
+    // fn compute_restricted_reparam() {
+    //     todo!();
+    // }
+}
+
+// Stores information for the SRMP algorithm
+pub struct SRMP<'a> {
+    cfn: &'a CostFunctionNetwork,    // the cost function network
+    relaxation: &'a Relaxation<'a>,  // the relaxation graph
+    node_edge_attrs: NodeEdgeAttrs, // the attributes used in the computations in the forward and backward passes
+    messages: SRMPMessages<'a>,     // the messages sent along the edges of the relaxation graph
+    factor_sequence: FactorSequence, // the sequence of factors considered in the forward and backward passes
+    initial_lower_bound: f64,        // the initial lower bound
+    lower_bound: f64,                // the lower bound after the last run
+    best_solution: Option<Solution>, // the best solution found in the last run
+    iterations: usize,               // the number of iterations performed in the last run
+    elapsed_time: Duration,          // the running time of the last run
+    is_tree: bool,                   // if the relaxation graph is a tree
+    is_exact: bool, // if the last run proved its lower bound optimal on a tree relaxation
+    stop_reason: Option<StopReason>, // the reason why the last run stopped (None if the solver hasn't run yet)
+    // the total absolute change of all messages over the last iteration (None if not tracked)
+    last_iteration_message_delta: Option<f64>,
+    lower_bound_history: Vec<f64>, // the lower bound after each iteration of the last run
+    // the best distinct solutions found in the last run with their costs (see SolverOptions::keep_top_k())
+    top_solutions: Vec<(f64, Solution)>,
+    // the indices of factors containing each variable, used to track the running costs of solutions
+    variable_factors: Vec<Vec<usize>>,
 }
 
 impl<'a> SRMP<'a> {
+    // Returns the difference between the cost of the best solution and the lower bound after the last run
+    // (None if no solution was computed)
+    pub fn dual_gap(&self) -> Option<f64> {
+        self.best_solution
+            .as_ref()
+            .map(|solution| solution.cost(self.cfn) - self.lower_bound)
+    }
+
+    // Checks if the best solution is provably optimal, i.e., if the dual gap is at most `eps`
+    pub fn is_optimal(&self, eps: f64) -> bool {
+        self.dual_gap().is_some_and(|gap| gap <= eps)
+    }
+
+    // Checks if the last run proved its lower bound optimal on a tree relaxation by a solution of the same cost
+    // (on trees the relaxation is tight, so the dual gap closes after one sweep)
+    pub fn is_exact(&self) -> bool {
+        self.is_exact
+    }
+
+    // Returns the number of iterations performed in the last run
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    // Returns the reason why the last run stopped (None if the solver hasn't run yet)
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+
+    // Returns the total absolute change of all messages over the last iteration of the last run
+    // (None if tracking was disabled in the solver options)
+    pub fn last_iteration_message_delta(&self) -> Option<f64> {
+        self.last_iteration_message_delta
+    }
+
+    // Returns the lower bound after each iteration of the last run (empty if the solver hasn't run yet)
+    pub fn lower_bound_history(&self) -> &[f64] {
+        &self.lower_bound_history
+    }
+
+    // Returns the best distinct solutions found in the last run (at most SolverOptions::keep_top_k() of them),
+    // paired with their costs (as costs to be minimized) and sorted by cost in increasing order
+    pub fn top_solutions(&self) -> &[(f64, Solution)] {
+        &self.top_solutions
+    }
+
+    // Records a given solution with a given cost among the best distinct solutions of the current run,
+    // keeping at most top_k of them
+    fn record_top_solution(&mut self, top_k: usize, cost: f64, solution: &Solution) {
+        if top_k == 0
+            || self
+                .top_solutions
+                .iter()
+                .any(|(_cost, top_solution)| top_solution == solution)
+        {
+            return;
+        }
+        let position = self
+            .top_solutions
+            .partition_point(|(top_cost, _top_solution)| *top_cost <= cost);
+        if position < top_k {
+            self.top_solutions
+                .insert(position, (cost, solution.clone()));
+            self.top_solutions.truncate(top_k);
+        }
+    }
+
+    // Returns the messages after the last run
+    pub fn messages(&self) -> &SRMPMessages<'a> {
+        &self.messages
+    }
+
+    // Returns the reparametrized unary costs (beliefs) of every variable after the last run,
+    // i.e., the unary factor with up-to-date messages from all factors containing the variable added to it
+    pub fn variable_beliefs(&self) -> Vec<Vec<f64>> {
+        // Nodes of unary factors come first in the relaxation graph, ordered by variable
+        self.relaxation
+            .node_indices()
+            .filter(|node| self.relaxation.is_unary_factor(*node))
+            .map(|node| self.messages.compute_belief(node).iter().copied().collect())
+            .collect()
+    }
+
+    // Initializes the solver as in init(), taking the message buffers of given scratch
+    // (e.g., left over from a solver for another relaxation, see into_scratch())
+    // Note: the scratch is left untouched if the relaxation is rejected
+    pub fn init_with_scratch(
+        relaxation: &'a Relaxation<'a>,
+        scratch: &mut MessageScratch,
+    ) -> Result<Self, SolverError> {
+        validate(relaxation)?;
+        let cfn = relaxation.cfn();
+        // Find and sort all factors with at least one incoming edge
+        // todo: different ordering procedures
+        let factor_sequence = FactorSequence::new(relaxation).sort();
+
+        // Count edges of each category and compute SRMP weights
+        let node_edge_attrs = NodeEdgeAttrs::new(relaxation, &factor_sequence, &WeightScheme::Srmp);
+
+        // Initialize messages
+        let messages = SRMPMessages::with_scratch(relaxation, mem::take(scratch));
+
+        // Compute initial lower bound (all messages are zero at this point)
+        let initial_lower_bound = messages.get_initial_lower_bound();
+
+        // Form and return SRMP struct
+        Ok(SRMP {
+            cfn,
+            relaxation,
+            node_edge_attrs,
+            messages,
+            factor_sequence,
+            initial_lower_bound,
+            lower_bound: initial_lower_bound,
+            best_solution: None,
+            iterations: 0,
+            elapsed_time: Duration::ZERO,
+            is_tree: relaxation.is_tree(),
+            is_exact: false,
+            stop_reason: None,
+            last_iteration_message_delta: None,
+            lower_bound_history: Vec::new(),
+            top_solutions: Vec::new(),
+            variable_factors: cfn.variable_factors(),
+        })
+    }
+
+    // Returns the message buffers of this solver for reuse (see init_with_scratch())
+    pub fn into_scratch(self) -> MessageScratch {
+        self.messages.into_scratch()
+    }
+
+    // Reinitializes the solver for a given relaxation of a cost function network with the same factor scopes
+    // (e.g., after updating unary factors), reusing the factor sequence, the SRMP weights, and the message storage,
+    // and resetting all messages to zero
+    // Note: the relaxation is passed along with the network, so that relaxation.cfn() is the network being solved
+    pub fn reinit(mut self, relaxation: &'a Relaxation<'a>) -> Self {
+        let cfn = relaxation.cfn();
+        assert!(
+            self.cfn.has_same_scopes(cfn)
+                && self.relaxation.node_count() == relaxation.node_count()
+                && self.relaxation.edge_count() == relaxation.edge_count(),
+            "Cost function network's factor scopes don't match those used to construct the relaxation."
+        );
+
+        self.cfn = cfn;
+        self.relaxation = relaxation;
+        self.messages.rebind(relaxation);
+        // Unary factors may have been added, so the factors containing each variable are recomputed
+        self.variable_factors = cfn.variable_factors();
+        self.initial_lower_bound = self.messages.get_initial_lower_bound();
+        self.lower_bound = self.initial_lower_bound;
+        self.best_solution = None;
+        self.top_solutions.clear();
+        self.iterations = 0;
+        self.elapsed_time = Duration::ZERO;
+        self.is_exact = false;
+        self.stop_reason = None;
+        self.last_iteration_message_delta = None;
+        self.lower_bound_history.clear();
+        self
+    }
+
     // If compute_solution == true, initializes an empty solution
     // If compute_solution == false, returns None
     fn init_solution(&mut self, compute_solution: bool) -> Option<Solution> {
         match compute_solution {
-            true => Some(Solution::new(self.cfn)),
+            true => Some(Solution::with_running_cost(self.cfn)),
             false => None,
         }
     }
 
     // Extends a partial solution using the given factor
     fn compute_solution(&self, solution: &mut Solution, beta: NodeIndex<usize>) {
-        let beta_origin = self.relaxation.factor_origin(beta);
-
-        if solution.is_fully_labeled(&self.cfn.factor_variables(beta_origin)) {
+        let beta_variables = self.relaxation.node_variables(beta);
+        if solution.is_fully_labeled(&beta_variables) {
             return;
         }
 
+        let newly_labeled = beta_variables
+            .iter()
+            .filter(|variable| solution[**variable].is_none())
+            .copied()
+            .collect::<Vec<_>>();
+
+        let beta_origin = self.relaxation.factor_origin(beta);
         let restricted_reparam = self.messages.compute_restricted_reparam(beta, solution);
 
         restricted_reparam.update_solution_restricted_min(self.cfn, beta_origin, solution);
+        solution.update_running_cost(self.cfn, &self.variable_factors, &newly_labeled);
+    }
+
+    // Computes a solution labeling each variable independently by the minimum of its belief
+    // (as opposed to compute_solution(), which takes already labeled variables into account)
+    // Note: the reparametrizations of unary factors are often all zeros after a pass, since SRMP redistributes them
+    // among the factors containing the variable, so the beliefs are used instead
+    fn compute_solution_belief_argmin(&self) -> Solution {
+        let mut solution = Solution::with_running_cost(self.cfn);
+        let mut newly_labeled = Vec::with_capacity(self.cfn.num_variables());
+        for node in self
+            .relaxation
+            .node_indices()
+            .filter(|node| self.relaxation.is_unary_factor(*node))
+        {
+            let variable = self.relaxation.node_variables(node)[0];
+            solution[variable] = Some(self.messages.compute_belief(node).index_min());
+            newly_labeled.push(variable);
+        }
+        solution.update_running_cost(self.cfn, &self.variable_factors, &newly_labeled);
+        solution
+    }
+
+    // Computes a solution labeling the variables one by one (in the order of their unary nodes) by the minimum
+    // of their beliefs restricted to the labels of the already labeled variables
+    fn compute_solution_greedy_sweep(&self) -> Solution {
+        let mut solution = Solution::with_running_cost(self.cfn);
+        for node in self
+            .relaxation
+            .node_indices()
+            .filter(|node| self.relaxation.is_unary_factor(*node))
+        {
+            let variable = self.relaxation.node_variables(node)[0];
+            let belief = self.messages.compute_restricted_belief(node, &solution);
+            solution[variable] = Some(belief.index_min());
+            solution.update_running_cost(self.cfn, &self.variable_factors, &[variable]);
+        }
+        solution
+    }
+
+    // Computes a solution after a pass by a given heuristic other than PrimalHeuristic::RestrictedMin,
+    // which extracts solutions during the passes
+    fn compute_solution_after_pass(&self, primal_heuristic: PrimalHeuristic) -> Solution {
+        match primal_heuristic {
+            PrimalHeuristic::RestrictedMin => {
+                unreachable!("Restricted minimum solutions are extracted during the passes.")
+            }
+            PrimalHeuristic::BeliefArgmin => self.compute_solution_belief_argmin(),
+            PrimalHeuristic::GreedySweep => self.compute_solution_greedy_sweep(),
+        }
     }
 
     // Performs the forward pass
@@ -579,7 +2309,8 @@ impl<'a> SRMP<'a> {
             }
 
             // Line 5 of SRMP pseudocode: compute reparametrization
-            let mut reparam = self.messages.compute_reparam(*factor);
+            let mut reparam = self.messages.take_reparam_scratch();
+            self.messages.compute_reparam_into(*factor, &mut reparam);
 
             // Line 6 of SRMP pseudocode: update messages along incoming "forward" edges
             reparam.mul_assign_scalar(self.node_edge_attrs.node_omega_forward[factor.index()]);
@@ -590,9 +2321,131 @@ impl<'a> SRMP<'a> {
             {
                 self.messages.sub_assign_reparam(&reparam, in_edge);
             }
+            self.messages.return_reparam_scratch(reparam);
+        }
+    }
+
+    // Performs the forward pass, processing the given color classes of non-conflicting factors one after another
+    // and the factors of each class in parallel
+    // (equivalent to forward_pass() with the factor sequence from FactorSequence::from_color_classes())
+    #[cfg(feature = "parallel")]
+    fn forward_pass_parallel(
+        &mut self,
+        solution: &mut Option<Solution>,
+        color_classes: &[Vec<NodeIndex<usize>>],
+    ) {
+        let relaxation = self.relaxation;
+        for color_class in color_classes.iter() {
+            // Line 4 of SRMP pseudocode: send messages along incoming "backward" edges
+            let in_edges = color_class
+                .iter()
+                .flat_map(|factor| relaxation.edges_directed(*factor, Incoming))
+                .filter(|in_edge| self.node_edge_attrs.edge_is_backward[in_edge.id().index()])
+                .collect::<Vec<_>>();
+            self.messages.send_parallel(&in_edges);
+
+            // Compute solution if necessary (sequentially, as factors may label the same variables)
+            if let Some(labeling) = solution {
+                for factor in color_class.iter() {
+                    self.compute_solution(labeling, *factor);
+                }
+            }
+
+            // Line 5 of SRMP pseudocode: compute reparametrizations
+            let reparams = color_class
+                .par_iter()
+                .map(|factor| {
+                    let mut reparam = self.messages.compute_reparam(*factor);
+                    reparam
+                        .mul_assign_scalar(self.node_edge_attrs.node_omega_forward[factor.index()]);
+                    reparam
+                })
+                .collect::<Vec<_>>();
+
+            // Line 6 of SRMP pseudocode: update messages along incoming "forward" edges
+            for (factor, reparam) in color_class.iter().zip(reparams) {
+                for in_edge in relaxation
+                    .edges_directed(*factor, Incoming)
+                    .filter(|in_edge| self.node_edge_attrs.edge_is_forward[in_edge.id().index()])
+                {
+                    self.messages.sub_assign_reparam(&reparam, in_edge);
+                }
+            }
         }
     }
 
+    // Performs the backward pass, processing the given color classes of non-conflicting factors in reverse order
+    // and the factors of each class in parallel
+    // (equivalent to backward_pass() with the factor sequence from FactorSequence::from_color_classes())
+    #[cfg(feature = "parallel")]
+    fn backward_pass_parallel(
+        &mut self,
+        solution: &mut Option<Solution>,
+        color_classes: &[Vec<NodeIndex<usize>>],
+    ) -> f64 {
+        let relaxation = self.relaxation;
+        let mut lower_bound = self.initial_lower_bound;
+
+        for color_class in color_classes.iter().rev() {
+            // Line 4 of SRMP pseudocode: send messages along incoming "forward" edges
+            // (as well as edges that update the lower bound)
+            let in_edges = color_class
+                .iter()
+                .flat_map(|factor| relaxation.edges_directed(*factor, Incoming))
+                .filter(|in_edge| {
+                    self.node_edge_attrs.edge_is_forward[in_edge.id().index()]
+                        || self.node_edge_attrs.edge_is_update_lb[in_edge.id().index()]
+                })
+                .collect::<Vec<_>>();
+            let deltas = self.messages.send_parallel(&in_edges);
+            lower_bound += in_edges
+                .iter()
+                .zip(deltas)
+                .filter(|(in_edge, _delta)| {
+                    self.node_edge_attrs.edge_is_update_lb[in_edge.id().index()]
+                })
+                .map(|(_in_edge, delta)| delta)
+                .sum::<f64>();
+
+            // Compute solution if necessary (sequentially, as factors may label the same variables)
+            if let Some(labeling) = solution {
+                for factor in color_class.iter().rev() {
+                    self.compute_solution(labeling, *factor);
+                }
+            }
+
+            // Line 5 of SRMP pseudocode: compute reparametrizations
+            let reparams = color_class
+                .par_iter()
+                .map(|factor| {
+                    let mut reparam = self.messages.compute_reparam(*factor);
+                    reparam.mul_assign_scalar(
+                        self.node_edge_attrs.node_omega_backward[factor.index()],
+                    );
+                    reparam
+                })
+                .collect::<Vec<_>>();
+
+            for (factor, reparam) in color_class.iter().zip(reparams) {
+                // Line 6 of SRMP pseudocode: update messages along incoming "backward" edges
+                for in_edge in relaxation
+                    .edges_directed(*factor, Incoming)
+                    .filter(|in_edge| self.node_edge_attrs.edge_is_backward[in_edge.id().index()])
+                {
+                    self.messages.sub_assign_reparam(&reparam, in_edge);
+                }
+
+                // Update lower bound if necessary
+                if self.node_edge_attrs.node_is_update_lb[factor.index()] {
+                    lower_bound += reparam.min()
+                        * self.node_edge_attrs.node_weight_update_lb[factor.index()] as f64;
+                }
+            }
+        }
+
+        lower_bound
+    }
+
     // Performs the backward pass
     fn backward_pass(&mut self, solution: &mut Option<Solution>) -> f64 {
         let mut lower_bound = self.initial_lower_bound;
@@ -620,7 +2473,8 @@ impl<'a> SRMP<'a> {
             }
 
             // Line 5 of SRMP pseudocode: compute reparametrization
-            let mut reparam = self.messages.compute_reparam(*factor);
+            let mut reparam = self.messages.take_reparam_scratch();
+            self.messages.compute_reparam_into(*factor, &mut reparam);
 
             // Line 6 of SRMP pseudocode: update messages along incoming "backward" edges
             reparam.mul_assign_scalar(self.node_edge_attrs.node_omega_backward[factor.index()]);
@@ -637,6 +2491,7 @@ impl<'a> SRMP<'a> {
                 lower_bound += reparam.min()
                     * self.node_edge_attrs.node_weight_update_lb[factor.index()] as f64;
             }
+            self.messages.return_reparam_scratch(reparam);
         }
 
         lower_bound
@@ -644,53 +2499,99 @@ impl<'a> SRMP<'a> {
 }
 
 impl<'a> Solver<'a> for SRMP<'a> {
-    fn init(cfn: &'a CostFunctionNetwork, relaxation: &'a Relaxation) -> Self {
-        // Find and sort all factors with at least one incoming edge
-        // todo: different ordering procedures
-        let factor_sequence = FactorSequence::new(&relaxation).sort();
-
-        // Count edges of each category and compute SRMP weights
-        let node_edge_attrs = NodeEdgeAttrs::new(&relaxation, &factor_sequence);
-
-        // Initialize messages
-        let mut messages = SRMPMessages::new(cfn, &relaxation);
-
-        // Compute initial lower bound
-        let initial_lower_bound = messages.get_initial_lower_bound();
-
-        // Form and return SRMP struct
-        SRMP {
-            cfn,
-            relaxation,
-            node_edge_attrs,
-            messages,
-            factor_sequence,
-            initial_lower_bound,
-        }
+    fn init(relaxation: &'a Relaxation<'a>) -> Result<Self, SolverError> {
+        Self::init_with_scratch(relaxation, &mut MessageScratch::new())
     }
 
     fn run(mut self, options: &SolverOptions) -> Self {
         let time_start = Instant::now();
         let mut iteration = 0;
         let mut iter_solution = options.compute_solution_period();
-        let mut compute_solution = options.compute_solution_period() > 0;
+        let mut compute_solution =
+            !options.lower_bound_only() && options.compute_solution_period() > 0;
         let mut current_lower_bound = 0.;
 
         let mut best_solution = None;
         let mut best_cost = 0.;
+        self.is_exact = false;
         let mut forward_cost;
         let mut backward_cost;
+        let stop_reason;
+
+        // Recompute scaling factors according to the requested weight scheme.
+        // On a tree relaxation, one sequential sweep with unit weights and without damping is exact,
+        // so the requested weight scheme, damping, and parallel processing are ignored there.
+        // The lower bound accumulated in the backward pass relies on the SRMP weights,
+        // so for other schemes (and with damping) it is computed directly from the reparametrization of every factor.
+        let weight_scheme = match self.is_tree {
+            true => &WeightScheme::Uniform(1.),
+            false => options.weight_scheme(),
+        };
+        let damping = match self.is_tree {
+            true => 0.,
+            false => options.damping(),
+        };
+        let is_srmp_weights = *weight_scheme == WeightScheme::Srmp;
+
+        // If messages are sent in parallel, factors are processed by color classes of non-conflicting factors,
+        // and the weights are computed for the corresponding factor sequence
+        #[cfg(feature = "parallel")]
+        let color_classes = (options.parallel() && !self.is_tree)
+            .then(|| self.factor_sequence.color_classes(self.relaxation));
+        #[cfg(feature = "parallel")]
+        let colored_factor_sequence = color_classes
+            .as_deref()
+            .map(FactorSequence::from_color_classes);
+        #[cfg(feature = "parallel")]
+        let factor_sequence = colored_factor_sequence
+            .as_ref()
+            .unwrap_or(&self.factor_sequence);
+        #[cfg(not(feature = "parallel"))]
+        let factor_sequence = &self.factor_sequence;
+        self.node_edge_attrs = NodeEdgeAttrs::new(self.relaxation, factor_sequence, weight_scheme);
+        self.last_iteration_message_delta = None;
+        self.lower_bound_history.clear();
+        self.top_solutions.clear();
+        self.messages
+            .set_hard_constraint_value(options.hard_constraint_value())
+            .set_damping(damping);
+
+        // The target is given in the sense of the network (negating is its own inverse for maximization)
+        let target_lower_bound = options
+            .target_lower_bound()
+            .map(|target| self.cfn.cost_to_objective(target));
+
+        // Solutions are extracted during the passes only by the restricted minimum heuristic,
+        // other heuristics are applied after each pass
+        let is_restricted_min = options.primal_heuristic() == PrimalHeuristic::RestrictedMin;
 
         loop {
             let previous_lower_bound = current_lower_bound;
+            if options.track_message_delta() {
+                self.messages.snapshot_messages();
+            }
 
             // Perform the forward pass
-            let mut forward_solution = self.init_solution(compute_solution);
+            let mut forward_solution = self.init_solution(compute_solution && is_restricted_min);
+            #[cfg(feature = "parallel")]
+            match &color_classes {
+                Some(color_classes) => {
+                    self.forward_pass_parallel(&mut forward_solution, color_classes)
+                }
+                None => self.forward_pass(&mut forward_solution),
+            }
+            #[cfg(not(feature = "parallel"))]
             self.forward_pass(&mut forward_solution);
+            if compute_solution && !is_restricted_min {
+                forward_solution =
+                    Some(self.compute_solution_after_pass(options.primal_heuristic()));
+            }
 
             if let Some(solution) = forward_solution {
                 // Log the forward solution
-                forward_cost = solution.cost(self.cfn);
+                forward_cost = solution
+                    .running_cost()
+                    .unwrap_or_else(|| solution.cost(self.cfn));
                 info!(
                     "Iteration {}. Elapsed time {:?}. Forward cost: {}. Forward solution {:#?}.",
                     iteration,
@@ -699,7 +2600,8 @@ impl<'a> Solver<'a> for SRMP<'a> {
                     solution
                 );
 
-                // Update the best solution
+                // Update the best solutions
+                self.record_top_solution(options.keep_top_k(), forward_cost, &solution);
                 if best_solution.is_none() || best_cost > forward_cost {
                     best_cost = forward_cost;
                     best_solution = Some(solution);
@@ -707,12 +2609,37 @@ impl<'a> Solver<'a> for SRMP<'a> {
             }
 
             // Perform the backward pass
-            let mut backward_solution = self.init_solution(compute_solution);
-            current_lower_bound = self.backward_pass(&mut backward_solution);
+            let mut backward_solution = self.init_solution(compute_solution && is_restricted_min);
+            #[cfg(feature = "parallel")]
+            {
+                current_lower_bound = match &color_classes {
+                    Some(color_classes) => {
+                        self.backward_pass_parallel(&mut backward_solution, color_classes)
+                    }
+                    None => self.backward_pass(&mut backward_solution),
+                };
+            }
+            #[cfg(not(feature = "parallel"))]
+            {
+                current_lower_bound = self.backward_pass(&mut backward_solution);
+            }
+            if options.track_message_delta() {
+                self.last_iteration_message_delta = self.messages.message_delta();
+            }
+            if !is_srmp_weights || damping > 0. {
+                current_lower_bound = self.messages.compute_lower_bound();
+            }
+            self.lower_bound_history.push(current_lower_bound);
+            if compute_solution && !is_restricted_min {
+                backward_solution =
+                    Some(self.compute_solution_after_pass(options.primal_heuristic()));
+            }
 
             if let Some(solution) = backward_solution {
                 // Log the backward solution
-                backward_cost = solution.cost(self.cfn);
+                backward_cost = solution
+                    .running_cost()
+                    .unwrap_or_else(|| solution.cost(self.cfn));
                 info!(
                     "Iteration {}. Elapsed time {:?}. Backward cost: {}. Backward solution {:#?}.",
                     iteration,
@@ -721,7 +2648,8 @@ impl<'a> Solver<'a> for SRMP<'a> {
                     solution
                 );
 
-                // Update the best solution
+                // Update the best solutions
+                self.record_top_solution(options.keep_top_k(), backward_cost, &solution);
                 if best_solution.is_none() || best_cost > backward_cost {
                     best_cost = backward_cost;
                     best_solution = Some(solution);
@@ -737,24 +2665,88 @@ impl<'a> Solver<'a> for SRMP<'a> {
 
             // Advance to next iteration
             iteration += 1;
+            if options.renormalize_period() > 0 && iteration % options.renormalize_period() == 0 {
+                self.messages.renormalize();
+            }
             iter_solution -= compute_solution as usize * options.compute_solution_period();
             iter_solution += 1;
-            compute_solution = (iter_solution == options.compute_solution_period())
-                || (iteration + 1 == options.max_iterations());
+            compute_solution = !options.lower_bound_only()
+                && ((iter_solution == options.compute_solution_period())
+                    || (iteration + 1 == options.max_iterations()));
 
             // Break if a stopping condition is satisfied
-            if iteration >= options.max_iterations() {
+            if self.is_tree {
+                info!("Relaxation is a tree, so the lower bound is optimal after one sweep. Interrupting.");
+                stop_reason = StopReason::Converged;
+                break;
+            } else if target_lower_bound
+                .is_some_and(|target| options.eps().is_reached(current_lower_bound, target))
+            {
+                info!("Lower bound reached the target value. Interrupting.");
+                stop_reason = StopReason::TargetReached;
+                break;
+            } else if iteration >= options.max_iterations() {
                 info!("Maximum number of iterations reached. Interrupting.");
+                stop_reason = StopReason::MaxIterations;
                 break;
             } else if elapsed_time >= options.time_max() {
                 info!("Time limit reached. Interrupting.");
+                stop_reason = StopReason::TimeLimit;
                 break;
-            } else if iteration > 1 && current_lower_bound < previous_lower_bound + options.eps() {
+            } else if iteration > 1
+                && options
+                    .eps()
+                    .is_converged(previous_lower_bound, current_lower_bound)
+            {
                 info!("Lower bound increased less than by epsilon. Interrupting.");
+                stop_reason = StopReason::Converged;
                 break;
             }
         }
 
+        // The lower bound may decrease between iterations (e.g., with damping or non-SRMP weights),
+        // so the best one over all completed iterations is reported
+        current_lower_bound = self
+            .lower_bound_history
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        // Label variables that were not reached by solution extraction, so the best solution is fully labeled
+        if let Some(solution) = best_solution.as_mut() {
+            if solution.num_labeled(0..self.cfn.num_variables()) < self.cfn.num_variables() {
+                solution.complete(self.cfn);
+                best_cost = solution.cost(self.cfn);
+            }
+        }
+
+        // On a tree relaxation, the solution extracted from the exact sweep certifies the lower bound
+        if self.is_tree {
+            let mut solution = self.compute_solution_greedy_sweep();
+            solution.complete(self.cfn);
+            let cost = solution.cost(self.cfn);
+            self.is_exact = options.eps().is_converged(current_lower_bound, cost);
+            if !options.lower_bound_only() && (best_solution.is_none() || best_cost > cost) {
+                best_cost = cost;
+                best_solution = Some(solution);
+            }
+        }
+
+        // Improve the best solution by local search
+        if let Some(solution) = best_solution.as_mut() {
+            if options.icm_sweeps() > 0 {
+                let sweeps = self.cfn.icm(solution, options.icm_sweeps());
+                best_cost = solution.cost(self.cfn);
+                info!(
+                    "ICM finished after {} sweeps. Best cost {}.",
+                    sweeps, best_cost
+                );
+            }
+        }
+        if let Some(solution) = best_solution.as_ref() {
+            self.record_top_solution(options.keep_top_k(), best_cost, solution);
+        }
+
         info!(
             "SRMP finished. Elapsed time {:?}. Best cost {}. Best solution {:?}.",
             time_start.elapsed(),
@@ -762,6 +2754,22 @@ impl<'a> Solver<'a> for SRMP<'a> {
             best_solution
         );
 
+        self.lower_bound = current_lower_bound;
+        self.best_solution = best_solution;
+        self.iterations = iteration;
+        self.elapsed_time = time_start.elapsed();
+        self.stop_reason = Some(stop_reason);
         self
     }
+
+    fn result(&self) -> SolverResult {
+        SolverResult::new(
+            self.cfn,
+            self.lower_bound,
+            self.best_solution.as_ref(),
+            self.elapsed_time,
+            self.iterations,
+            self.stop_reason,
+        )
+    }
 }
@@ -1,11 +1,14 @@
 #![allow(dead_code)]
 
-use std::{cmp::max, time::Instant};
+use std::{
+    cmp::max,
+    time::{Duration, Instant},
+};
 
 use bitvec::{order::LocalBits, vec::BitVec};
 use log::{debug, info};
 use petgraph::{
-    graph::{DiGraph, EdgeReference, NodeIndex},
+    graph::{EdgeReference, NodeIndex},
     visit::EdgeRef,
     Direction::{Incoming, Outgoing},
 };
@@ -19,7 +22,58 @@ use crate::{
     CostFunctionNetwork, FactorOrigin,
 };
 
-use super::solver::{Solver, SolverOptions};
+use super::solver::{validate, Solver, SolverError, SolverOptions, SolverResult, StopReason};
+
+// Stores the categories of an edge of the relaxation graph in the forward and backward passes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct EdgeCategory {
+    is_forward: bool, // if the source is processed after the target in the backward pass
+    is_backward: bool, // if the source is processed before the target in the forward pass
+    is_update_lb: bool, // if the lower bound is updated via the edge in the backward pass
+}
+
+// Labels every edge of a given relaxation (indexed by edge indices) with its categories
+// for a given factor sequence
+fn categorize_edges(
+    relaxation: &Relaxation,
+    factor_sequence: &FactorSequence,
+) -> Vec<EdgeCategory> {
+    let num_nodes = relaxation.node_count();
+    let mut edge_category = vec![EdgeCategory::default(); relaxation.edge_count()];
+
+    // Label backward edges
+    let mut is_touched = BitVec::<usize, LocalBits>::repeat(false, num_nodes);
+    for factor in factor_sequence.iter() {
+        is_touched.set(factor.index(), true);
+
+        for in_edge in relaxation.edges_directed(*factor, Incoming) {
+            let beta = in_edge.source().index();
+            let category = &mut edge_category[in_edge.id().index()];
+            if is_touched[beta] {
+                category.is_backward = true;
+            } else {
+                category.is_update_lb = true;
+            }
+            is_touched.set(beta, true);
+        }
+    }
+
+    // Label forward edges
+    let mut is_touched = BitVec::<usize, LocalBits>::repeat(false, num_nodes);
+    for factor in factor_sequence.iter().rev() {
+        is_touched.set(factor.index(), true);
+
+        for in_edge in relaxation.edges_directed(*factor, Incoming) {
+            let beta = in_edge.source().index();
+            if is_touched[beta] {
+                edge_category[in_edge.id().index()].is_forward = true;
+            }
+            is_touched.set(beta, true);
+        }
+    }
+
+    edge_category
+}
 
 struct SRMP2Messages<'a> {
     cfn: &'a CostFunctionNetwork,
@@ -30,10 +84,7 @@ struct SRMP2Messages<'a> {
     node_omega_backward: Vec<f64>, // the scaling factor for the reparametrization update in the backward pass
     node_weight_update_lb: Vec<usize>, // weight for updating the lower bound in the backward pass
 
-    graph_forward: DiGraph<(), (), usize>, // todo: additionally store edge data = edge index in relaxation graph?
-    graph_backward: DiGraph<(), (), usize>, // todo: additionally store edge data = edge index in relaxation graph?
-    graph_update: DiGraph<(), (), usize>,  // todo: additionally store edge data = edge index in relaxation graph?
-    // todo: share node set between these graphs
+    edge_category: Vec<EdgeCategory>, // the categories of every edge of the relaxation graph
     alignment_indexing: Vec<AlignmentIndexing>, // todo: make generic
     messages: Vec<MessageND>,                   // todo: make generic
 }
@@ -50,16 +101,9 @@ impl<'a> SRMP2Messages<'a> {
         let mut node_omega_backward = vec![0.; num_nodes];
         let mut node_weight_update_lb = vec![0; num_nodes];
 
-        let mut graph_forward = DiGraph::with_capacity(num_nodes, 0);
-        let mut graph_backward = DiGraph::with_capacity(num_nodes, 0);
-        let mut graph_update = DiGraph::with_capacity(num_nodes, 0);
-        for _i in 0..num_nodes {
-            graph_forward.add_node(());
-            graph_backward.add_node(());
-            graph_update.add_node(());
-        }
+        let edge_category = categorize_edges(relaxation, factor_sequence);
 
-        // Label backward edges
+        // Label nodes that update the lower bound
         let mut is_touched = BitVec::<usize, LocalBits>::repeat(false, num_nodes);
         for factor in factor_sequence.iter() {
             let alpha = factor.index();
@@ -70,28 +114,7 @@ impl<'a> SRMP2Messages<'a> {
             is_touched.set(alpha, true);
 
             for in_edge in relaxation.edges_directed(*factor, Incoming) {
-                let beta = in_edge.source().index();
-                if is_touched[beta] {
-                    graph_backward.add_edge(in_edge.source(), in_edge.target(), ());
-                } else {
-                    graph_update.add_edge(in_edge.source(), in_edge.target(), ());
-                }
-                is_touched.set(beta, true);
-            }
-        }
-
-        // Label forward edges
-        let mut is_touched = BitVec::<usize, LocalBits>::repeat(false, num_nodes);
-        for factor in factor_sequence.iter().rev() {
-            let alpha = factor.index();
-            is_touched.set(alpha, true);
-
-            for in_edge in relaxation.edges_directed(*factor, Incoming) {
-                let beta = in_edge.source().index();
-                if is_touched[beta] {
-                    graph_forward.add_edge(in_edge.source(), in_edge.target(), ());
-                }
-                is_touched.set(beta, true);
+                is_touched.set(in_edge.source().index(), true);
             }
         }
 
@@ -102,7 +125,7 @@ impl<'a> SRMP2Messages<'a> {
             is_touched.set(alpha, true);
 
             // Compute number of outgoing edges in forward and backward direction
-            let mut weight_out_dir = vec![0, 0]; // forward, backward
+            let mut weight_out_dir = [0, 0]; // forward, backward
             for out_edge in relaxation.edges_directed(*factor, Outgoing) {
                 let beta = out_edge.target().index();
                 weight_out_dir[is_touched[beta] as usize] += 1;
@@ -110,8 +133,14 @@ impl<'a> SRMP2Messages<'a> {
 
             // Compute number of incoming edges in forward and backward direction and total number of incoming edges
             let weight_in_total = relaxation.neighbors(*factor, Incoming).count();
-            let weight_in_forward = graph_forward.neighbors_directed(*factor, Incoming).count();
-            let weight_in_backward = graph_backward.neighbors_directed(*factor, Incoming).count();
+            let count_in = |is_category: fn(&EdgeCategory) -> bool| {
+                relaxation
+                    .edges_directed(*factor, Incoming)
+                    .filter(|in_edge| is_category(&edge_category[in_edge.id().index()]))
+                    .count()
+            };
+            let weight_in_forward = count_in(|category| category.is_forward);
+            let weight_in_backward = count_in(|category| category.is_backward);
 
             // Compute node weight in forward direction
             let mut alpha_weight_forward =
@@ -143,8 +172,8 @@ impl<'a> SRMP2Messages<'a> {
         for edge in relaxation.edge_references() {
             let alpha = relaxation.factor_origin(edge.source());
             let beta = relaxation.factor_origin(edge.target());
-            messages.push(MessageND::zero(&cfn, beta));
-            alignment_indexing.push(AlignmentIndexing::new(&cfn, &alpha, &beta));
+            messages.push(MessageND::zero(cfn, beta));
+            alignment_indexing.push(AlignmentIndexing::new(cfn, alpha, beta));
         }
 
         SRMP2Messages {
@@ -154,9 +183,7 @@ impl<'a> SRMP2Messages<'a> {
             node_omega_backward,
             node_is_update_lb,
             node_weight_update_lb,
-            graph_forward,
-            graph_backward,
-            graph_update,
+            edge_category,
             alignment_indexing,
             messages,
         }
@@ -170,7 +197,7 @@ impl<'a> SRMP2Messages<'a> {
     // Adds messages along all incoming edges to a given reparametrization
     fn add_all_incoming_messages(&self, reparam: &mut MessageND, factor: NodeIndex<usize>) {
         for in_edge in self.relaxation.edges_directed(factor, Incoming) {
-            reparam.add_assign_incoming(&self.messages[in_edge.id().index()]);
+            reparam.add_assign_incoming(self.messages[in_edge.id().index()].as_slice());
         }
     }
 
@@ -178,7 +205,7 @@ impl<'a> SRMP2Messages<'a> {
     fn sub_all_outgoing_messages(&self, reparam: &mut MessageND, factor: NodeIndex<usize>) {
         for out_edge in self.relaxation.edges_directed(factor, Outgoing) {
             reparam.sub_assign_outgoing(
-                &self.messages[out_edge.id().index()],
+                self.messages[out_edge.id().index()].as_slice(),
                 &self.alignment_indexing[out_edge.id().index()],
             );
         }
@@ -189,7 +216,7 @@ impl<'a> SRMP2Messages<'a> {
         &self,
         reparam: &mut MessageND,
         factor: NodeIndex<usize>,
-        edge: EdgeReference<'_, (), usize>,
+        edge: EdgeReference<'_, AlignmentIndexing, usize>,
     ) {
         if true {
             for out_edge in self
@@ -198,7 +225,7 @@ impl<'a> SRMP2Messages<'a> {
                 .filter(|out_edge| out_edge.id().index() != edge.id().index())
             {
                 reparam.sub_assign_outgoing(
-                    &self.messages[out_edge.id().index()],
+                    self.messages[out_edge.id().index()].as_slice(),
                     &self.alignment_indexing[out_edge.id().index()],
                 );
             }
@@ -209,7 +236,7 @@ impl<'a> SRMP2Messages<'a> {
             // todo: bench performance
             self.sub_all_outgoing_messages(reparam, factor);
             reparam.add_assign_outgoing(
-                &self.messages[edge.id().index()],
+                self.messages[edge.id().index()].as_slice(),
                 &self.alignment_indexing[edge.id().index()],
             );
         }
@@ -220,17 +247,19 @@ impl<'a> SRMP2Messages<'a> {
     fn update_and_normalize(
         &mut self,
         reparam: &MessageND,
-        edge: EdgeReference<'_, (), usize>,
+        edge: EdgeReference<'_, AlignmentIndexing, usize>,
     ) -> f64 {
-        let delta = self.messages[edge.id().index()]
-            .set_to_reparam_min(&reparam, &self.alignment_indexing[edge.id().index()]);
+        let delta = reparam.reparam_min_into(
+            self.messages[edge.id().index()].as_mut_slice(),
+            &self.alignment_indexing[edge.id().index()],
+        );
         self.messages[edge.id().index()].add_assign_scalar(-delta);
         delta
     }
 
     // Updates the message corresponding to a given edge by sending messages,
     // i.e., performs a computation from equation (17) in the SRMP paper
-    fn send(&mut self, edge: EdgeReference<'_, (), usize>) -> f64 {
+    fn send(&mut self, edge: EdgeReference<'_, AlignmentIndexing, usize>) -> f64 {
         debug!(
             "In send() for edge {} from {} to {}",
             edge.id().index(),
@@ -247,7 +276,7 @@ impl<'a> SRMP2Messages<'a> {
 
     // Computes a reparametrization for a given factor by sending messages to and from it,
     // i.e., performs a computation from line 5 in the SRMP paper
-    fn compute_reparam(&mut self, factor: NodeIndex<usize>) -> MessageND {
+    fn compute_reparam(&self, factor: NodeIndex<usize>) -> MessageND {
         debug!("In compute_reparam() for factor {}", factor.index());
 
         let mut reparam = self.init_reparam(factor);
@@ -257,7 +286,11 @@ impl<'a> SRMP2Messages<'a> {
     }
 
     // Subtracts a given reparametrization from the message corresponding to a given edge
-    fn sub_assign_reparam(&mut self, reparam: &MessageND, edge: EdgeReference<'_, (), usize>) {
+    fn sub_assign_reparam(
+        &mut self,
+        reparam: &MessageND,
+        edge: EdgeReference<'_, AlignmentIndexing, usize>,
+    ) {
         debug!(
             "In sub_assign_reparam() for edge {} from {} to {}",
             edge.id().index(),
@@ -265,7 +298,7 @@ impl<'a> SRMP2Messages<'a> {
             edge.target().index()
         );
 
-        self.messages[edge.id().index()].sub_assign_incoming(reparam);
+        self.messages[edge.id().index()].sub_assign_incoming(reparam.as_slice());
     }
 
     fn factor_origin(&self, node: NodeIndex<usize>) -> &FactorOrigin {
@@ -273,7 +306,12 @@ impl<'a> SRMP2Messages<'a> {
     }
 
     fn send_incoming_backward(&mut self, node: NodeIndex<usize>) {
-        for in_edge in self.graph_backward.edges_directed(node, Incoming) {
+        let relaxation = self.relaxation;
+        let in_edges = relaxation
+            .edges_directed(node, Incoming)
+            .filter(|in_edge| self.edge_category[in_edge.id().index()].is_backward)
+            .collect::<Vec<_>>();
+        for in_edge in in_edges {
             self.send(in_edge);
         }
         // todo: split data structure like in original impl of SRMP:
@@ -281,7 +319,7 @@ impl<'a> SRMP2Messages<'a> {
         // messages + alignment
     }
 
-    fn update_incoming_forward(&self, node: NodeIndex<usize>, reparam: &MessageND) {
+    fn update_incoming_forward(&self, _node: NodeIndex<usize>, _reparam: &MessageND) {
         unimplemented!()
 
         // let mut reparam = self.messages.compute_reparam_forward(*factor);
@@ -296,13 +334,13 @@ impl<'a> SRMP2Messages<'a> {
     // .edges_directed(*factor, Incoming)
     // .filter(|in_edge| self.messages.node_edge_attrs.edge_is_forward[in_edge.id().index()])
 
-    fn update_incoming_backward(&self, node: NodeIndex<usize>, reparam: &MessageND) {
+    fn update_incoming_backward(&self, _node: NodeIndex<usize>, _reparam: &MessageND) {
         // for in_edge in self.messages.send_incoming_backward(*factor) {
         //     self.messages.sub_assign_reparam(&reparam, *in_edge);
         // }
     }
 
-    fn send_incoming_forward_update_lb(&self, node: NodeIndex<usize>) -> f64 {
+    fn send_incoming_forward_update_lb(&self, _node: NodeIndex<usize>) -> f64 {
         unimplemented!()
 
         // todo: send messages along incoming edges that are forward or update, exactly once
@@ -324,7 +362,7 @@ impl<'a> SRMP2Messages<'a> {
         // }
     }
 
-    fn send_incoming_backward_update_lb(&self, node: NodeIndex<usize>) -> f64 {
+    fn send_incoming_backward_update_lb(&self, _node: NodeIndex<usize>) -> f64 {
         unimplemented!()
     }
 
@@ -346,7 +384,7 @@ impl<'a> SRMP2Messages<'a> {
     // Refer to the "Extracting primal solution" subsection in the SRMP section for more details.
     fn send_restricted(
         &self,
-        edge: EdgeReference<'_, (), usize>,
+        edge: EdgeReference<'_, AlignmentIndexing, usize>,
         solution: &Solution,
     ) -> MessageND {
         debug!(
@@ -395,12 +433,12 @@ impl<'a> SRMP2Messages<'a> {
         self.sub_all_outgoing_messages(&mut reparam_beta, factor);
         for in_edge in self.relaxation.edges_directed(factor, Incoming) {
             let alpha = self.relaxation.factor_origin(in_edge.source());
-            let num_labeled = solution.num_labeled(&self.cfn.factor_variables(alpha));
+            let num_labeled = solution.num_labeled(self.cfn.factor_variables(alpha).iter());
             if num_labeled > 0 && num_labeled < self.cfn.arity(alpha) {
                 let restrected_message = self.send_restricted(in_edge, solution);
-                reparam_beta.add_assign_incoming(&restrected_message);
+                reparam_beta.add_assign_incoming(restrected_message.as_slice());
             } else {
-                reparam_beta.add_assign_incoming(&self.messages[in_edge.id().index()]);
+                reparam_beta.add_assign_incoming(self.messages[in_edge.id().index()].as_slice());
             }
         }
         reparam_beta
@@ -436,6 +474,11 @@ pub struct SRMP2<'a> {
     messages: SRMP2Messages<'a>, // the messages sent along the edges of the relaxation graph
     factor_sequence: FactorSequence, // the sequence of factors considered in the forward and backward passes
     initial_lower_bound: f64,        // the initial lower bound
+    lower_bound: f64,                // the lower bound reached in the last run
+    best_solution: Option<Solution>, // the best solution found in the last run
+    iterations: usize,               // the number of iterations performed in the last run
+    elapsed_time: Duration,          // the running time of the last run
+    stop_reason: Option<StopReason>, // the reason why the last run stopped (None if the solver hasn't run yet)
 }
 
 impl<'a> SRMP2<'a> {
@@ -494,17 +537,24 @@ impl<'a> SRMP2<'a> {
 }
 
 impl<'a> Solver<'a> for SRMP2<'a> {
-    fn init(cfn: &'a CostFunctionNetwork, relaxation: &'a Relaxation) -> Self {
+    fn init(relaxation: &'a Relaxation<'a>) -> Result<Self, SolverError> {
+        validate(relaxation)?;
+        let cfn = relaxation.cfn();
         // todo: different ordering procedures
-        let factor_sequence = FactorSequence::new(&relaxation).sort();
+        let factor_sequence = FactorSequence::new(relaxation).sort();
         let messages = SRMP2Messages::new(cfn, relaxation, &factor_sequence);
         let initial_lower_bound = messages.get_initial_lower_bound();
 
-        SRMP2 {
+        Ok(SRMP2 {
             messages,
             factor_sequence,
             initial_lower_bound,
-        }
+            lower_bound: initial_lower_bound,
+            best_solution: None,
+            iterations: 0,
+            elapsed_time: Duration::ZERO,
+            stop_reason: None,
+        })
     }
 
     fn run(mut self, options: &SolverOptions) -> Self {
@@ -518,6 +568,7 @@ impl<'a> Solver<'a> for SRMP2<'a> {
         let mut best_cost = 0.;
         let mut forward_cost;
         let mut backward_cost;
+        let stop_reason;
 
         loop {
             let previous_lower_bound = current_lower_bound;
@@ -583,12 +634,19 @@ impl<'a> Solver<'a> for SRMP2<'a> {
             // Break if a stopping condition is satisfied
             if iteration >= options.max_iterations() {
                 info!("Maximum number of iterations reached. Interrupting.");
+                stop_reason = StopReason::MaxIterations;
                 break;
             } else if elapsed_time >= options.time_max() {
                 info!("Time limit reached. Interrupting.");
+                stop_reason = StopReason::TimeLimit;
                 break;
-            } else if iteration > 1 && current_lower_bound < previous_lower_bound + options.eps() {
+            } else if iteration > 1
+                && options
+                    .eps()
+                    .is_converged(previous_lower_bound, current_lower_bound)
+            {
                 info!("Lower bound increased less than by epsilon. Interrupting.");
+                stop_reason = StopReason::Converged;
                 break;
             }
         }
@@ -600,6 +658,112 @@ impl<'a> Solver<'a> for SRMP2<'a> {
             best_solution
         );
 
+        self.lower_bound = current_lower_bound;
+        self.best_solution = best_solution;
+        self.iterations = iteration;
+        self.elapsed_time = time_start.elapsed();
+        self.stop_reason = Some(stop_reason);
         self
     }
+
+    fn result(&self) -> SolverResult {
+        SolverResult::new(
+            self.messages.cfn,
+            self.lower_bound,
+            self.best_solution.as_ref(),
+            self.elapsed_time,
+            self.iterations,
+            self.stop_reason,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::graph::DiGraph;
+
+    use crate::cfn::{
+        cost_function_network::tests::construct_cfn_example_1, relaxation::ConstructRelaxation,
+    };
+
+    use super::*;
+
+    // Labels edges as before the categories were stored per edge: with a separate graph for every category
+    // (returns the graphs of forward, backward, and update edges)
+    fn categorize_edges_three_graphs(
+        relaxation: &Relaxation,
+        factor_sequence: &FactorSequence,
+    ) -> [DiGraph<(), (), usize>; 3] {
+        let num_nodes = relaxation.node_count();
+        let mut graph_forward = DiGraph::with_capacity(num_nodes, 0);
+        let mut graph_backward = DiGraph::with_capacity(num_nodes, 0);
+        let mut graph_update = DiGraph::with_capacity(num_nodes, 0);
+        for _i in 0..num_nodes {
+            graph_forward.add_node(());
+            graph_backward.add_node(());
+            graph_update.add_node(());
+        }
+
+        let mut is_touched = BitVec::<usize, LocalBits>::repeat(false, num_nodes);
+        for factor in factor_sequence.iter() {
+            is_touched.set(factor.index(), true);
+            for in_edge in relaxation.edges_directed(*factor, Incoming) {
+                let beta = in_edge.source().index();
+                if is_touched[beta] {
+                    graph_backward.add_edge(in_edge.source(), in_edge.target(), ());
+                } else {
+                    graph_update.add_edge(in_edge.source(), in_edge.target(), ());
+                }
+                is_touched.set(beta, true);
+            }
+        }
+
+        let mut is_touched = BitVec::<usize, LocalBits>::repeat(false, num_nodes);
+        for factor in factor_sequence.iter().rev() {
+            is_touched.set(factor.index(), true);
+            for in_edge in relaxation.edges_directed(*factor, Incoming) {
+                let beta = in_edge.source().index();
+                if is_touched[beta] {
+                    graph_forward.add_edge(in_edge.source(), in_edge.target(), ());
+                }
+                is_touched.set(beta, true);
+            }
+        }
+
+        [graph_forward, graph_backward, graph_update]
+    }
+
+    #[test]
+    fn categorize_edges_matches_three_graphs() {
+        for cfn in [
+            construct_cfn_example_1(),
+            CostFunctionNetwork::random_grid(4, 5, 2),
+        ] {
+            let relaxation = Relaxation::new(&cfn);
+            let factor_sequence = FactorSequence::new(&relaxation).sort();
+            let edge_category = categorize_edges(&relaxation, &factor_sequence);
+            assert_eq!(edge_category.len(), relaxation.edge_count());
+
+            let graphs = categorize_edges_three_graphs(&relaxation, &factor_sequence);
+            let is_categories: [fn(&EdgeCategory) -> bool; 3] = [
+                |category| category.is_forward,
+                |category| category.is_backward,
+                |category| category.is_update_lb,
+            ];
+            for (graph, is_category) in graphs.iter().zip(is_categories) {
+                let mut expected_edges = graph
+                    .edge_references()
+                    .map(|edge| (edge.source(), edge.target()))
+                    .collect::<Vec<_>>();
+                let mut edges = relaxation
+                    .edge_references()
+                    .filter(|edge| is_category(&edge_category[edge.id().index()]))
+                    .map(|edge| (edge.source(), edge.target()))
+                    .collect::<Vec<_>>();
+                expected_edges.sort();
+                edges.sort();
+                assert_eq!(edges, expected_edges);
+            }
+        }
+    }
 }
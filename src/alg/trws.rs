@@ -0,0 +1,304 @@
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use petgraph::{graph::NodeIndex, visit::EdgeRef, Direction::Incoming};
+
+use crate::{
+    cfn::{factor_sequence::FactorSequence, relaxation::Relaxation, solution::Solution},
+    messages::message_trait::Message,
+    CostFunctionNetwork,
+};
+
+use super::{
+    solver::{
+        validate, Solver, SolverError, SolverOptions, SolverResult, StopReason, WeightScheme,
+    },
+    srmp::{NodeEdgeAttrs, SRMPMessages},
+};
+
+// Stores information for the sequential tree-reweighted message passing (TRW-S) algorithm.
+// Factors are processed in the same order as in SRMP, and messages are sent along the same edge categories,
+// but each factor's reparametrization is distributed uniformly among all its incoming edges.
+// The lower bound is computed from the complete reparametrization after every iteration.
+pub struct TRWS<'a> {
+    cfn: &'a CostFunctionNetwork,    // the cost function network
+    relaxation: &'a Relaxation<'a>,  // the relaxation graph
+    node_edge_attrs: NodeEdgeAttrs,  // the edge categories used in the forward and backward passes
+    node_omega: Vec<f64>, // the uniform scaling factor for the reparametrization updates of every node
+    messages: SRMPMessages<'a>, // the messages sent along the edges of the relaxation graph
+    factor_sequence: FactorSequence, // the sequence of factors considered in the forward and backward passes
+    lower_bound: f64,                // the lower bound after the last run
+    best_solution: Option<Solution>, // the best solution found in the last run
+    iterations: usize,               // the number of iterations performed in the last run
+    elapsed_time: Duration,          // the running time of the last run
+    stop_reason: Option<StopReason>, // the reason why the last run stopped (None if the solver hasn't run yet)
+}
+
+impl<'a> TRWS<'a> {
+    // Extends a partial solution using the given factor
+    fn compute_solution(&self, solution: &mut Solution, beta: NodeIndex<usize>) {
+        if solution.is_fully_labeled(&self.relaxation.node_variables(beta)) {
+            return;
+        }
+
+        let beta_origin = self.relaxation.factor_origin(beta);
+        let restricted_reparam = self.messages.compute_restricted_reparam(beta, solution);
+
+        restricted_reparam.update_solution_restricted_min(self.cfn, beta_origin, solution);
+    }
+
+    // Processes a given factor: sends messages along incoming edges of one category,
+    // then moves the factor's reparametrization into the incoming edges of the other category
+    fn process_factor(
+        &mut self,
+        factor: NodeIndex<usize>,
+        is_forward_pass: bool,
+        solution: &mut Option<Solution>,
+    ) {
+        let relaxation = self.relaxation;
+        let is_send_edge = |attrs: &NodeEdgeAttrs, edge: usize| match is_forward_pass {
+            true => attrs.edge_is_backward(edge),
+            false => attrs.edge_is_forward(edge),
+        };
+
+        for in_edge in relaxation
+            .edges_directed(factor, Incoming)
+            .filter(|in_edge| is_send_edge(&self.node_edge_attrs, in_edge.id().index()))
+        {
+            self.messages.send(in_edge);
+        }
+
+        if let Some(labeling) = solution {
+            self.compute_solution(labeling, factor);
+        }
+
+        let mut reparam = self.messages.compute_reparam(factor);
+        reparam.mul_assign_scalar(self.node_omega[factor.index()]);
+        for in_edge in relaxation
+            .edges_directed(factor, Incoming)
+            .filter(|in_edge| !is_send_edge(&self.node_edge_attrs, in_edge.id().index()))
+        {
+            self.messages.sub_assign_reparam(&reparam, in_edge);
+        }
+    }
+}
+
+impl<'a> Solver<'a> for TRWS<'a> {
+    fn init(relaxation: &'a Relaxation<'a>) -> Result<Self, SolverError> {
+        validate(relaxation)?;
+        let cfn = relaxation.cfn();
+        let factor_sequence = FactorSequence::new(relaxation).sort();
+        let node_edge_attrs = NodeEdgeAttrs::new(relaxation, &factor_sequence, &WeightScheme::Srmp);
+
+        // Distribute reparametrizations uniformly among all incoming edges
+        let node_omega = relaxation
+            .node_indices()
+            .map(|node| 1. / relaxation.edges_directed(node, Incoming).count().max(1) as f64)
+            .collect();
+
+        Ok(TRWS {
+            cfn,
+            relaxation,
+            node_edge_attrs,
+            node_omega,
+            messages: SRMPMessages::new(relaxation),
+            factor_sequence,
+            lower_bound: f64::NEG_INFINITY,
+            best_solution: None,
+            iterations: 0,
+            elapsed_time: Duration::ZERO,
+            stop_reason: None,
+        })
+    }
+
+    fn run(mut self, options: &SolverOptions) -> Self {
+        let time_start = Instant::now();
+        let mut iteration = 0;
+        let mut best_cost = 0.;
+        let mut lower_bound = f64::NEG_INFINITY;
+        let mut best_solution: Option<Solution> = None;
+        let sequence: Vec<NodeIndex<usize>> = self.factor_sequence.iter().copied().collect();
+        let stop_reason;
+
+        // TRW-S distributes reparametrizations uniformly, so the weight scheme doesn't apply
+        if *options.weight_scheme() != WeightScheme::Srmp {
+            warn!(
+                "TRW-S ignores the weight scheme {:?}.",
+                options.weight_scheme()
+            );
+        }
+        self.messages
+            .set_hard_constraint_value(options.hard_constraint_value())
+            .set_damping(options.damping());
+
+        // The target is given in the sense of the network (negating is its own inverse for maximization)
+        let target_lower_bound = options
+            .target_lower_bound()
+            .map(|target| self.cfn.cost_to_objective(target));
+
+        loop {
+            let previous_lower_bound = lower_bound;
+            let compute_solution = !options.lower_bound_only()
+                && options.compute_solution_period() > 0
+                && ((iteration + 1) % options.compute_solution_period() == 0
+                    || iteration + 1 == options.max_iterations());
+
+            // Perform the forward and the backward passes
+            for (is_forward_pass, factors) in [
+                (true, sequence.clone()),
+                (false, sequence.iter().rev().copied().collect()),
+            ] {
+                let mut solution = compute_solution.then(|| Solution::new(self.cfn));
+                for factor in factors {
+                    self.process_factor(factor, is_forward_pass, &mut solution);
+                }
+                if let Some(mut solution) = solution {
+                    // Label variables that were not reached by solution extraction, so the cost is defined
+                    if solution.num_labeled(0..self.cfn.num_variables()) < self.cfn.num_variables()
+                    {
+                        solution.complete(self.cfn);
+                    }
+                    let cost = solution.cost(self.cfn);
+                    if best_solution.is_none() || best_cost > cost {
+                        best_cost = cost;
+                        best_solution = Some(solution);
+                    }
+                }
+            }
+
+            lower_bound = lower_bound.max(self.messages.compute_lower_bound());
+            let elapsed_time = time_start.elapsed();
+            info!(
+                "TRW-S iteration {}. Elapsed time {:?}. Current lower bound {}.",
+                iteration, elapsed_time, lower_bound
+            );
+
+            // Break if a stopping condition is satisfied
+            iteration += 1;
+            if options.renormalize_period() > 0 && iteration % options.renormalize_period() == 0 {
+                self.messages.renormalize();
+            }
+            if target_lower_bound
+                .is_some_and(|target| options.eps().is_reached(lower_bound, target))
+            {
+                info!("Lower bound reached the target value. Interrupting.");
+                stop_reason = StopReason::TargetReached;
+                break;
+            } else if iteration >= options.max_iterations() {
+                info!("Maximum number of iterations reached. Interrupting.");
+                stop_reason = StopReason::MaxIterations;
+                break;
+            } else if elapsed_time >= options.time_max() {
+                info!("Time limit reached. Interrupting.");
+                stop_reason = StopReason::TimeLimit;
+                break;
+            } else if iteration > 1
+                && options
+                    .eps()
+                    .is_converged(previous_lower_bound, lower_bound)
+            {
+                info!("Lower bound increased less than by epsilon. Interrupting.");
+                stop_reason = StopReason::Converged;
+                break;
+            }
+        }
+
+        info!(
+            "TRW-S finished. Elapsed time {:?}. Best cost {}. Best solution {:?}.",
+            time_start.elapsed(),
+            best_cost,
+            best_solution
+        );
+
+        self.lower_bound = lower_bound;
+        self.best_solution = best_solution;
+        self.iterations = iteration;
+        self.elapsed_time = time_start.elapsed();
+        self.stop_reason = Some(stop_reason);
+        self
+    }
+
+    fn result(&self) -> SolverResult {
+        SolverResult::new(
+            self.cfn,
+            self.lower_bound,
+            self.best_solution.as_ref(),
+            self.elapsed_time,
+            self.iterations,
+            self.stop_reason,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        alg::exact::brute_force_min,
+        cfn::{relaxation::ConstructRelaxation, uai::UAI},
+        factors::{factor_type::FactorType, function_table::FunctionTable},
+    };
+
+    use super::*;
+
+    #[test]
+    fn solution_fully_labeled() {
+        // Variable 2 isn't contained in any non-unary factor
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 2, 4], true, 1);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            vec![0., 2., 1., 0., 3., 1.],
+        )));
+        cfn.update_unary_factor(2, vec![3., 1., 2., 5.]);
+        let relaxation = Relaxation::new(&cfn);
+
+        let result = TRWS::solve(&relaxation, &SolverOptions::default()).unwrap();
+        assert!(result.labeling().iter().all(|label| label.is_some()));
+        assert_eq!(result.labeling()[2], Some(1));
+        assert_eq!(result.best_cost(), Some(1.));
+    }
+
+    #[test]
+    fn message_options() {
+        let cfn = CostFunctionNetwork::random_grid(3, 3, 3);
+        let optimal_cost = brute_force_min(&cfn).0;
+        let relaxation = Relaxation::new(&cfn);
+        let default = TRWS::init(&relaxation)
+            .unwrap()
+            .run(&SolverOptions::default());
+
+        let mut options = SolverOptions::default();
+        options
+            .set_damping(0.5)
+            .set_hard_constraint_value(1e6)
+            .set_renormalize_period(2);
+        let trws = TRWS::init(&relaxation).unwrap().run(&options);
+        assert_eq!(trws.messages.hard_constraint_value(), 1e6);
+        assert_eq!(trws.messages.damping(), 0.5);
+        // Damping changes the messages, but the bound stays valid
+        assert!((0..relaxation.edge_count())
+            .any(|index| trws.messages.message(index) != default.messages.message(index)));
+        let result = trws.result();
+        assert!(result.lower_bound() <= optimal_cost + 1e-9);
+        assert!(result.best_cost().unwrap() >= optimal_cost - 1e-9);
+    }
+
+    #[test]
+    fn lower_bound_frustrated_cycle() {
+        // The optimum of this instance is 1, while its LP relaxation has optimal value 0
+        let cfn =
+            CostFunctionNetwork::read_uai("test_instances/frustrated_cycle_3.uai".into(), false)
+                .unwrap();
+        let relaxation = Relaxation::new(&cfn);
+        let trws = TRWS::init(&relaxation)
+            .unwrap()
+            .run(&SolverOptions::default());
+
+        let result = trws.result();
+        assert!(result.lower_bound() <= 1. + 1e-9);
+        assert!(result.lower_bound() > -1e-9);
+        assert!(result.best_cost().unwrap() >= 1. - 1e-9);
+    }
+}
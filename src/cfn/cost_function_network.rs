@@ -2,20 +2,34 @@
 
 use std::{
     borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
     fmt::Debug,
     fs::OpenOptions,
-    io::{self, BufRead, BufReader, Write},
-    mem,
+    hash::{BuildHasher, RandomState},
+    io::{self, BufRead, Write},
+    iter, mem,
     path::PathBuf,
     slice::Iter,
     time::Instant,
 };
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 use log::{debug, warn};
 
 use crate::{
-    cfn::uai::{string_to_vec, vec_to_string},
-    factors::{factor_trait::Factor, factor_type::FactorType, function_table::FunctionTable},
+    cfn::{
+        solution::Solution,
+        uai::{
+            find_duplicate_variable, open_uai, parse_token, parse_tokens, sort_scope,
+            vec_to_string, UaiParseError, MAX_RESERVED_TABLE_ENTRIES,
+        },
+    },
+    factors::{
+        factor_trait::Factor, factor_type::FactorType, function_table::FunctionTable, potts::Potts,
+    },
+    messages::{message_nd::MessageND, message_trait::Message},
 };
 
 use crate::cfn::uai::UAIState;
@@ -31,17 +45,35 @@ pub enum FactorOrigin {
     NonUnaryFactor(FactorIndex),
 }
 
+// Shows whether the costs of a cost function network are minimized or maximized (i.e., treated as scores)
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sense {
+    Minimize,
+    Maximize,
+}
+
 // Stores information about a variable in the cost function network
-#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
 pub struct Variable {
     domain_size: usize,          // the size of the domain of this variable
     factor_index: Option<usize>, // the index of the corresponding unary factor in `factors` (if it exits)
 }
 
 // Stores a cost function network
+// Note: factors and the constant are always stored as costs to be minimized;
+// for networks with `Sense::Maximize`, they are negated when added and solver results are negated back
+// Clones are deep, i.e., all function tables are copied, so a clone can be modified independently
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
 pub struct CostFunctionNetwork {
     variables: Vec<Variable>, // stores information about variables in the network
     factors: Vec<FactorType>, // stores representations of all factors (unary and non-unary)
+    constant: f64,            // the constant term contributing to the cost of every labeling
+    sense: Sense,             // whether the user-facing objective is minimized or maximized
+    // the indices of factors that are hard constraints, which keep their costs when the sense changes
+    hard_constraints: BTreeSet<FactorIndex>,
 }
 
 impl CostFunctionNetwork {
@@ -50,6 +82,9 @@ impl CostFunctionNetwork {
         CostFunctionNetwork {
             variables: Vec::new(),
             factors: Vec::new(),
+            constant: 0.,
+            sense: Sense::Minimize,
+            hard_constraints: BTreeSet::new(),
         }
     }
 
@@ -59,17 +94,22 @@ impl CostFunctionNetwork {
         CostFunctionNetwork {
             variables: Vec::with_capacity(capacity_unary),
             factors: Vec::with_capacity(reserve_capacity),
+            constant: 0.,
+            sense: Sense::Minimize,
+            hard_constraints: BTreeSet::new(),
         }
     }
 
     // Creates an empty cost function network with provided domain sizes,
     // optionally reserves capacity for unary factors,
     // and additionally reserves capacity for a given number of non-unary factors
+    // Panics if some domain size is zero
     pub fn from_domain_sizes(
         domain_sizes: &Vec<usize>,
         reserve_unary: bool,
         capacity_non_unary: usize,
     ) -> Self {
+        check_domain_sizes(domain_sizes);
         let variables = domain_sizes
             .iter()
             .map(|domain_size| Variable {
@@ -82,7 +122,53 @@ impl CostFunctionNetwork {
         CostFunctionNetwork {
             variables,
             factors: Vec::with_capacity(reserve_capacity),
+            constant: 0.,
+            sense: Sense::Minimize,
+            hard_constraints: BTreeSet::new(),
+        }
+    }
+
+    // Creates a grid network with given numbers of rows, columns, and labels, and pseudo-random unary and pairwise costs
+    // (the costs are deterministic, so the network can be used in tests and benchmarks)
+    pub fn random_grid(rows: usize, columns: usize, num_labels: usize) -> Self {
+        let num_variables = rows * columns;
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(
+            &vec![num_labels; num_variables],
+            true,
+            2 * num_variables,
+        );
+        let mut seed: u64 = 1;
+        let mut next_cost = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 59) as f64
+        };
+
+        for variable in 0..num_variables {
+            let table = (0..num_labels).map(|_| next_cost()).collect();
+            cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn,
+                vec![variable],
+                table,
+            )));
+        }
+        for variable in 0..num_variables {
+            let (row, column) = (variable / columns, variable % columns);
+            let neighbors = [
+                (column + 1 < columns).then_some(variable + 1),
+                (row + 1 < rows).then_some(variable + columns),
+            ];
+            for neighbor in neighbors.into_iter().flatten() {
+                let table = (0..num_labels * num_labels).map(|_| next_cost()).collect();
+                cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                    &cfn,
+                    vec![variable, neighbor],
+                    table,
+                )));
+            }
         }
+        cfn
     }
 
     // Reserves capacity for at least `additional` more non-unary factors
@@ -92,23 +178,117 @@ impl CostFunctionNetwork {
     }
 
     // Computes the product of domain sizes of given variables
+    // Panics if the product overflows usize
+    // Note: a variant folding over the variables directly (without mapping them to domain sizes first) was
+    // benchmarked against this one in benches/product_domain_sizes.rs and showed no consistent difference
     fn product_domain_sizes(&self, variables: &Vec<usize>) -> usize {
         variables
             .iter()
             .map(|variable| self.domain_size(*variable))
-            .product()
+            .try_fold(1usize, |product, domain_size| {
+                product.checked_mul(domain_size)
+            })
+            .unwrap_or_else(|| {
+                panic!(
+                    "Product of domain sizes of variables {:?} overflows usize.",
+                    variables
+                )
+            })
     }
 
-    // Computes the product of domain sizes of given variables, alternative implementation
-    // todo: bench against product_domain_sizes()
-    fn product_domain_sizes_alt(&self, variables: &Vec<usize>) -> usize {
-        variables
-            .iter()
-            .fold(1, |product, variable| product * self.domain_size(*variable))
+    // Sets the optimization sense of this network; the values of existing factors and the constant
+    // are reinterpreted in the new sense, i.e., they are negated internally if the sense changes
+    // (truncated factors are expanded into function tables, see FactorType::scale())
+    // Hard constraints are costs to be minimized in either sense, so they are kept as they are
+    pub fn set_sense(&mut self, sense: Sense) -> &mut Self {
+        if sense != self.sense {
+            for (factor_index, factor) in self.factors.iter_mut().enumerate() {
+                if !self.hard_constraints.contains(&factor_index) {
+                    factor.scale(-1.);
+                }
+            }
+            self.constant *= -1.;
+            self.sense = sense;
+        }
+        self
+    }
+
+    // Returns the optimization sense of this network
+    pub fn sense(&self) -> Sense {
+        self.sense
+    }
+
+    // Converts an internal (minimization) cost or bound into the sense of this network
+    pub fn cost_to_objective(&self, cost: f64) -> f64 {
+        match self.sense {
+            Sense::Minimize => cost,
+            Sense::Maximize => -cost,
+        }
+    }
+
+    // Sets a factor of arbitrary type (given in the sense of this network)
+    pub fn add_factor(&mut self, mut factor: FactorType) -> &mut Self {
+        if self.sense == Sense::Maximize {
+            factor.scale(-1.);
+        }
+        self.insert_factor(factor);
+        self
     }
 
-    // Sets a factor of arbitrary type
-    pub fn add_factor(&mut self, factor: FactorType) -> &mut Self {
+    // Adds a Potts factor on given variables, which costs `penalty` (in the sense of this network)
+    // whenever their labels differ and 0 otherwise
+    // Panics unless var_a < var_b and both variables have the same domain size
+    pub fn add_potts_factor(&mut self, var_a: usize, var_b: usize, penalty: f64) -> &mut Self {
+        assert!(
+            var_a < var_b,
+            "Variables of a Potts factor must be distinct and sorted in increasing order."
+        );
+        assert_eq!(
+            self.domain_size(var_a),
+            self.domain_size(var_b),
+            "Variables {} and {} of a Potts factor have different domain sizes.",
+            var_a,
+            var_b
+        );
+        let potts = Potts::new(self, vec![var_a, var_b], penalty);
+        self.add_factor(FactorType::Potts(potts))
+    }
+
+    // Adds a hard constraint on given variables: a function table that costs 0 for labelings satisfying
+    // `is_allowed` and `hard_constraint_value` for all other labelings
+    // The value is a cost to be minimized regardless of the sense of this network (also after set_sense()),
+    // so that forbidden labelings are never preferred; a large finite value (see SolverOptions::set_hard_constraint_value()) avoids
+    // `inf - inf = NaN` in message passing on instances with conflicting constraints
+    pub fn add_hard_constraint(
+        &mut self,
+        variables: Vec<usize>,
+        is_allowed: impl Fn(&[usize]) -> bool,
+        hard_constraint_value: f64,
+    ) -> &mut Self {
+        let table = self
+            .labelings_of(&variables)
+            .map(|labeling| match is_allowed(&labeling) {
+                true => 0.,
+                false => hard_constraint_value,
+            })
+            .collect();
+        let factor = FunctionTable::new(self, variables, table);
+        let factor_index = self.insert_factor(FactorType::FunctionTable(factor));
+        self.hard_constraints.insert(factor_index);
+        self
+    }
+
+    // Checks if the factor with a given index was added as a hard constraint (see add_hard_constraint())
+    pub fn is_hard_constraint(&self, factor_index: usize) -> bool {
+        self.hard_constraints.contains(&factor_index)
+    }
+
+    // Sets a factor of arbitrary type (given as a cost to be minimized) and returns its index
+    fn insert_factor(&mut self, factor: FactorType) -> FactorIndex {
+        assert!(
+            factor.arity() > 0,
+            "Nullary factors are not supported, use add_constant() instead."
+        );
         assert_eq!(
             factor.arity(),
             factor.variables().len(),
@@ -124,9 +304,12 @@ impl CostFunctionNetwork {
                 let variable = factor.variables()[0];
                 if let Some(unary_factor_index) = self.variables[variable].factor_index {
                     self.factors[unary_factor_index] = factor;
+                    self.hard_constraints.remove(&unary_factor_index);
+                    unary_factor_index
                 } else {
                     self.variables[variable].factor_index = Some(self.factors.len());
                     self.factors.push(factor);
+                    self.factors.len() - 1
                 }
             }
             _ => {
@@ -137,12 +320,216 @@ impl CostFunctionNetwork {
                     unimplemented!("Overwriting non-unary factors is not currently implemented");
                 } else {
                     self.factors.push(factor);
+                    self.factors.len() - 1
+                }
+            }
+        }
+    }
+
+    // Adds a given value (in the sense of this network) to the constant term,
+    // which contributes to the cost of every labeling
+    pub fn add_constant(&mut self, value: f64) -> &mut Self {
+        self.constant += self.cost_to_objective(value);
+        self
+    }
+
+    // Returns the constant term (as a cost to be minimized), which contributes to the cost of every labeling
+    pub fn constant(&self) -> f64 {
+        self.constant
+    }
+
+    // Replaces the function table of the unary factor of a given variable (adds a unary factor if there is none)
+    // Note: this does not change any factor scopes, so relaxations constructed for this network stay valid
+    pub fn update_unary_factor(&mut self, variable: usize, new_table: Vec<f64>) -> &mut Self {
+        assert_eq!(
+            new_table.len(),
+            self.domain_size(variable),
+            "Unary function table length doesn't match the domain size of variable {}.",
+            variable
+        );
+        let factor = FactorType::FunctionTable(FunctionTable::new(self, vec![variable], new_table));
+        self.add_factor(factor)
+    }
+
+    // Adds the factors of a given cost function network with the same variables and domain sizes to this one:
+    // each factor is added to the existing factor with the same scope (both are stored as a function table),
+    // or inserted as a new factor if there is none
+    // Hard constraints of the other network stay hard constraints, also when they are added to a soft factor
+    pub fn add_assign(&mut self, other: &CostFunctionNetwork) -> &mut Self {
+        assert!(
+            self.num_variables() == other.num_variables()
+                && (0..self.num_variables())
+                    .all(|variable| self.domain_size(variable) == other.domain_size(variable)),
+            "Cost function networks must have the same variables and domain sizes."
+        );
+        assert_eq!(
+            self.sense, other.sense,
+            "Cost function networks must have the same optimization sense."
+        );
+
+        for (other_factor_index, other_factor) in other.factors.iter().enumerate() {
+            let variables = other_factor.variables();
+            let factor_index = match self.factor_index_by_scope(variables) {
+                Some(factor_index) => {
+                    self.add_to_factor(factor_index, other_factor.entries());
+                    factor_index
+                }
+                None => {
+                    let table = other_factor.clone_function_table();
+                    let factor = FunctionTable::new(self, variables.clone(), table);
+                    self.insert_factor(FactorType::FunctionTable(factor))
                 }
+            };
+            if other.is_hard_constraint(other_factor_index) {
+                self.hard_constraints.insert(factor_index);
+            }
+        }
+        self.constant += other.constant;
+        self
+    }
+
+    // Replaces the factor with a given index by a function table with the given values added to its entries
+    // Note: a hard constraint stays a hard constraint, i.e., added soft costs are no longer negated by set_sense()
+    fn add_to_factor(&mut self, factor_index: usize, values: impl Iterator<Item = f64>) {
+        let variables = self.factors[factor_index].variables().clone();
+        let table = self.factors[factor_index]
+            .entries()
+            .zip(values)
+            .map(|(value, other_value)| value + other_value)
+            .collect();
+        let factor = FunctionTable::new(self, variables, table);
+        self.factors[factor_index] = FactorType::FunctionTable(factor);
+    }
+
+    // Multiplies all function table entries of all factors by the given value
+    // Hard constraints are costs to be minimized (see add_hard_constraint()), so they aren't negated:
+    // they are skipped for negative multipliers
+    pub fn scale(&mut self, multiplier: f64) -> &mut Self {
+        for (factor_index, factor) in self.factors.iter_mut().enumerate() {
+            if multiplier >= 0. || !self.hard_constraints.contains(&factor_index) {
+                factor.scale(multiplier);
             }
         }
+        self.constant *= multiplier;
         self
     }
 
+    // Converts this network into an equivalent pairwise one (the standard higher-order-to-pairwise decomposition):
+    // every factor of arity at least 3 is replaced by an auxiliary variable with one label per entry
+    // of the factor's function table (in row-major order, i.e., the last variable changes the fastest)
+    // and a unary factor storing these entries, and by pairwise factors between each variable of the factor
+    // and the auxiliary variable, which cost 0 if the label of the variable agrees with the auxiliary label
+    // and infinity otherwise.
+    // Auxiliary variables are appended after the original ones in the order of their factors, so the original
+    // variables keep their indices and labels: a solution of the pairwise network maps back to this network
+    // by keeping its first num_variables() labels (and the label of an auxiliary variable is the index
+    // of the chosen entry in the function table of the corresponding factor)
+    pub fn to_pairwise(&self) -> CostFunctionNetwork {
+        let mut domain_sizes = (0..self.num_variables())
+            .map(|variable| self.domain_size(variable))
+            .collect::<Vec<_>>();
+        domain_sizes.extend(
+            self.factors
+                .iter()
+                .filter(|factor| factor.arity() > 2)
+                .map(|factor| factor.function_table_len()),
+        );
+        let capacity_non_unary = self
+            .factors
+            .iter()
+            .map(|factor| match factor.arity() {
+                1 => 0,
+                2 => 1,
+                arity => arity,
+            })
+            .sum();
+        let mut pairwise =
+            CostFunctionNetwork::from_domain_sizes(&domain_sizes, true, capacity_non_unary);
+        pairwise.constant = self.constant;
+        pairwise.sense = self.sense;
+
+        let mut auxiliary_variable = self.num_variables();
+        for (factor_index, factor) in self.factors.iter().enumerate() {
+            let is_hard_constraint = self.is_hard_constraint(factor_index);
+            if factor.arity() <= 2 {
+                let pairwise_index = pairwise.insert_factor(factor.clone());
+                if is_hard_constraint {
+                    pairwise.hard_constraints.insert(pairwise_index);
+                }
+                continue;
+            }
+
+            let table_len = factor.function_table_len();
+            let unary_factor = FunctionTable::new(
+                &pairwise,
+                vec![auxiliary_variable],
+                factor.clone_function_table(),
+            );
+            let unary_index = pairwise.insert_factor(FactorType::FunctionTable(unary_factor));
+            if is_hard_constraint {
+                pairwise.hard_constraints.insert(unary_index);
+            }
+
+            let mut stride = table_len;
+            for &variable in factor.variables() {
+                let domain_size = self.domain_size(variable);
+                stride /= domain_size;
+                let table = (0..domain_size)
+                    .flat_map(|label| {
+                        (0..table_len).map(move |auxiliary_label| {
+                            if (auxiliary_label / stride) % domain_size == label {
+                                0.
+                            } else {
+                                f64::INFINITY
+                            }
+                        })
+                    })
+                    .collect();
+                let pairwise_factor =
+                    FunctionTable::new(&pairwise, vec![variable, auxiliary_variable], table);
+                // The agreement constraints are hard, so they are kept when the sense changes
+                let pairwise_index =
+                    pairwise.insert_factor(FactorType::FunctionTable(pairwise_factor));
+                pairwise.hard_constraints.insert(pairwise_index);
+            }
+            auxiliary_variable += 1;
+        }
+        pairwise
+    }
+
+    // Checks if the given cost function network has the same variables, domain sizes,
+    // and non-unary factor scopes (at the same indices) as this one,
+    // i.e., if relaxations constructed for one network are valid for the other
+    pub fn has_same_scopes(&self, other: &CostFunctionNetwork) -> bool {
+        let non_unary_scopes = |cfn: &CostFunctionNetwork| {
+            cfn.factors
+                .iter()
+                .enumerate()
+                .filter(|(_factor_index, factor)| factor.arity() > 1)
+                .map(|(factor_index, factor)| (factor_index, factor.variables().clone()))
+                .collect::<Vec<_>>()
+        };
+
+        self.num_variables() == other.num_variables()
+            && (0..self.num_variables())
+                .all(|variable| self.domain_size(variable) == other.domain_size(variable))
+            && non_unary_scopes(self) == non_unary_scopes(other)
+    }
+
+    // Returns the function table of the unary factor of a given variable (as costs to be minimized),
+    // or None if the variable has no unary factor or its unary factor is not stored as a function table
+    pub fn unary_costs(&self, variable: usize) -> Option<&[f64]> {
+        match self.get_factor(&FactorOrigin::Variable(variable)) {
+            Some(FactorType::FunctionTable(function_table)) => Some(function_table.table()),
+            _ => None,
+        }
+    }
+
+    // Returns the factor with a given index (unary or non-unary), as in factors_iter() and variable_factors()
+    pub fn factor(&self, factor_index: usize) -> &FactorType {
+        &self.factors[factor_index]
+    }
+
     // Returns the factor indicated by its origin (unary or non-unary)
     pub fn get_factor(&self, factor_origin: &FactorOrigin) -> Option<&FactorType> {
         match factor_origin {
@@ -152,6 +539,25 @@ impl CostFunctionNetwork {
         .and_then(|factor_index| Some(&self.factors[factor_index]))
     }
 
+    // Returns the index of the factor whose variables are exactly the given ones (None if there is no such factor)
+    // todo: use an index of non-unary factors by variables instead of scanning all factors
+    fn factor_index_by_scope(&self, scope: &[usize]) -> Option<usize> {
+        match scope.len() {
+            0 => None,
+            1 => self.variables[scope[0]].factor_index,
+            _ => self
+                .factors
+                .iter()
+                .position(|factor| factor.arity() > 1 && factor.variables() == scope),
+        }
+    }
+
+    // Returns the factor whose variables are exactly the given ones (None if there is no such factor)
+    pub fn factor_by_scope(&self, scope: &[usize]) -> Option<&FactorType> {
+        self.factor_index_by_scope(scope)
+            .map(|factor_index| &self.factors[factor_index])
+    }
+
     // Returns arity of a given factor (unary or non-unary)
     pub fn arity(&self, factor_origin: &FactorOrigin) -> usize {
         match factor_origin {
@@ -170,6 +576,36 @@ impl CostFunctionNetwork {
         }
     }
 
+    // Returns an iterator over all joint labelings of the variables of a given factor (unary or non-unary)
+    // in row-major order, i.e., the last variable changes the fastest, which matches the layout of function tables
+    pub fn labelings(&self, factor_origin: &FactorOrigin) -> impl Iterator<Item = Vec<usize>> {
+        self.labelings_of(&self.factor_variables(factor_origin))
+    }
+
+    // Returns an iterator over all joint labelings of given variables in row-major order
+    fn labelings_of(&self, variables: &[usize]) -> impl Iterator<Item = Vec<usize>> {
+        let domain_sizes = variables
+            .iter()
+            .map(|variable| self.domain_size(*variable))
+            .collect::<Vec<_>>();
+        let num_labelings = domain_sizes.iter().product();
+        let mut labeling = vec![0; domain_sizes.len()];
+
+        (0..num_labelings).map(move |index| {
+            if index > 0 {
+                // Advance to the next labeling by mixed-radix counting
+                for position in (0..labeling.len()).rev() {
+                    labeling[position] += 1;
+                    if labeling[position] < domain_sizes[position] {
+                        break;
+                    }
+                    labeling[position] = 0;
+                }
+            }
+            labeling.clone()
+        })
+    }
+
     // Returns the length
     pub fn function_table_len(&self, factor_origin: &FactorOrigin) -> usize {
         match factor_origin {
@@ -181,16 +617,17 @@ impl CostFunctionNetwork {
     }
 
     // Returns a list of variables contained in the first factor and not the second,
-    // assuming the first fully contains the second
+    // assuming the first fully contains the second (checked in debug builds by AlignmentIndexing::new())
     pub fn get_variables_difference(
         &self,
         alpha: &FactorOrigin,
         beta: &FactorOrigin,
     ) -> Vec<usize> {
-        // Assumption: `alpha` contains `beta`
+        // Assumption: `alpha` contains `beta` (both scopes are sorted)
         let alpha_variables = self.factor_variables(alpha);
         let beta_variables = self.factor_variables(beta);
-        let mut difference = Vec::with_capacity(alpha_variables.len() - beta_variables.len());
+        let mut difference =
+            Vec::with_capacity(alpha_variables.len().saturating_sub(beta_variables.len()));
         let mut var_b_iter = beta_variables.iter().peekable();
         for &var_a in alpha_variables.iter() {
             if var_b_iter.peek().is_some_and(|var_b| **var_b == var_a) {
@@ -199,9 +636,39 @@ impl CostFunctionNetwork {
                 difference.push(var_a);
             }
         }
+        // If a variable of `beta` is missing from `alpha`, the walk gets stuck on it and the difference is wrong
+        debug_assert!(
+            var_b_iter.peek().is_none(),
+            "Scope {:?} is not contained in scope {:?}.",
+            beta_variables,
+            alpha_variables
+        );
         difference
     }
 
+    // Computes the min-marginal of a given factor onto one of its variables,
+    // i.e., for each label of `onto_variable`, the smallest value of the factor over labelings of its other variables
+    pub fn min_marginal(&self, factor_origin: &FactorOrigin, onto_variable: usize) -> Vec<f64> {
+        assert!(
+            self.factor_variables(factor_origin)
+                .contains(&onto_variable),
+            "Variable {} is not in the scope of the factor.",
+            onto_variable
+        );
+
+        // The min-marginal is the restricted minimum with respect to an empty solution
+        MessageND::clone_factor(self, factor_origin)
+            .restricted_min(
+                self,
+                &Solution::new(self),
+                factor_origin,
+                &FactorOrigin::Variable(onto_variable),
+            )
+            .iter()
+            .copied()
+            .collect()
+    }
+
     // Returns the number of variables in the cost function network
     pub fn num_variables(&self) -> usize {
         self.variables.len()
@@ -217,21 +684,232 @@ impl CostFunctionNetwork {
         self.factors.iter()
     }
 
+    // Returns an iterator over all factors (in the order of factors_iter()), each paired with its origin,
+    // i.e., its variable for unary factors and its index for non-unary ones (see get_factor())
+    pub fn iter_factors_with_origin(&self) -> impl Iterator<Item = (FactorOrigin, &FactorType)> {
+        self.factors
+            .iter()
+            .enumerate()
+            .map(|(factor_index, factor)| match factor.arity() {
+                1 => (FactorOrigin::Variable(factor.variables()[0]), factor),
+                _ => (FactorOrigin::NonUnaryFactor(factor_index), factor),
+            })
+    }
+
+    // Returns the contributions to the objective value of a given solution (in the sense of this network):
+    // first the constant term (with origin None), then the value of each factor in the order of factors_iter(),
+    // so that the contributions sum to the objective value of the solution
+    pub fn cost_breakdown(&self, solution: &Solution) -> Vec<(Option<FactorOrigin>, f64)> {
+        let factor_costs = self
+            .iter_factors_with_origin()
+            .map(|(factor_origin, factor)| {
+                let labeling = factor
+                    .variables()
+                    .iter()
+                    .map(|variable| {
+                        solution[*variable]
+                            .expect("Solution is undefined on a variable involved in this factor")
+                    })
+                    .collect::<Vec<_>>();
+                let cost = factor.value_at(self, &labeling);
+                (Some(factor_origin), self.cost_to_objective(cost))
+            });
+        std::iter::once((None, self.cost_to_objective(self.constant)))
+            .chain(factor_costs)
+            .collect()
+    }
+
+    // Returns the indices (in the order of factors_iter()) of all factors containing each variable,
+    // including the unary ones
+    pub fn variable_factors(&self) -> Vec<Vec<usize>> {
+        let mut variable_factors = vec![Vec::new(); self.num_variables()];
+        for (factor_index, factor) in self.factors.iter().enumerate() {
+            for variable in factor.variables() {
+                variable_factors[*variable].push(factor_index);
+            }
+        }
+        variable_factors
+    }
+
+    // Returns all factors containing a given variable (including its unary factor, if any),
+    // each paired with the position of the variable in the factor's scope
+    // Note: this scans all factors; to query many variables, use variable_factors() instead
+    pub fn incident_factors(&self, variable: usize) -> Vec<(&FactorType, usize)> {
+        self.factors
+            .iter()
+            .filter_map(|factor| {
+                factor
+                    .variables()
+                    .iter()
+                    .position(|factor_variable| *factor_variable == variable)
+                    .map(|position| (factor, position))
+            })
+            .collect()
+    }
+
+    // Improves a fully labeled solution by ICM (iterated conditional modes), a local search that sweeps over
+    // all variables and relabels each one by the label minimizing the sum of costs of the factors containing it,
+    // given the labels of the other variables; stops after a sweep without changes or after `max_sweeps` sweeps
+    // Returns the number of performed sweeps
+    // Note: labels are only changed if this strictly decreases the cost, so the cost never increases
+    pub fn icm(&self, solution: &mut Solution, max_sweeps: usize) -> usize {
+        assert!(
+            solution.is_fully_labeled(&(0..self.num_variables()).collect::<Vec<_>>()),
+            "ICM requires a fully labeled solution."
+        );
+        let variable_factors = self.variable_factors();
+        let local_cost = |solution: &Solution, variable: usize| {
+            variable_factors[variable]
+                .iter()
+                .map(|factor_index| self.factors[*factor_index].cost(self, solution))
+                .sum::<f64>()
+        };
+
+        // Relabeling stops tracking the running cost, so it is restored after the sweeps
+        let running_cost = solution.running_cost();
+        let mut total_improvement = 0.;
+        let mut sweeps = 0;
+        while sweeps < max_sweeps {
+            sweeps += 1;
+            let mut is_changed = false;
+            for variable in 0..self.num_variables() {
+                let current_label = solution[variable];
+                let current_cost = local_cost(solution, variable);
+                let (mut best_label, mut best_cost) = (current_label, current_cost);
+                for label in 0..self.domain_size(variable) {
+                    solution[variable] = Some(label);
+                    let cost = local_cost(solution, variable);
+                    if cost < best_cost {
+                        (best_label, best_cost) = (Some(label), cost);
+                    }
+                }
+                solution[variable] = best_label;
+                if best_label != current_label {
+                    total_improvement += current_cost - best_cost;
+                    is_changed = true;
+                }
+            }
+            if !is_changed {
+                break;
+            }
+        }
+
+        // An infinite running cost is recomputed, since shifting it by an infinite improvement would give NaN
+        solution.set_running_cost(running_cost.map(
+            |running_cost| match running_cost.is_finite() {
+                true => running_cost - total_improvement,
+                false => solution.cost(self),
+            },
+        ));
+        sweeps
+    }
+
     // Returns the number of factors in the cost function network
     pub fn factors_len(&self) -> usize {
         self.factors.len()
     }
+
+    // Returns the number of non-unary factors in the cost function network
+    pub fn num_non_unary_factors(&self) -> usize {
+        self.factors
+            .iter()
+            .filter(|factor| factor.arity() > 1)
+            .count()
+    }
+
+    // Returns the largest arity among all factors in the cost function network (0 if there are no factors)
+    pub fn max_arity(&self) -> usize {
+        self.factors
+            .iter()
+            .map(|factor| factor.arity())
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Returns the number of factors of each arity (arities without factors are omitted)
+    pub fn arity_histogram(&self) -> BTreeMap<usize, usize> {
+        let mut histogram = BTreeMap::new();
+        for factor in self.factors.iter() {
+            *histogram.entry(factor.arity()).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    // Estimates the memory (in bytes) taken by the function tables of all factors, as if every factor stored
+    // its complete table (structured factors, e.g., Potts, store much less); doubled, it also approximates
+    // the memory taken by messages, which helps to decide whether to attempt solving a large instance
+    pub fn total_table_memory(&self) -> usize {
+        self.factors
+            .iter()
+            .map(|factor| factor.function_table_len() * mem::size_of::<f64>())
+            .sum()
+    }
+
+    // Returns the largest function table length among all factors and variables in the cost function network,
+    // i.e., the size of a buffer that fits the reparametrization of any factor (0 if there are no variables)
+    pub fn max_function_table_len(&self) -> usize {
+        self.variables
+            .iter()
+            .map(|variable| variable.domain_size)
+            .chain(
+                self.factors
+                    .iter()
+                    .map(|factor| factor.function_table_len()),
+            )
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CostFunctionNetwork {
+    // Saves the cost function network to a file in a compact binary format
+    pub fn save_bincode(&self, path: PathBuf) -> io::Result<()> {
+        debug!("In save_bincode() for file {:?}", path);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        bincode::serialize_into(io::BufWriter::new(file), self).map_err(io::Error::other)
+    }
+
+    // Loads a cost function network from a file in a compact binary format (see save_bincode())
+    pub fn load_bincode(path: PathBuf) -> io::Result<Self> {
+        debug!("In load_bincode() for file {:?}", path);
+
+        let file = OpenOptions::new().read(true).open(path)?;
+        bincode::deserialize_from(io::BufReader::new(file))
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+// Returns the first variable that has an empty domain (None if all given domain sizes are nonzero)
+fn find_zero_domain_size(domain_sizes: &[usize]) -> Option<usize> {
+    domain_sizes
+        .iter()
+        .position(|domain_size| *domain_size == 0)
+}
+
+// Checks that all given domain sizes are nonzero, panicking with the first variable that has an empty domain
+fn check_domain_sizes(domain_sizes: &[usize]) {
+    if let Some(variable) = find_zero_domain_size(domain_sizes) {
+        panic!("Variable {} has domain size 0.", variable);
+    }
 }
 
 impl UAI for CostFunctionNetwork {
-    fn read_uai(path: PathBuf, lg: bool) -> Self {
+    fn read_uai(path: PathBuf, lg: bool) -> Result<Self, UaiParseError> {
         debug!("In read_uai() for file {:?} with lg option {}", path, lg);
+        Self::read_uai_from(open_uai(path)?, lg)
+    }
 
-        let file = OpenOptions::new().read(true).open(path).unwrap();
-
+    // Functions with identical scopes are summed into a single factor
+    fn read_uai_from<R: BufRead>(reader: R, lg: bool) -> Result<Self, UaiParseError> {
         let mut state = UAIState::ModelType;
 
-        let lines = BufReader::new(file).lines();
+        let lines = reader.lines();
         let mut trimmed_line;
 
         // Flip signs for UAI, exponentiate and flip signs for LG
@@ -244,125 +922,217 @@ impl UAI for CostFunctionNetwork {
 
         let mut num_variables = 0;
         let mut domain_sizes = Vec::new();
-        let mut function_scopes = Vec::new();
+        let mut num_functions = 0;
+        // Scopes are listed before all function tables, so they are stored until the corresponding table is read,
+        // at which point each scope is moved into its factor (and each table is moved into its factor once read)
+        let mut function_scopes: Vec<Vec<usize>> = Vec::new();
         let mut function_entries = Vec::new();
+        // Indices of non-unary factors by the hashes of their scopes (storing hashes instead of copies of scopes
+        // saves memory; on hash collisions, factors are found by scanning all factors)
+        let mut non_unary_factor_indices: HashMap<u64, usize> = HashMap::new();
+        let scope_hasher = RandomState::new();
 
         for line in lines {
-            let line = line.unwrap();
-            trimmed_line = line.trim();
+            let line = line?;
+            // Function tables don't have to end at line boundaries (values of several functions may share a line,
+            // and a table may span many lines), so table sizes and values are processed one token at a time
+            let is_table_section = matches!(
+                state,
+                UAIState::NumberOfTableValues(_) | UAIState::TableValues(_, _, _)
+            );
+            let items: Box<dyn Iterator<Item = &str>> = match is_table_section {
+                true => Box::new(line.split_whitespace()),
+                false => Box::new(iter::once(line.trim())),
+            };
 
-            if trimmed_line.is_empty() {
-                continue;
-            }
+            for item in items {
+                trimmed_line = item;
 
-            match state {
-                UAIState::ModelType => {
-                    debug!("Reading model type");
-                    if trimmed_line != "MARKOV" {
-                        unimplemented!("Only MARKOV graph type is supported.");
-                    }
-                    state = UAIState::NumberOfVariables;
-                }
-                UAIState::NumberOfVariables => {
-                    debug!("Reading number of variables");
-                    num_variables = trimmed_line.parse::<usize>().unwrap();
-                    state = UAIState::DomainSizes;
-                }
-                UAIState::DomainSizes => {
-                    debug!("Reading domain sizes");
-                    domain_sizes = string_to_vec(trimmed_line);
-                    assert_eq!(num_variables, domain_sizes.len());
-                    state = UAIState::NumberOfFunctions;
-                }
-                UAIState::NumberOfFunctions => {
-                    debug!("Reading number of functions");
-                    let num_functions = trimmed_line.parse::<usize>().unwrap();
-                    let capacity_non_unary = if num_functions > num_variables {
-                        num_functions - num_variables
-                    } else {
-                        0
-                    };
-                    cfn = CostFunctionNetwork::from_domain_sizes(
-                        &domain_sizes,
-                        true,
-                        capacity_non_unary,
-                    );
-                    function_scopes = Vec::with_capacity(num_functions);
-                    state = UAIState::FunctionScopes(0);
+                if trimmed_line.is_empty() {
+                    continue;
                 }
-                UAIState::FunctionScopes(function_idx) => {
-                    debug!("Reading scope of function {}", function_idx);
-                    let function_desc = string_to_vec(trimmed_line);
-                    let (scope_len, function_scope) = function_desc.split_at(1);
-                    assert_eq!(scope_len[0], function_scope.len());
-                    function_scopes.push(function_scope.to_vec());
-                    state = if function_idx + 1 < function_scopes.capacity() {
-                        UAIState::FunctionScopes(function_idx + 1)
-                    } else {
-                        UAIState::NumberOfTableValues(0)
-                    };
-                }
-                UAIState::NumberOfTableValues(function_idx) => {
-                    debug!("Reading function table size of function {}", function_idx);
-                    assert!(function_idx < function_scopes.len());
-                    let num_entries = trimmed_line.parse::<usize>().unwrap();
-                    function_entries = Vec::with_capacity(num_entries);
-                    state = UAIState::TableValues(function_idx, 0, num_entries);
-                }
-                UAIState::TableValues(function_idx, cur_entries, num_entries) => {
-                    assert!(
-                        function_idx < function_scopes.len(),
-                        "Function index out of bounds."
-                    );
-                    let mut new_entries = string_to_vec(trimmed_line);
-                    let new_cur_entries = cur_entries + new_entries.len();
-                    function_entries.append(&mut new_entries);
-
-                    assert!(new_cur_entries <= num_entries, "Too many entries.");
-                    if new_cur_entries < num_entries {
+
+                match state {
+                    UAIState::ModelType => {
+                        debug!("Reading model type");
+                        if trimmed_line != "MARKOV" {
+                            return Err(UaiParseError::UnsupportedModelType(
+                                trimmed_line.to_string(),
+                            ));
+                        }
+                        state = UAIState::NumberOfVariables;
+                    }
+                    UAIState::NumberOfVariables => {
+                        debug!("Reading number of variables");
+                        num_variables = parse_token(trimmed_line)?;
+                        state = UAIState::DomainSizes;
+                    }
+                    UAIState::DomainSizes => {
+                        debug!("Reading domain sizes");
+                        domain_sizes = parse_tokens(trimmed_line)?;
+                        if domain_sizes.len() != num_variables {
+                            return Err(UaiParseError::NumberOfDomainSizes(
+                                num_variables,
+                                domain_sizes.len(),
+                            ));
+                        }
+                        if let Some(variable) = find_zero_domain_size(&domain_sizes) {
+                            return Err(UaiParseError::ZeroDomainSize(variable));
+                        }
+                        state = UAIState::NumberOfFunctions;
+                    }
+                    UAIState::NumberOfFunctions => {
+                        debug!("Reading number of functions");
+                        num_functions = parse_token::<usize>(trimmed_line)?;
+                        let capacity_non_unary = num_functions.saturating_sub(num_variables);
+                        cfn = CostFunctionNetwork::from_domain_sizes(
+                            &domain_sizes,
+                            true,
+                            capacity_non_unary,
+                        );
+                        function_scopes = Vec::with_capacity(num_functions);
+                        state = match num_functions {
+                            0 => UAIState::EndOfFile,
+                            _ => UAIState::FunctionScopes(0),
+                        };
+                    }
+                    UAIState::FunctionScopes(function_idx) => {
+                        debug!("Reading scope of function {}", function_idx);
+                        let function_desc = parse_tokens::<usize>(trimmed_line)?;
+                        let (scope_len, function_scope) = function_desc.split_at(1);
+                        if scope_len[0] != function_scope.len() {
+                            return Err(UaiParseError::ScopeLength(function_idx));
+                        }
+                        if let Some(variable) = function_scope
+                            .iter()
+                            .find(|variable| **variable >= num_variables)
+                        {
+                            return Err(UaiParseError::VariableOutOfBounds(
+                                function_idx,
+                                *variable,
+                            ));
+                        }
+                        if let Some(variable) = find_duplicate_variable(function_scope) {
+                            return Err(UaiParseError::DuplicateVariable(function_idx, variable));
+                        }
+                        function_scopes.push(function_scope.to_vec());
+                        state = if function_idx + 1 < num_functions {
+                            UAIState::FunctionScopes(function_idx + 1)
+                        } else {
+                            UAIState::NumberOfTableValues(0)
+                        };
+                    }
+                    UAIState::NumberOfTableValues(function_idx) => {
+                        debug!("Reading function table size of function {}", function_idx);
+                        let num_entries = parse_token(trimmed_line)?;
+                        let expected_entries = function_scopes[function_idx]
+                            .iter()
+                            .try_fold(1usize, |size, variable| {
+                                size.checked_mul(domain_sizes[*variable])
+                            });
+                        if expected_entries != Some(num_entries) {
+                            return Err(UaiParseError::TableSize(
+                                function_idx,
+                                expected_entries.unwrap_or(usize::MAX),
+                                num_entries,
+                            ));
+                        }
+                        function_entries =
+                            Vec::with_capacity(num_entries.min(MAX_RESERVED_TABLE_ENTRIES));
+                        state = UAIState::TableValues(function_idx, 0, num_entries);
+                    }
+                    UAIState::TableValues(function_idx, cur_entries, num_entries) => {
+                        function_entries.push(parse_token(trimmed_line)?);
+                        if cur_entries + 1 < num_entries {
+                            state =
+                                UAIState::TableValues(function_idx, cur_entries + 1, num_entries);
+                            continue;
+                        }
                         debug!(
-                            "Reading function {}. Collected {} out of {} entries.",
-                            function_idx, cur_entries, num_entries
+                            "Reading function {}. Collected all {} entries.",
+                            function_idx, num_entries
+                        );
+
+                        // Move values into a separate vector
+                        let mut function_table = Vec::new();
+                        mem::swap(&mut function_entries, &mut function_table);
+
+                        // Apply mapping (flip signs for UAI, exponentiate and flip signs for LG)
+                        function_table.iter_mut().for_each(mapping);
+
+                        // Check that the values are valid
+                        if function_table.iter().any(|value| value.is_nan()) {
+                            return Err(UaiParseError::NaN(function_idx));
+                        }
+                        if lg && function_table.iter().any(|value| !value.is_finite()) {
+                            return Err(UaiParseError::Overflow(function_idx));
+                        }
+
+                        // Move the scope out, as it is not needed after this function is read,
+                        // and sort its variables, as required by factors
+                        let (function_scope, function_table) = sort_scope(
+                            mem::take(&mut function_scopes[function_idx]),
+                            function_table,
+                            &domain_sizes,
                         );
-                        state = UAIState::TableValues(function_idx, new_cur_entries, num_entries);
-                        continue;
+                        if function_scope.is_empty() {
+                            // Add nullary function (with a single entry) to the constant term
+                            cfn.add_constant(function_table[0]);
+                        } else {
+                            let scope_hash = scope_hasher.hash_one(&function_scope);
+                            let existing_factor_index = match function_scope.len() {
+                                1 => cfn.variables[function_scope[0]].factor_index,
+                                _ => match non_unary_factor_indices.get(&scope_hash) {
+                                    Some(factor_index)
+                                        if cfn.factors[*factor_index].variables()
+                                            == &function_scope =>
+                                    {
+                                        Some(*factor_index)
+                                    }
+                                    Some(_) => cfn.factor_index_by_scope(&function_scope),
+                                    None => None,
+                                },
+                            };
+                            if let Some(factor_index) = existing_factor_index {
+                                // Add function table to the factor with the same scope
+                                cfn.add_to_factor(factor_index, function_table.into_iter());
+                            } else {
+                                // Create factor from function table and add it to the cost function network
+                                if function_scope.len() > 1 {
+                                    non_unary_factor_indices
+                                        .entry(scope_hash)
+                                        .or_insert(cfn.factors_len());
+                                }
+                                let factor = FactorType::FunctionTable(FunctionTable::new(
+                                    &cfn,
+                                    function_scope,
+                                    function_table,
+                                ));
+                                cfn.add_factor(factor);
+                            }
+                        }
+
+                        // Proceed to the next function
+                        state = if function_idx + 1 < function_scopes.len() {
+                            UAIState::NumberOfTableValues(function_idx + 1)
+                        } else {
+                            UAIState::EndOfFile
+                        };
+                    }
+                    UAIState::EndOfFile => {
+                        warn!("Ignored trailing data at the end of file: {}", trimmed_line);
                     }
-                    debug!(
-                        "Reading function {}. Collected all {} entries.",
-                        function_idx, num_entries
-                    );
-
-                    // Move values into a separate vector
-                    let mut function_table = Vec::new();
-                    mem::swap(&mut function_entries, &mut function_table);
-
-                    // Apply mapping (flip signs for UAI, exponentiate and flip signs for LG)
-                    function_table.iter_mut().for_each(|value| mapping(value));
-
-                    // Create factor from function table and add it to the cost function network
-                    let factor = FactorType::FunctionTable(FunctionTable::new(
-                        &cfn,
-                        function_scopes[function_idx].to_vec(),
-                        function_table,
-                    ));
-                    cfn.add_factor(factor);
-
-                    // Proceed to the next function
-                    state = if function_idx + 1 < function_scopes.len() {
-                        UAIState::NumberOfTableValues(function_idx + 1)
-                    } else {
-                        UAIState::EndOfFile
-                    };
-                }
-                UAIState::EndOfFile => {
-                    warn!("Ignored trailing line at the end of file: {}", line);
                 }
             }
         }
 
+        if !matches!(state, UAIState::EndOfFile) {
+            return Err(UaiParseError::UnexpectedEndOfFile);
+        }
+
         debug!("UAI import complete.");
 
-        cfn
+        Ok(cfn)
     }
 
     fn write_uai(&self, path: PathBuf, lg: bool) -> io::Result<()> {
@@ -371,8 +1141,8 @@ impl UAI for CostFunctionNetwork {
         let mut file = OpenOptions::new()
             .create(true)
             .write(true)
-            .open("problem_instances/output.uai")
-            .unwrap();
+            .truncate(true)
+            .open(path)?;
 
         let time_start = Instant::now();
         let mapping = [|value: &f64| -*value, |value: &f64| (-value).ln()][lg as usize];
@@ -383,34 +1153,1085 @@ impl UAI for CostFunctionNetwork {
         let domain_sizes: Vec<usize> = (0..num_variables)
             .map(|var| self.domain_size(var))
             .collect();
-        write!(
+        writeln!(
             file,
-            "{}\n{}\n{}\n",
+            "{}\n{}\n{}",
             graph_type,
             num_variables,
             vec_to_string(&domain_sizes)
         )?;
 
+        // The constant term (if any) is written as an additional function with empty scope
+        let has_constant = self.constant != 0.;
+
         debug!("Writing number of functions");
-        write!(file, "{}\n", self.factors_len())?;
+        writeln!(file, "{}", self.factors_len() + has_constant as usize)?;
 
         debug!("Writing function scopes");
         for factor in &self.factors {
-            write!(
+            writeln!(
                 file,
-                "{} {}\n",
+                "{} {}",
                 factor.arity(),
                 vec_to_string(factor.variables())
             )?;
         }
+        if has_constant {
+            writeln!(file, "0")?;
+        }
 
+        // Specialized factors are expanded to complete function tables
         debug!("Writing function tables");
         for factor in self.factors.iter() {
             factor.write_uai(&mut file, mapping)?;
         }
+        if has_constant {
+            writeln!(file, "\n1\n{}", mapping(&self.constant))?;
+        }
 
         let elapsed_time = time_start.elapsed();
         debug!("UAI export complete. Elapsed time {:?}.", elapsed_time);
         Ok(())
     }
 }
+
+impl TryFrom<&str> for CostFunctionNetwork {
+    type Error = UaiParseError;
+
+    /// Parses a model in the UAI format (not LG) from a string.
+    /// Malformed models are reported as errors, as in `read_uai()`.
+    ///
+    /// ```
+    /// use mrf_map::cfn::cost_function_network::CostFunctionNetwork;
+    ///
+    /// let cfn = CostFunctionNetwork::try_from(
+    ///     "MARKOV
+    ///      2
+    ///      2 3
+    ///      2
+    ///      1 0
+    ///      2 0 1
+    ///      2
+    ///      0.4 0.6
+    ///      6
+    ///      1 2 3 4 5 6",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(cfn.num_variables(), 2);
+    /// assert_eq!(cfn.factors_len(), 2);
+    /// ```
+    fn try_from(uai_str: &str) -> Result<Self, Self::Error> {
+        Self::read_uai_from(uai_str.as_bytes(), false)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::{
+        alg::{
+            exact::brute_force_min,
+            solver::{Solver, SolverOptions},
+            srmp::SRMP,
+        },
+        cfn::relaxation::{ConstructRelaxation, Relaxation},
+    };
+
+    use super::*;
+
+    pub(crate) fn construct_cfn_example_1() -> CostFunctionNetwork {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 4, 5], false, 3);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0],
+            vec![1., 2., 3.],
+        )));
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![2],
+            vec![11., 12., 13., 14., 15.],
+        )));
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            vec![4.; 3 * 4],
+        )));
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 2],
+            vec![5.; 3 * 5],
+        )));
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![1, 2],
+            vec![6.; 4 * 5],
+        )));
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1, 2],
+            vec![7.; 3 * 4 * 5],
+        )));
+        cfn
+    }
+
+    #[test]
+    fn num_non_unary_factors_and_max_arity() {
+        let cfn = construct_cfn_example_1();
+        assert_eq!(cfn.num_non_unary_factors(), 4);
+        assert_eq!(cfn.max_arity(), 3);
+        assert_eq!(CostFunctionNetwork::new().max_arity(), 0);
+    }
+
+    // Constructs a network with 6 binary variables and factors with scopes [0, 2, 3, 5], [2, 5], and [1, 2]
+    fn construct_cfn_interleaved_scopes() -> CostFunctionNetwork {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2; 6], false, 3);
+        for scope in [vec![0, 2, 3, 5], vec![2, 5], vec![1, 2]] {
+            let table = vec![0.; 1 << scope.len()];
+            cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn, scope, table,
+            )));
+        }
+        cfn
+    }
+
+    #[test]
+    fn get_variables_difference() {
+        let cfn = construct_cfn_interleaved_scopes();
+        let alpha = FactorOrigin::NonUnaryFactor(0);
+        assert_eq!(
+            cfn.get_variables_difference(&alpha, &FactorOrigin::NonUnaryFactor(1)),
+            vec![0, 3]
+        );
+        for (variable, difference) in [
+            (0, vec![2, 3, 5]),
+            (2, vec![0, 3, 5]),
+            (3, vec![0, 2, 5]),
+            (5, vec![0, 2, 3]),
+        ] {
+            assert_eq!(
+                cfn.get_variables_difference(&alpha, &FactorOrigin::Variable(variable)),
+                difference
+            );
+        }
+        assert_eq!(
+            cfn.get_variables_difference(&alpha, &alpha),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Scope [1, 2] is not contained in scope [0, 2, 3, 5].")]
+    fn get_variables_difference_not_contained() {
+        let cfn = construct_cfn_interleaved_scopes();
+        cfn.get_variables_difference(
+            &FactorOrigin::NonUnaryFactor(0),
+            &FactorOrigin::NonUnaryFactor(2),
+        );
+    }
+
+    #[test]
+    fn clone_independent() {
+        let cfn = construct_cfn_example_1();
+        let solution = Solution::from(vec![Some(2), Some(1), Some(3)]);
+        let cost = solution.cost(&cfn);
+
+        let mut cloned = cfn.clone();
+        assert_eq!(solution.cost(&cloned), cost);
+        cloned.scale(2.);
+        cloned.update_unary_factor(1, vec![1., 1., 1., 1.]);
+        cloned.add_constant(5.);
+        assert_eq!(solution.cost(&cloned), 2. * cost + 1. + 5.);
+
+        assert_eq!(solution.cost(&cfn), cost);
+        assert_eq!(cfn.unary_costs(1), None);
+        assert_eq!(cfn.constant(), 0.);
+    }
+
+    #[test]
+    fn iter_factors_with_origin() {
+        for cfn in [
+            construct_cfn_example_1(),
+            CostFunctionNetwork::random_grid(3, 4, 2),
+        ] {
+            let num_variable_origins = cfn
+                .iter_factors_with_origin()
+                .filter(|(factor_origin, _factor)| {
+                    matches!(factor_origin, FactorOrigin::Variable(_))
+                })
+                .count();
+            let num_unary_factors = (0..cfn.num_variables())
+                .filter(|variable| cfn.get_factor(&FactorOrigin::Variable(*variable)).is_some())
+                .count();
+            assert_eq!(num_variable_origins, num_unary_factors);
+
+            // Every origin refers to the factor it is paired with
+            assert_eq!(cfn.iter_factors_with_origin().count(), cfn.factors_len());
+            for (factor_origin, factor) in cfn.iter_factors_with_origin() {
+                assert!(std::ptr::eq(
+                    cfn.get_factor(&factor_origin).unwrap(),
+                    factor
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn incident_factors() {
+        let cfn = construct_cfn_example_1();
+        let scopes_and_positions = |variable| {
+            cfn.incident_factors(variable)
+                .into_iter()
+                .map(|(factor, position)| (factor.variables().clone(), position))
+                .collect::<Vec<_>>()
+        };
+
+        // Variable 1 has no unary factor
+        assert_eq!(
+            scopes_and_positions(1),
+            vec![(vec![0, 1], 1), (vec![1, 2], 0), (vec![0, 1, 2], 1)]
+        );
+        assert_eq!(
+            scopes_and_positions(2),
+            vec![
+                (vec![2], 0),
+                (vec![0, 2], 1),
+                (vec![1, 2], 1),
+                (vec![0, 1, 2], 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn icm() {
+        // Deliberately suboptimal labeling: variable 0 takes its most expensive label
+        let cfn = construct_cfn_example_1();
+        let mut solution = Solution::from(vec![Some(2), Some(0), Some(0)]);
+        let cost_before = solution.cost(&cfn);
+        let sweeps = cfn.icm(&mut solution, 10);
+        assert!(solution.cost(&cfn) < cost_before);
+        assert_eq!(solution[0], Some(0));
+        // The last sweep doesn't change anything
+        assert_eq!(sweeps, 2);
+
+        // The cost never increases, in particular for optimal labelings
+        let (optimal_cost, mut solution) = brute_force_min(&cfn);
+        cfn.icm(&mut solution, 10);
+        assert_eq!(solution.cost(&cfn), optimal_cost);
+
+        let cfn = CostFunctionNetwork::random_grid(4, 4, 3);
+        for start_label in 0..3 {
+            let mut solution = Solution::from(vec![Some(start_label); cfn.num_variables()]);
+            let mut previous_cost = solution.cost(&cfn);
+            for _ in 0..5 {
+                cfn.icm(&mut solution, 1);
+                let cost = solution.cost(&cfn);
+                assert!(cost <= previous_cost);
+                previous_cost = cost;
+            }
+        }
+
+        // The running cost (if tracked) is kept up to date
+        let mut solution = Solution::with_running_cost(&cfn);
+        for variable in 0..cfn.num_variables() {
+            solution[variable] = Some(0);
+        }
+        let variable_factors = cfn.variable_factors();
+        solution.update_running_cost(
+            &cfn,
+            &variable_factors,
+            &(0..cfn.num_variables()).collect::<Vec<_>>(),
+        );
+        cfn.icm(&mut solution, 10);
+        assert!((solution.running_cost().unwrap() - solution.cost(&cfn)).abs() < 1e-9);
+
+        // An infinite running cost doesn't turn into NaN when ICM leaves the infeasible labeling
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 2], false, 0);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            vec![f64::INFINITY, 1., 2., 3.],
+        )));
+        let mut solution = Solution::with_running_cost(&cfn);
+        solution[0] = Some(0);
+        solution[1] = Some(0);
+        solution.update_running_cost(&cfn, &cfn.variable_factors(), &[0, 1]);
+        assert_eq!(solution.running_cost(), Some(f64::INFINITY));
+        cfn.icm(&mut solution, 10);
+        assert!(solution.running_cost().unwrap().is_finite());
+        assert_eq!(solution.running_cost(), Some(solution.cost(&cfn)));
+    }
+
+    #[test]
+    fn unary_costs() {
+        let mut cfn = construct_cfn_example_1();
+        assert_eq!(cfn.unary_costs(0), Some([1., 2., 3.].as_slice()));
+        assert_eq!(cfn.unary_costs(1), None);
+
+        // Values are returned as costs to be minimized
+        cfn.set_sense(Sense::Maximize);
+        assert_eq!(cfn.unary_costs(0), Some([-1., -2., -3.].as_slice()));
+    }
+
+    #[test]
+    fn arity_histogram() {
+        let cfn = construct_cfn_example_1();
+        assert_eq!(
+            cfn.arity_histogram(),
+            BTreeMap::from([(1, 2), (2, 3), (3, 1)])
+        );
+        assert!(CostFunctionNetwork::new().arity_histogram().is_empty());
+    }
+
+    #[test]
+    fn labelings() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 3], false, 1);
+        cfn.add_factor(FactorType::Potts(Potts::new(&cfn, vec![0, 1], 1.)));
+
+        let labelings = cfn
+            .labelings(&FactorOrigin::NonUnaryFactor(0))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labelings,
+            vec![
+                vec![0, 0],
+                vec![0, 1],
+                vec![0, 2],
+                vec![1, 0],
+                vec![1, 1],
+                vec![1, 2]
+            ]
+        );
+
+        // The order matches the function table layout
+        let factor = cfn.get_factor(&FactorOrigin::NonUnaryFactor(0)).unwrap();
+        for (labeling, value) in labelings.iter().zip(factor.entries()) {
+            assert_eq!(factor.value_at(&cfn, labeling), value);
+        }
+
+        assert_eq!(
+            cfn.labelings(&FactorOrigin::Variable(1))
+                .collect::<Vec<_>>(),
+            vec![vec![0], vec![1], vec![2]]
+        );
+    }
+
+    #[test]
+    fn add_potts_factor() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 3], false, 1);
+        cfn.add_potts_factor(0, 1, 2.5);
+
+        let table = cfn
+            .get_factor(&FactorOrigin::NonUnaryFactor(0))
+            .unwrap()
+            .clone_function_table();
+        for (labeling, value) in cfn.labelings(&FactorOrigin::NonUnaryFactor(0)).zip(table) {
+            let expected_value = if labeling[0] == labeling[1] { 0. } else { 2.5 };
+            assert_eq!(value, expected_value);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Variables 0 and 1 of a Potts factor have different domain sizes.")]
+    fn add_potts_factor_domain_sizes() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 3], false, 1);
+        cfn.add_potts_factor(0, 1, 1.);
+    }
+
+    #[test]
+    fn add_hard_constraint() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 3], false, 1);
+        cfn.set_sense(Sense::Maximize);
+        cfn.add_hard_constraint(vec![0, 1], |labeling| labeling[0] < labeling[1], 100.);
+
+        // The hard constraint value is a cost regardless of the sense
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::NonUnaryFactor(0))
+                .unwrap()
+                .clone_function_table(),
+            vec![100., 0., 0., 100., 100., 0.]
+        );
+    }
+
+    #[test]
+    fn hard_constraint_set_sense() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 3], true, 1);
+        cfn.add_hard_constraint(
+            vec![0, 1],
+            |labeling| labeling[0] < labeling[1],
+            f64::INFINITY,
+        );
+        cfn.set_sense(Sense::Maximize);
+        // Without the constraint, labeling (1, 0) would have the best score 8
+        cfn.update_unary_factor(0, vec![0., 5.]);
+        cfn.update_unary_factor(1, vec![3., 0., 0.]);
+
+        // The hard constraint still forbids labelings after the sense changes
+        assert!(cfn.is_hard_constraint(0));
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::NonUnaryFactor(0))
+                .unwrap()
+                .clone_function_table(),
+            vec![f64::INFINITY, 0., 0., f64::INFINITY, f64::INFINITY, 0.]
+        );
+        assert_eq!(cfn.cost_to_objective(brute_force_min(&cfn).0), 5.);
+
+        let relaxation = Relaxation::new(&cfn);
+        let result = SRMP::solve(&relaxation, &SolverOptions::default()).unwrap();
+        assert_eq!(result.labeling(), &vec![Some(1), Some(2)]);
+        assert_eq!(result.best_cost(), Some(5.));
+
+        // The agreement constraints of the pairwise decomposition are hard constraints as well
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2; 3], false, 1);
+        cfn.add_hard_constraint(
+            vec![0, 1, 2],
+            |labeling| labeling.iter().sum::<usize>() == 1,
+            f64::INFINITY,
+        );
+        let pairwise = cfn.to_pairwise();
+        assert!((0..pairwise.factors_len())
+            .all(|factor_index| pairwise.is_hard_constraint(factor_index)));
+    }
+
+    // Constructs a network with two variables and a hard constraint that forbids equal labels
+    fn construct_cfn_hard_constraint() -> CostFunctionNetwork {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 2], false, 1);
+        cfn.add_hard_constraint(
+            vec![0, 1],
+            |labeling| labeling[0] != labeling[1],
+            f64::INFINITY,
+        );
+        cfn
+    }
+
+    #[test]
+    fn add_assign_hard_constraint() {
+        // A hard constraint inserted as a new factor stays a hard constraint
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 2], false, 0);
+        cfn.add_assign(&construct_cfn_hard_constraint());
+        assert!(cfn.is_hard_constraint(0));
+        cfn.set_sense(Sense::Maximize);
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::NonUnaryFactor(0))
+                .unwrap()
+                .clone_function_table(),
+            vec![f64::INFINITY, 0., 0., f64::INFINITY]
+        );
+    }
+
+    #[test]
+    fn add_to_factor_hard_constraint() {
+        let mut soft = CostFunctionNetwork::from_domain_sizes(&vec![2, 2], false, 1);
+        soft.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &soft,
+            vec![0, 1],
+            vec![1., 2., 3., 4.],
+        )));
+
+        // Summing a hard constraint into a soft factor gives a hard constraint
+        let mut cfn = soft.clone();
+        cfn.add_assign(&construct_cfn_hard_constraint());
+        assert!(cfn.is_hard_constraint(0));
+
+        // Summing soft costs into a hard constraint keeps it a hard constraint
+        let mut cfn = construct_cfn_hard_constraint();
+        cfn.add_assign(&soft);
+        assert!(cfn.is_hard_constraint(0));
+        cfn.set_sense(Sense::Maximize);
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::NonUnaryFactor(0))
+                .unwrap()
+                .clone_function_table(),
+            vec![f64::INFINITY, 2., 3., f64::INFINITY]
+        );
+    }
+
+    #[test]
+    fn scale_hard_constraint() {
+        let mut cfn = construct_cfn_hard_constraint();
+        cfn.update_unary_factor(0, vec![1., 2.]);
+
+        // Hard constraints are skipped for negative multipliers, soft factors are scaled
+        cfn.scale(-2.);
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::NonUnaryFactor(0))
+                .unwrap()
+                .clone_function_table(),
+            vec![f64::INFINITY, 0., 0., f64::INFINITY]
+        );
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::Variable(0))
+                .unwrap()
+                .clone_function_table(),
+            vec![-2., -4.]
+        );
+    }
+
+    #[test]
+    fn product_domain_sizes() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 4, 5], false, 0);
+        assert_eq!(cfn.product_domain_sizes(&vec![0, 2]), 15);
+        assert_eq!(cfn.product_domain_sizes(&vec![0, 1, 2]), 60);
+    }
+
+    #[test]
+    #[should_panic(expected = "Product of domain sizes of variables [0, 1, 2] overflows usize.")]
+    fn product_domain_sizes_overflow() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, usize::MAX / 2, 3], false, 0);
+        cfn.product_domain_sizes(&vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn max_function_table_len() {
+        let cfn = construct_cfn_example_1();
+        let largest_factor_len = cfn
+            .factors_iter()
+            .map(|factor| factor.function_table_len())
+            .max()
+            .unwrap();
+        assert_eq!(largest_factor_len, 3 * 4 * 5);
+        assert_eq!(cfn.max_function_table_len(), largest_factor_len);
+        assert_eq!(CostFunctionNetwork::new().max_function_table_len(), 0);
+    }
+
+    #[test]
+    fn cost_breakdown() {
+        let mut cfn = CostFunctionNetwork::random_grid(3, 4, 3);
+        cfn.add_constant(2.5);
+        let mut solution = Solution::new(&cfn);
+        for variable in 0..cfn.num_variables() {
+            solution[variable] = Some((variable * 7) % 3);
+        }
+
+        let breakdown = cfn.cost_breakdown(&solution);
+        assert_eq!(breakdown.len(), cfn.factors_len() + 1);
+        assert!(breakdown[0].0.is_none());
+        assert_eq!(breakdown[0].1, 2.5);
+        let total = breakdown
+            .iter()
+            .map(|(_factor_origin, cost)| cost)
+            .sum::<f64>();
+        assert!((total - solution.cost(&cfn)).abs() < 1e-9);
+
+        for (factor_origin, cost) in breakdown.iter() {
+            if let Some(FactorOrigin::Variable(variable)) = factor_origin {
+                let table = cfn
+                    .get_factor(&FactorOrigin::Variable(*variable))
+                    .unwrap()
+                    .clone_function_table();
+                assert_eq!(*cost, table[solution[*variable].unwrap()]);
+            }
+        }
+
+        // Contributions are given in the sense of the network, so they don't change when the values
+        // are reinterpreted as scores to be maximized (even though they are negated internally)
+        cfn.set_sense(Sense::Maximize);
+        let breakdown_maximize = cfn.cost_breakdown(&solution);
+        assert_eq!(breakdown_maximize[0].1, 2.5);
+        for ((_factor_origin, cost), (_factor_origin_maximize, cost_maximize)) in
+            breakdown.iter().zip(breakdown_maximize.iter())
+        {
+            assert_eq!(*cost, *cost_maximize);
+        }
+    }
+
+    #[test]
+    fn total_table_memory() {
+        let cfn = construct_cfn_example_1();
+        let total_len = cfn
+            .factors_iter()
+            .map(|factor| factor.function_table_len())
+            .sum::<usize>();
+        assert_eq!(total_len, 3 + 5 + 3 * 4 + 3 * 5 + 4 * 5 + 3 * 4 * 5);
+        assert_eq!(cfn.total_table_memory(), total_len * 8);
+        assert_eq!(CostFunctionNetwork::new().total_table_memory(), 0);
+    }
+
+    #[test]
+    fn update_unary_factor() {
+        let mut cfn = construct_cfn_example_1();
+        let original = construct_cfn_example_1();
+
+        cfn.update_unary_factor(0, vec![30., 20., 10.]);
+        cfn.update_unary_factor(1, vec![0., 1., 2., 3.]);
+
+        assert_eq!(cfn.factors_len(), original.factors_len() + 1);
+        assert!(cfn.has_same_scopes(&original));
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::Variable(0))
+                .unwrap()
+                .clone_function_table(),
+            vec![30., 20., 10.]
+        );
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::Variable(1))
+                .unwrap()
+                .clone_function_table(),
+            vec![0., 1., 2., 3.]
+        );
+    }
+
+    #[test]
+    fn add_assign() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 2], false, 1);
+        cfn.update_unary_factor(0, vec![1., 2., 3.]);
+
+        let mut other = CostFunctionNetwork::from_domain_sizes(&vec![3, 2], false, 1);
+        other.update_unary_factor(0, vec![10., 20., 30.]);
+        other.update_unary_factor(1, vec![5., 6.]);
+        other.add_factor(FactorType::Potts(Potts::new(&other, vec![0, 1], 1.5)));
+
+        cfn.add_assign(&other);
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::Variable(0))
+                .unwrap()
+                .clone_function_table(),
+            vec![11., 22., 33.]
+        );
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::Variable(1))
+                .unwrap()
+                .clone_function_table(),
+            vec![5., 6.]
+        );
+        assert_eq!(cfn.num_non_unary_factors(), 1);
+
+        // Adding the same pairwise factor again doubles it instead of inserting a new one
+        cfn.add_assign(&other);
+        assert_eq!(cfn.num_non_unary_factors(), 1);
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::NonUnaryFactor(2))
+                .unwrap()
+                .clone_function_table(),
+            vec![0., 3., 3., 0., 3., 3.]
+        );
+    }
+
+    #[test]
+    fn scale() {
+        let mut cfn = construct_cfn_example_1();
+        cfn.add_factor(FactorType::Potts(Potts::new(&cfn, vec![1, 2], 1.5)));
+        let solution = Solution::from(vec![Some(2), Some(1), Some(3)]);
+        let cost = solution.cost(&cfn);
+        assert!(cost > 0.);
+
+        cfn.scale(2.);
+        assert_eq!(solution.cost(&cfn), 2. * cost);
+    }
+
+    #[test]
+    fn set_sense() {
+        let mut cfn = construct_cfn_example_1();
+        cfn.add_constant(10.);
+        let entries = |cfn: &CostFunctionNetwork| {
+            cfn.factors_iter()
+                .flat_map(|factor| factor.entries().collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        let costs = entries(&cfn);
+        assert_eq!(cfn.sense(), Sense::Minimize);
+
+        cfn.set_sense(Sense::Maximize);
+        assert_eq!(cfn.sense(), Sense::Maximize);
+        assert_eq!(cfn.constant(), -10.);
+        assert_eq!(
+            entries(&cfn),
+            costs.iter().map(|cost| -cost).collect::<Vec<_>>()
+        );
+        assert_eq!(cfn.cost_to_objective(-3.), 3.);
+
+        cfn.add_constant(2.);
+        assert_eq!(cfn.constant(), -12.);
+
+        cfn.set_sense(Sense::Maximize);
+        assert_eq!(cfn.constant(), -12.);
+        cfn.set_sense(Sense::Minimize);
+        assert_eq!(cfn.constant(), 12.);
+        assert_eq!(entries(&cfn), costs);
+    }
+
+    #[test]
+    fn to_pairwise_triplet() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 3, 2], false, 1);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1, 2],
+            (0..12).map(|index| ((index * 7) % 12) as f64).collect(),
+        )));
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![1],
+            vec![0., 3., 1.],
+        )));
+        cfn.add_constant(2.);
+
+        let pairwise = cfn.to_pairwise();
+        assert_eq!(pairwise.max_arity(), 2);
+        assert_eq!(pairwise.num_variables(), 4);
+        assert_eq!(pairwise.domain_size(3), 12);
+
+        let (optimum, solution) = brute_force_min(&cfn);
+        let (pairwise_optimum, pairwise_solution) = brute_force_min(&pairwise);
+        assert_eq!(pairwise_optimum, optimum);
+        assert_eq!(
+            pairwise_solution.labels()[..cfn.num_variables()],
+            solution.labels()[..]
+        );
+    }
+
+    #[test]
+    fn add_constant() {
+        let mut cfn = construct_cfn_example_1();
+        let solutions = [
+            Solution::from(vec![Some(0), Some(0), Some(0)]),
+            Solution::from(vec![Some(2), Some(1), Some(3)]),
+            Solution::from(vec![Some(1), Some(3), Some(4)]),
+        ];
+        let costs: Vec<f64> = solutions
+            .iter()
+            .map(|solution| solution.cost(&cfn))
+            .collect();
+
+        cfn.add_constant(10.);
+        assert_eq!(cfn.constant(), 10.);
+        for (solution, cost) in solutions.iter().zip(costs) {
+            assert_eq!(solution.cost(&cfn), cost + 10.);
+        }
+    }
+
+    #[test]
+    fn read_uai_nullary() {
+        // Values are negated when reading UAI files, so the nullary function 3.5 becomes the constant -3.5
+        let cfn =
+            CostFunctionNetwork::read_uai("test_instances/nullary_constant.uai".into(), false)
+                .unwrap();
+        assert_eq!(cfn.constant(), -3.5);
+        assert_eq!(cfn.factors_len(), 2);
+        assert_eq!(cfn.num_non_unary_factors(), 1);
+    }
+
+    #[test]
+    fn read_uai_lg_overflow() {
+        let uai = "MARKOV\n2\n2 2\n2\n1 0\n2 0 1\n\n2\n0 1\n\n4\n0 1 1000 0\n";
+        let error = CostFunctionNetwork::read_uai_from(uai.as_bytes(), true)
+            .err()
+            .unwrap();
+        assert!(matches!(error, UaiParseError::Overflow(1)));
+        assert_eq!(
+            error.to_string(),
+            "Function 1 contains a value that overflows when exponentiated."
+        );
+    }
+
+    #[test]
+    fn read_uai_nan() {
+        let error = CostFunctionNetwork::try_from("MARKOV\n1\n2\n1\n1 0\n\n2\n0 nan\n")
+            .err()
+            .unwrap();
+        assert!(matches!(error, UaiParseError::NaN(0)));
+        assert_eq!(error.to_string(), "Function 0 contains NaN.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Variable 1 has domain size 0.")]
+    fn from_domain_sizes_zero() {
+        CostFunctionNetwork::from_domain_sizes(&vec![2, 0, 3], false, 0);
+    }
+
+    #[test]
+    fn read_uai_zero_domain_size() {
+        let error = CostFunctionNetwork::try_from("MARKOV\n3\n2 2 0\n1\n1 0\n\n2\n0 1\n")
+            .err()
+            .unwrap();
+        assert!(matches!(error, UaiParseError::ZeroDomainSize(2)));
+        assert_eq!(error.to_string(), "Variable 2 has domain size 0.");
+    }
+
+    #[test]
+    fn read_uai_unsorted_scope() {
+        // The same function with the scope listed in decreasing and in increasing order
+        let unsorted = CostFunctionNetwork::try_from(
+            "MARKOV\n3\n2 3 2\n1\n3 2 1 0\n\n12\n0 1 2 3 4 5 6 7 8 9 10 11\n",
+        )
+        .unwrap();
+        let sorted = CostFunctionNetwork::try_from(
+            "MARKOV\n3\n2 3 2\n1\n3 0 1 2\n\n12\n0 6 2 8 4 10 1 7 3 9 5 11\n",
+        )
+        .unwrap();
+        let factor = unsorted.factor_by_scope(&[0, 1, 2]).unwrap();
+        assert_eq!(
+            factor.clone_function_table(),
+            sorted
+                .factor_by_scope(&[0, 1, 2])
+                .unwrap()
+                .clone_function_table()
+        );
+    }
+
+    #[test]
+    fn read_uai_malformed() {
+        let read = |uai: &str| CostFunctionNetwork::try_from(uai).err().unwrap();
+        assert!(matches!(
+            read("BAYES\n1\n2\n0\n"),
+            UaiParseError::UnsupportedModelType(_)
+        ));
+        assert!(matches!(
+            read("MARKOV\nx\n"),
+            UaiParseError::InvalidToken(_)
+        ));
+        assert!(matches!(
+            read("MARKOV\n2\n2\n0\n"),
+            UaiParseError::NumberOfDomainSizes(2, 1)
+        ));
+        assert!(matches!(
+            read("MARKOV\n1\n2\n1\n2 0\n\n2\n0 1\n"),
+            UaiParseError::ScopeLength(0)
+        ));
+        assert!(matches!(
+            read("MARKOV\n1\n2\n1\n1 1\n\n2\n0 1\n"),
+            UaiParseError::VariableOutOfBounds(0, 1)
+        ));
+        assert!(matches!(
+            read("MARKOV\n2\n2 2\n1\n2 1 1\n\n4\n0 1 2 3\n"),
+            UaiParseError::DuplicateVariable(0, 1)
+        ));
+        assert!(matches!(
+            read("MARKOV\n1\n2\n1\n1 0\n\n3\n0 1 2\n"),
+            UaiParseError::TableSize(0, 2, 3)
+        ));
+        assert!(matches!(
+            read("MARKOV\n5\n65536 65536 65536 65536 65536\n1\n5 0 1 2 3 4\n\n0\n"),
+            UaiParseError::TableSize(0, usize::MAX, 0)
+        ));
+        assert!(matches!(
+            read("MARKOV\n3\n65536 65536 65536\n1\n3 0 1 2\n\n281474976710656\n0 1 2\n"),
+            UaiParseError::UnexpectedEndOfFile
+        ));
+        assert!(matches!(
+            read("MARKOV\n1\n2\n1\n1 0\n\n2\n0\n"),
+            UaiParseError::UnexpectedEndOfFile
+        ));
+        assert!(matches!(
+            CostFunctionNetwork::read_uai("test_instances/nonexistent.uai".into(), false),
+            Err(UaiParseError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn factor_by_scope() {
+        let cfn = construct_cfn_example_1();
+
+        let factor = cfn.factor_by_scope(&[0, 1, 2]).unwrap();
+        assert_eq!(factor.variables(), &vec![0, 1, 2]);
+        assert_eq!(factor.clone_function_table(), vec![7.; 3 * 4 * 5]);
+        assert_eq!(
+            cfn.factor_by_scope(&[2]).unwrap().clone_function_table(),
+            vec![11., 12., 13., 14., 15.]
+        );
+
+        assert!(cfn.factor_by_scope(&[1]).is_none());
+        assert!(cfn.factor_by_scope(&[]).is_none());
+    }
+
+    #[test]
+    fn read_uai_duplicate_scopes() {
+        // Values are negated when reading UAI files
+        let cfn =
+            CostFunctionNetwork::read_uai("test_instances/duplicate_scopes.uai".into(), false)
+                .unwrap();
+        assert_eq!(cfn.factors_len(), 3);
+        assert_eq!(cfn.num_non_unary_factors(), 1);
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::NonUnaryFactor(1))
+                .unwrap()
+                .clone_function_table(),
+            vec![-11., -22., -33., -44.]
+        );
+        assert_eq!(
+            cfn.get_factor(&FactorOrigin::Variable(0))
+                .unwrap()
+                .clone_function_table(),
+            vec![-4., -6.]
+        );
+    }
+
+    #[test]
+    fn read_uai_auto() {
+        let contents = "MARKOV\n1\n2\n1\n1 0\n\n2\n0 1\n";
+        let path_lg = std::env::temp_dir().join("mrf_map_read_uai_auto.UAI.LG");
+        let path_uai = std::env::temp_dir().join("mrf_map_read_uai_auto.uai");
+        std::fs::write(&path_lg, contents).unwrap();
+        std::fs::write(&path_uai, contents).unwrap();
+        let cfn_lg = CostFunctionNetwork::read_uai_auto(path_lg.clone()).unwrap();
+        let cfn_uai = CostFunctionNetwork::read_uai_auto(path_uai.clone()).unwrap();
+        std::fs::remove_file(path_lg).unwrap();
+        std::fs::remove_file(path_uai).unwrap();
+
+        // Values are exponentiated and negated for LG files, and only negated otherwise
+        assert_eq!(
+            cfn_lg
+                .get_factor(&FactorOrigin::Variable(0))
+                .unwrap()
+                .clone_function_table(),
+            vec![-1., -(1f64.exp())]
+        );
+        assert_eq!(
+            cfn_uai
+                .get_factor(&FactorOrigin::Variable(0))
+                .unwrap()
+                .clone_function_table(),
+            vec![-0., -1.]
+        );
+    }
+
+    #[test]
+    fn read_uai_continuous_tables() {
+        // The same functions as in duplicate_scopes.uai, but table sizes and values run continuously across lines
+        let cfn =
+            CostFunctionNetwork::read_uai("test_instances/continuous_tables.uai".into(), false)
+                .unwrap();
+        let expected_cfn =
+            CostFunctionNetwork::read_uai("test_instances/duplicate_scopes.uai".into(), false)
+                .unwrap();
+
+        assert_eq!(cfn.factors_len(), expected_cfn.factors_len());
+        for (factor, expected_factor) in cfn.factors_iter().zip(expected_cfn.factors_iter()) {
+            assert_eq!(factor.variables(), expected_factor.variables());
+            assert_eq!(
+                factor.clone_function_table(),
+                expected_factor.clone_function_table()
+            );
+        }
+        assert_eq!(cfn.constant(), expected_cfn.constant());
+    }
+
+    #[test]
+    fn read_uai_unchanged() {
+        // Numbers of factors and costs of two labelings, as read by the previous (non-streaming) implementation
+        for (file, expected_factors_len, expected_costs) in [
+            ("frustrated_tangle.uai", 6, [6., 1.]),
+            ("example_1.uai", 6, [-34., -36.]),
+            ("duplicate_scopes.uai", 3, [-15., -26.]),
+        ] {
+            let cfn =
+                CostFunctionNetwork::read_uai(format!("test_instances/{}", file).into(), false)
+                    .unwrap();
+            let solution_zero = Solution::from(vec![Some(0); cfn.num_variables()]);
+            let solution_mod = Solution::from(
+                (0..cfn.num_variables())
+                    .map(|variable| Some(variable % cfn.domain_size(variable)))
+                    .collect::<Vec<_>>(),
+            );
+            assert_eq!(cfn.factors_len(), expected_factors_len, "{}", file);
+            assert_eq!(
+                [solution_zero.cost(&cfn), solution_mod.cost(&cfn)],
+                expected_costs,
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn write_uai_constant_round_trip() {
+        let mut cfn = construct_cfn_example_1();
+        cfn.add_constant(10.);
+
+        let path = std::env::temp_dir().join("mrf_map_write_uai_constant_round_trip.uai");
+        cfn.write_uai(path.clone(), false).unwrap();
+        let loaded = CostFunctionNetwork::read_uai(path.clone(), false).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.constant(), 10.);
+        assert_eq!(loaded.factors_len(), cfn.factors_len());
+        let solution = Solution::from(vec![Some(2), Some(1), Some(3)]);
+        assert_eq!(solution.cost(&loaded), solution.cost(&cfn));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn bincode_round_trip() {
+        let cfn = construct_cfn_example_1();
+        let path = std::env::temp_dir().join("mrf_map_bincode_round_trip.bin");
+        cfn.save_bincode(path.clone()).unwrap();
+        let loaded = CostFunctionNetwork::load_bincode(path.clone()).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.num_variables(), cfn.num_variables());
+        for variable in 0..cfn.num_variables() {
+            assert_eq!(loaded.domain_size(variable), cfn.domain_size(variable));
+        }
+        assert_eq!(loaded.factors_len(), cfn.factors_len());
+        for (loaded_factor, factor) in loaded.factors_iter().zip(cfn.factors_iter()) {
+            assert_eq!(loaded_factor.variables(), factor.variables());
+            assert_eq!(
+                loaded_factor.clone_function_table(),
+                factor.clone_function_table()
+            );
+        }
+    }
+
+    #[test]
+    fn write_uai_potts_round_trip() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 2], false, 2);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0],
+            vec![1., 0.5, 2.],
+        )));
+        cfn.add_factor(FactorType::Potts(Potts::new(&cfn, vec![0, 1], 2.5)));
+
+        let path = std::env::temp_dir().join("mrf_map_write_uai_potts_round_trip.uai");
+        cfn.write_uai(path.clone(), false).unwrap();
+        let loaded = CostFunctionNetwork::read_uai(path.clone(), false).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.factors_len(), cfn.factors_len());
+        for (loaded_factor, factor) in loaded.factors_iter().zip(cfn.factors_iter()) {
+            assert!(matches!(loaded_factor, FactorType::FunctionTable(_)));
+            assert_eq!(loaded_factor.variables(), factor.variables());
+            assert_eq!(
+                loaded_factor.clone_function_table(),
+                factor.clone_function_table()
+            );
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn read_uai_gzip() {
+        let cfn =
+            CostFunctionNetwork::read_uai("test_instances/frustrated_cycle_5.uai".into(), false)
+                .unwrap();
+        let cfn_gzip =
+            CostFunctionNetwork::read_uai("test_instances/frustrated_cycle_5.uai.gz".into(), false)
+                .unwrap();
+
+        assert_eq!(cfn_gzip.num_variables(), cfn.num_variables());
+        for variable in 0..cfn.num_variables() {
+            assert_eq!(cfn_gzip.domain_size(variable), cfn.domain_size(variable));
+        }
+        assert_eq!(cfn_gzip.factors_len(), cfn.factors_len());
+        for (gzip_factor, factor) in cfn_gzip.factors_iter().zip(cfn.factors_iter()) {
+            assert_eq!(gzip_factor.variables(), factor.variables());
+            assert_eq!(
+                gzip_factor.clone_function_table(),
+                factor.clone_function_table()
+            );
+        }
+    }
+
+    #[test]
+    fn min_marginal() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 3], false, 1);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            vec![5., 2., 7., 1., 8., 3.],
+        )));
+        let factor = FactorOrigin::NonUnaryFactor(0);
+
+        // Rows are labels of variable 0, columns are labels of variable 1
+        assert_eq!(cfn.min_marginal(&factor, 0), vec![2., 1.]);
+        assert_eq!(cfn.min_marginal(&factor, 1), vec![1., 2., 3.]);
+    }
+}
@@ -2,7 +2,12 @@
 
 use std::slice::Iter;
 
-use petgraph::{graph::NodeIndex, Direction::Incoming};
+use log::debug;
+use petgraph::{
+    graph::NodeIndex,
+    visit::EdgeRef,
+    Direction::{Incoming, Outgoing},
+};
 
 use super::relaxation::Relaxation;
 
@@ -15,6 +20,17 @@ impl FactorSequence {
     // Creates a factor sequence for the given relaxation
     // (i.e., all unary factors and all factors with at least one incoming edge)
     pub fn new(relaxation: &Relaxation) -> Self {
+        let isolated_nodes = relaxation.isolated_nodes();
+        if !isolated_nodes.is_empty() {
+            debug!(
+                "Factor sequence skips isolated non-unary factors at nodes {:?}.",
+                isolated_nodes
+                    .iter()
+                    .map(|node| node.index())
+                    .collect::<Vec<_>>()
+            );
+        }
+
         FactorSequence {
             sequence: relaxation
                 .node_indices()
@@ -33,7 +49,114 @@ impl FactorSequence {
         self
     }
 
+    // Sorts the factor sequence in a topological order of the given relaxation,
+    // so that every factor comes before the factors its outgoing edges point to
+    // Assumption: the relaxation graph is a directed acyclic graph
+    pub fn sort_topological(mut self, relaxation: &Relaxation) -> Self {
+        let order = relaxation
+            .topological_order()
+            .expect("Relaxation graph must be a directed acyclic graph.");
+        let mut position = vec![0; relaxation.node_count()];
+        for (index, node) in order.iter().enumerate() {
+            position[node.index()] = index;
+        }
+        self.sequence
+            .sort_unstable_by_key(|node| position[node.index()]);
+        self
+    }
+
+    // Creates a factor sequence that processes the given color classes one after another
+    pub fn from_color_classes(color_classes: &[Vec<NodeIndex<usize>>]) -> Self {
+        FactorSequence {
+            sequence: color_classes.concat(),
+        }
+    }
+
+    // Partitions the factor sequence into color classes of non-conflicting factors, greedily in sequence order.
+    // Processing a factor touches the messages along the edges incident to it or to its parents,
+    // so two factors conflict if they touch a common edge. Factors within a class can be processed in any order
+    // (or in parallel), and processing the classes one after another is equivalent to processing
+    // the factor sequence from from_color_classes().
+    pub fn color_classes(&self, relaxation: &Relaxation) -> Vec<Vec<NodeIndex<usize>>> {
+        let mut color_classes: Vec<Vec<NodeIndex<usize>>> = Vec::new();
+        let mut edge_colors = vec![Vec::new(); relaxation.edge_count()];
+
+        for factor in self.sequence.iter() {
+            let mut touched_edges = relaxation
+                .edges_directed(*factor, Outgoing)
+                .map(|edge| edge.id().index())
+                .collect::<Vec<_>>();
+            for in_edge in relaxation.edges_directed(*factor, Incoming) {
+                let parent = in_edge.source();
+                touched_edges.extend(
+                    relaxation
+                        .edges_directed(parent, Incoming)
+                        .chain(relaxation.edges_directed(parent, Outgoing))
+                        .map(|edge| edge.id().index()),
+                );
+            }
+
+            let color = (0..)
+                .find(|color| {
+                    touched_edges
+                        .iter()
+                        .all(|edge| !edge_colors[*edge].contains(color))
+                })
+                .unwrap();
+            if color == color_classes.len() {
+                color_classes.push(Vec::new());
+            }
+            color_classes[color].push(*factor);
+            for edge in touched_edges {
+                edge_colors[edge].push(color);
+            }
+        }
+        color_classes
+    }
+
     pub fn iter(&self) -> Iter<NodeIndex<usize>> {
         self.sequence.iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cfn::relaxation::ConstructRelaxation,
+        factors::{factor_type::FactorType, potts::Potts},
+        CostFunctionNetwork,
+    };
+
+    use super::*;
+
+    #[test]
+    fn color_classes() {
+        // A chain of 4 variables: neighboring variables share a factor and thus conflict
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2; 4], false, 3);
+        for variable in 0..3 {
+            cfn.add_factor(FactorType::Potts(Potts::new(
+                &cfn,
+                vec![variable, variable + 1],
+                1.,
+            )));
+        }
+        let relaxation = Relaxation::new(&cfn);
+        let factor_sequence = FactorSequence::new(&relaxation).sort();
+
+        let color_classes = factor_sequence.color_classes(&relaxation);
+        let nodes = |indices: &[usize]| {
+            indices
+                .iter()
+                .map(|index| NodeIndex::new(*index))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(color_classes, vec![nodes(&[0, 2]), nodes(&[1, 3])]);
+        assert_eq!(
+            FactorSequence::from_color_classes(&color_classes)
+                .iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            nodes(&[0, 2, 1, 3])
+        );
+    }
+}
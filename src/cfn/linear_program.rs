@@ -0,0 +1,340 @@
+#![allow(dead_code)]
+
+use std::{
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use petgraph::{visit::EdgeRef, Direction::Incoming, Direction::Outgoing};
+
+use crate::factors::factor_trait::Factor;
+
+use super::relaxation::Relaxation;
+
+// Stores a linear constraint sum_k coefficient_k * x_k >= lower_bound
+pub struct LinearConstraint {
+    coefficients: Vec<(usize, f64)>, // pairs of variable indices and (nonzero) coefficients
+    lower_bound: f64,
+}
+
+impl LinearConstraint {
+    // Returns the pairs of variable indices and coefficients
+    pub fn coefficients(&self) -> &Vec<(usize, f64)> {
+        &self.coefficients
+    }
+
+    // Returns the right-hand side of the constraint
+    pub fn lower_bound(&self) -> f64 {
+        self.lower_bound
+    }
+
+    // Computes the left-hand side of the constraint at a given point
+    fn evaluate(&self, point: &[f64]) -> f64 {
+        self.coefficients
+            .iter()
+            .map(|(variable, coefficient)| coefficient * point[*variable])
+            .sum()
+    }
+}
+
+// Stores a linear program: maximize constant + sum_k objective_k * x_k subject to linear constraints,
+// where all variables are free (unbounded in both directions)
+pub struct LinearProgram {
+    variable_names: Vec<String>,
+    objective: Vec<(usize, f64)>, // pairs of variable indices and (nonzero) objective coefficients
+    constant: f64,                // the constant term of the objective
+    constraints: Vec<LinearConstraint>,
+}
+
+impl LinearProgram {
+    // Creates an empty linear program with a given constant term of the objective
+    fn new(constant: f64) -> Self {
+        LinearProgram {
+            variable_names: Vec::new(),
+            objective: Vec::new(),
+            constant,
+            constraints: Vec::new(),
+        }
+    }
+
+    // Adds a variable with a given name and objective coefficient, returns its index
+    fn add_variable(&mut self, name: String, objective_coefficient: f64) -> usize {
+        let variable = self.variable_names.len();
+        self.variable_names.push(name);
+        if objective_coefficient != 0. {
+            self.objective.push((variable, objective_coefficient));
+        }
+        variable
+    }
+
+    // Adds a constraint sum_k coefficient_k * x_k >= lower_bound
+    // Constraints with lower_bound = -infinity are always satisfied and skipped
+    fn add_constraint(&mut self, coefficients: Vec<(usize, f64)>, lower_bound: f64) {
+        if lower_bound == f64::NEG_INFINITY {
+            return;
+        }
+        self.constraints.push(LinearConstraint {
+            coefficients,
+            lower_bound,
+        });
+    }
+
+    // Returns the number of variables
+    pub fn num_variables(&self) -> usize {
+        self.variable_names.len()
+    }
+
+    // Returns the number of constraints
+    pub fn num_constraints(&self) -> usize {
+        self.constraints.len()
+    }
+
+    // Returns the names of the variables
+    pub fn variable_names(&self) -> &Vec<String> {
+        &self.variable_names
+    }
+
+    // Returns the constraints
+    pub fn constraints(&self) -> &Vec<LinearConstraint> {
+        &self.constraints
+    }
+
+    // Returns the pairs of variable indices and objective coefficients
+    pub fn objective(&self) -> &Vec<(usize, f64)> {
+        &self.objective
+    }
+
+    // Returns the constant term of the objective
+    pub fn constant(&self) -> f64 {
+        self.constant
+    }
+
+    // Computes the value of the objective at a given point
+    pub fn objective_value(&self, point: &[f64]) -> f64 {
+        self.constant
+            + self
+                .objective
+                .iter()
+                .map(|(variable, coefficient)| coefficient * point[*variable])
+                .sum::<f64>()
+    }
+
+    // Checks if a given point satisfies all constraints up to a given tolerance
+    pub fn is_feasible(&self, point: &[f64], tolerance: f64) -> bool {
+        self.constraints
+            .iter()
+            .all(|constraint| constraint.evaluate(point) >= constraint.lower_bound - tolerance)
+    }
+
+    // Encodes the linear program in the (CPLEX) LP format, which is read by most LP solvers
+    // Note: the constant term of the objective is written as a comment, as not all solvers support it
+    pub fn to_lp_format(&self) -> String {
+        let linear_expression = |coefficients: &Vec<(usize, f64)>| {
+            let mut expression = coefficients
+                .iter()
+                .map(|(variable, coefficient)| {
+                    format!("{:+} {}", coefficient, self.variable_names[*variable])
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            if expression.is_empty() {
+                expression = format!("0 {}", self.variable_names[0]);
+            }
+            expression
+        };
+
+        let mut lp = String::new();
+        writeln!(lp, "\\ Objective constant: {}", self.constant).unwrap();
+        writeln!(lp, "Maximize").unwrap();
+        writeln!(lp, " obj: {}", linear_expression(&self.objective)).unwrap();
+        writeln!(lp, "Subject To").unwrap();
+        for (index, constraint) in self.constraints.iter().enumerate() {
+            writeln!(
+                lp,
+                " c{}: {} >= {}",
+                index,
+                linear_expression(&constraint.coefficients),
+                constraint.lower_bound
+            )
+            .unwrap();
+        }
+        writeln!(lp, "Bounds").unwrap();
+        for name in self.variable_names.iter() {
+            writeln!(lp, " {} free", name).unwrap();
+        }
+        writeln!(lp, "End").unwrap();
+        lp
+    }
+
+    // Writes the linear program to file in the LP format
+    pub fn write_lp(&self, path: PathBuf) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(self.to_lp_format().as_bytes())
+    }
+}
+
+impl<'a> Relaxation<'a> {
+    // Constructs the OSAC (optimal soft arc consistency) linear program of this relaxation, i.e., the dual
+    // of its LP relaxation, whose optimum is the best lower bound attainable by reparametrizations:
+    // - variable u[node] is the amount of cost moved from the factor of a given node to the constant term,
+    // - variable p[edge][label] is the message along a given edge for a given labeling of the edge's target,
+    // - for every node and every labeling x of its factor, the reparametrized cost is nonnegative:
+    //   theta(x) + sum_{incoming edges} p[edge][x] - sum_{outgoing edges} p[edge][x restricted] - u[node] >= 0,
+    // - the objective is to maximize the constant term of the network plus sum_{nodes} u[node].
+    // For MinimalEdges relaxations of pairwise networks, these are the arity-1 and arity-2 constraints of OSAC
+    // (the variables u of pairwise factors can be set to 0 without changing the optimum).
+    // Reparametrizations computed by message passing give feasible points: p are the messages,
+    // and u are the smallest entries of the reparametrizations (their sum is the lower bound).
+    // Labelings with infinite costs don't constrain the program, so their constraints are skipped.
+    pub fn to_osac_lp(&self) -> LinearProgram {
+        let cfn = self.cfn();
+        let mut lp = LinearProgram::new(cfn.constant());
+
+        let u = self
+            .node_indices()
+            .map(|node| lp.add_variable(format!("u_{}", node.index()), 1.))
+            .collect::<Vec<_>>();
+        let p = self
+            .edge_references()
+            .map(|edge| {
+                (0..cfn.function_table_len(self.factor_origin(edge.target())))
+                    .map(|label| lp.add_variable(format!("p_{}_{}", edge.id().index(), label), 0.))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        for node in self.node_indices() {
+            let factor_origin = self.factor_origin(node);
+            let node_variables = self.node_variables(node);
+            let table = match cfn.get_factor(factor_origin) {
+                Some(factor) => factor.clone_function_table(),
+                None => vec![0.; cfn.function_table_len(factor_origin)],
+            };
+
+            // For every outgoing edge, the positions of the target's variables among this node's variables
+            // and the domain sizes of the target's variables
+            let out_edges = self
+                .edges_directed(node, Outgoing)
+                .map(|edge| {
+                    let target_variables = self.node_variables(edge.target());
+                    let positions = target_variables
+                        .iter()
+                        .map(|variable| {
+                            node_variables
+                                .iter()
+                                .position(|node_variable| node_variable == variable)
+                                .unwrap()
+                        })
+                        .collect::<Vec<_>>();
+                    let domain_sizes = target_variables
+                        .iter()
+                        .map(|variable| cfn.domain_size(*variable))
+                        .collect::<Vec<_>>();
+                    (edge.id().index(), positions, domain_sizes)
+                })
+                .collect::<Vec<_>>();
+            let in_edges = self
+                .edges_directed(node, Incoming)
+                .map(|edge| edge.id().index())
+                .collect::<Vec<_>>();
+
+            for (index, (labeling, cost)) in cfn.labelings(factor_origin).zip(table).enumerate() {
+                let mut coefficients = vec![(u[node.index()], 1.)];
+                coefficients.extend(in_edges.iter().map(|edge| (p[*edge][index], 1.)));
+                coefficients.extend(out_edges.iter().map(|(edge, positions, domain_sizes)| {
+                    // Row-major index of the restriction of the labeling to the target's variables
+                    let target_index = positions.iter().zip(domain_sizes).fold(
+                        0,
+                        |target_index, (position, domain_size)| {
+                            target_index * domain_size + labeling[*position]
+                        },
+                    );
+                    (p[*edge][target_index], -1.)
+                }));
+                // theta(x) + incoming - outgoing - u >= 0 <=> u + outgoing - incoming <= theta(x)
+                coefficients
+                    .iter_mut()
+                    .for_each(|(_variable, coefficient)| *coefficient *= -1.);
+                lp.add_constraint(coefficients, -cost);
+            }
+        }
+
+        lp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        alg::{
+            solver::{Solver, SolverOptions},
+            srmp::SRMP,
+        },
+        cfn::relaxation::ConstructRelaxation,
+        messages::message_trait::Message,
+        CostFunctionNetwork,
+    };
+
+    use super::*;
+
+    // Constructs a frustrated cycle of 3 binary variables with pairwise "not equal" preferences
+    fn construct_cfn_cycle_3() -> CostFunctionNetwork {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2; 3], false, 3);
+        for (var_a, var_b) in [(0, 1), (0, 2), (1, 2)] {
+            cfn.add_potts_factor(var_a, var_b, -1.);
+        }
+        cfn
+    }
+
+    #[test]
+    fn osac_lp_cycle_3() {
+        let cfn = construct_cfn_cycle_3();
+        let relaxation = Relaxation::new(&cfn);
+        let lp = relaxation.to_osac_lp();
+
+        // 6 nodes (3 variables and 3 pairwise factors) and 6 edges with 2 labels each
+        assert_eq!(lp.num_variables(), 6 + 6 * 2);
+        // 2 constraints per variable and 4 per pairwise factor
+        assert_eq!(lp.num_constraints(), 3 * 2 + 3 * 4);
+        // Every constraint of a pairwise factor involves its u and two p
+        assert!(lp
+            .constraints()
+            .iter()
+            .all(|constraint| constraint.coefficients().len() == 3));
+
+        let lp_format = lp.to_lp_format();
+        assert!(lp_format.starts_with("\\ Objective constant: 0\nMaximize\n"));
+        assert_eq!(lp_format.matches(" >= ").count(), lp.num_constraints());
+        assert_eq!(lp_format.matches(" free\n").count(), lp.num_variables());
+    }
+
+    #[test]
+    fn osac_lp_feasible_point_from_srmp() {
+        let cfn = construct_cfn_cycle_3();
+        let relaxation = Relaxation::new(&cfn);
+        let lp = relaxation.to_osac_lp();
+        let srmp = SRMP::init(&relaxation)
+            .unwrap()
+            .run(&SolverOptions::default());
+
+        // Variables u (the smallest entries of reparametrizations) come first, followed by the messages
+        let messages = srmp.messages();
+        let mut point = relaxation
+            .node_indices()
+            .map(|node| *messages.compute_reparam(node).min())
+            .collect::<Vec<_>>();
+        for edge in relaxation.edge_references() {
+            point.extend(messages.message(edge.id().index()));
+        }
+        assert!(lp.is_feasible(&point, 1e-9));
+
+        // The LP optimum is -3 (each variable takes both labels with weight 1/2), while the best labeling costs -2
+        assert!((lp.objective_value(&point) + 3.).abs() < 1e-6);
+    }
+}
@@ -1,23 +1,51 @@
 #![allow(dead_code)]
 
-use std::marker::{self, PhantomData};
+use std::borrow::Cow;
 
 use log::debug;
-use petgraph::graph::{DiGraph, EdgeReferences, Edges, Neighbors, NodeIndex, NodeIndices};
+use petgraph::algo::{is_cyclic_directed, is_cyclic_undirected, toposort};
+use petgraph::graph::{
+    DiGraph, EdgeIndex, EdgeReferences, Edges, Neighbors, NodeIndex, NodeIndices,
+};
 use petgraph::Directed;
 use petgraph::Direction::{self};
 
 use crate::factors::factor_trait::Factor;
+use crate::messages::message_nd::AlignmentIndexing;
 use crate::{CostFunctionNetwork, FactorOrigin};
 
+/// Relaxation graph of a cost function network.
+///
+/// The relaxation borrows the network it was constructed for, so the network can't be modified
+/// (e.g., by adding factors) while the relaxation exists, and the relaxation can't become stale:
+///
+/// ```compile_fail,E0502
+/// use mrf_map::cfn::{
+///     cost_function_network::CostFunctionNetwork,
+///     relaxation::{ConstructRelaxation, Relaxation},
+/// };
+///
+/// let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2; 2], false, 1);
+/// let relaxation = Relaxation::new(&cfn);
+/// cfn.add_potts_factor(0, 1, 1.);
+/// assert_eq!(relaxation.node_count(), 2);
+/// ```
+///
+/// To rerun a solver on a modified network with the same factor scopes (reusing its messages),
+/// construct a relaxation for the modified network and see `SRMP::reinit()`.
 pub struct Relaxation<'a> {
-    graph: DiGraph<FactorOrigin, (), usize>,
-    cfn: marker::PhantomData<&'a CostFunctionNetwork>,
+    graph: DiGraph<FactorOrigin, AlignmentIndexing, usize>,
+    cfn: &'a CostFunctionNetwork,
 }
 
 impl<'a> Relaxation<'a> {
+    // Returns the cost function network for which the relaxation was constructed
+    pub fn cfn(&self) -> &'a CostFunctionNetwork {
+        self.cfn
+    }
+
     // Returns an iterator over all edges of the relaxation graph
-    pub fn edge_references(&self) -> EdgeReferences<(), usize> {
+    pub fn edge_references(&self) -> EdgeReferences<AlignmentIndexing, usize> {
         self.graph.edge_references()
     }
 
@@ -41,6 +69,17 @@ impl<'a> Relaxation<'a> {
         self.graph.node_weight(node).unwrap()
     }
 
+    // Returns the factor origins of the source and the target of the given edge in the relaxation graph
+    pub fn edge_endpoints_origins(&self, edge: EdgeIndex<usize>) -> (&FactorOrigin, &FactorOrigin) {
+        let (source, target) = self.graph.edge_endpoints(edge).unwrap();
+        (self.factor_origin(source), self.factor_origin(target))
+    }
+
+    // Returns the variables associated with the factor corresponding to the given node in the relaxation graph
+    pub fn node_variables(&self, node: NodeIndex<usize>) -> Cow<'a, Vec<usize>> {
+        self.cfn.factor_variables(self.factor_origin(node))
+    }
+
     // Checks if the factor corresponding to the given node in the relaxation graph is unary
     pub fn is_unary_factor(&self, node: NodeIndex<usize>) -> bool {
         match self.factor_origin(node) {
@@ -49,22 +88,27 @@ impl<'a> Relaxation<'a> {
         }
     }
 
-    // // Returns the data associated with the given edge in the relaxation graph
-    // pub fn edge_data(&self, edge: EdgeIndex<usize>) -> &() {
-    //     self.graph.edge_weight(edge).unwrap()
-    // }
+    // Returns the alignment indexing of the given edge in the relaxation graph,
+    // which aligns messages along the edge with the reparametrization of its source factor
+    pub fn edge_alignment(&self, edge: EdgeIndex<usize>) -> &AlignmentIndexing {
+        self.graph.edge_weight(edge).unwrap()
+    }
 
     // Returns an iterator over all edges incident to the given node in the relaxation graph pointing in the given direction
     pub fn edges_directed(
         &self,
         node: NodeIndex<usize>,
         direction: Direction,
-    ) -> Edges<'_, (), Directed, usize> {
+    ) -> Edges<'_, AlignmentIndexing, Directed, usize> {
         self.graph.edges_directed(node, direction)
     }
 
     // Returns an iterator over the neighbors of the given node in the relaxation graph
-    pub fn neighbors(&self, node: NodeIndex<usize>, direction: Direction) -> Neighbors<(), usize> {
+    pub fn neighbors(
+        &self,
+        node: NodeIndex<usize>,
+        direction: Direction,
+    ) -> Neighbors<AlignmentIndexing, usize> {
         self.graph.neighbors_directed(node, direction)
     }
 
@@ -73,6 +117,43 @@ impl<'a> Relaxation<'a> {
         self.neighbors(node, direction).next().is_some()
     }
 
+    // Returns the nodes of the relaxation graph that have exactly one outgoing edge,
+    // i.e., the factors that could be pruned after moving their min-marginals into their child
+    pub fn single_child_factors(&self) -> Vec<NodeIndex<usize>> {
+        self.node_indices()
+            .filter(|node| self.neighbors(*node, Direction::Outgoing).count() == 1)
+            .collect()
+    }
+
+    // Returns the nodes of non-unary factors that have no incoming and no outgoing edges in the relaxation graph,
+    // i.e., the factors that are not connected to the rest of the relaxation
+    // (they only contribute to the initial lower bound and are skipped by the factor sequence)
+    pub fn isolated_nodes(&self) -> Vec<NodeIndex<usize>> {
+        self.node_indices()
+            .filter(|node| {
+                !self.is_unary_factor(*node)
+                    && !self.has_edges(*node, Direction::Incoming)
+                    && !self.has_edges(*node, Direction::Outgoing)
+            })
+            .collect()
+    }
+
+    // Checks if the relaxation graph is a directed acyclic graph
+    pub fn is_dag(&self) -> bool {
+        !is_cyclic_directed(&self.graph)
+    }
+
+    // Returns the nodes of the relaxation graph in topological order, i.e., every edge points from an earlier node
+    // to a later one (None if the relaxation graph has a directed cycle)
+    pub fn topological_order(&self) -> Option<Vec<NodeIndex<usize>>> {
+        toposort(&self.graph, None).ok()
+    }
+
+    // Checks if the relaxation graph is a forest, i.e., if it has no cycles when edge directions are ignored
+    pub fn is_tree(&self) -> bool {
+        !is_cyclic_undirected(&self.graph)
+    }
+
     // // Creates a new zero message corresponding to the given factor (unary or non-unary)
     // pub fn message_zero(
     //     &self,
@@ -107,6 +188,20 @@ impl<'a> Relaxation<'a> {
     // }
 }
 
+#[cfg(test)]
+impl<'a> Relaxation<'a> {
+    // Removes all edges incident to the given node in the relaxation graph
+    pub fn isolate_node(&mut self, node: NodeIndex<usize>) {
+        while let Some(edge) = self
+            .graph
+            .first_edge(node, Direction::Outgoing)
+            .or_else(|| self.graph.first_edge(node, Direction::Incoming))
+        {
+            self.graph.remove_edge(edge);
+        }
+    }
+}
+
 // Trait for defining relaxation types
 pub trait RelaxationType {}
 
@@ -120,6 +215,9 @@ pub trait ConstructRelaxation<'a, RT: RelaxationType> {
     // - then we can reparametrize theta to get min_{x_a ~ x_b} theta_a(x_a) = 0 for all x_b and then remove alpha
     // - this won't affect the relaxation
     // clearly, this can be iterated or, more efficiently, processed in one go in top-sort order
+    // note: in MinimalEdges relaxations every non-unary factor has an edge to each of its (at least two) variables,
+    // so there is nothing to prune (see single_child_factors()); pruning only becomes relevant for relaxation types
+    // with edges between non-unary factors, and it has to reparametrize the network, which relaxations only borrow
 }
 
 // The minimal edges relaxation type, which consists of edges from every non-unary factor to all its associated variables
@@ -131,10 +229,7 @@ impl<'a> ConstructRelaxation<'a, MinimalEdges> for Relaxation<'a> {
         debug!("Constructing new MinimalEdges relaxation.");
 
         // Create an empty directed graph with reserved capacity for nodes and edges
-        let num_non_unary_factors = cfn
-            .factors_iter()
-            .filter(|factor| factor.arity() > 1)
-            .count();
+        let num_non_unary_factors = cfn.num_non_unary_factors();
         let edge_capacity = cfn
             .factors_iter()
             .filter_map(|factor| {
@@ -172,7 +267,7 @@ impl<'a> ConstructRelaxation<'a, MinimalEdges> for Relaxation<'a> {
                 new_node.index()
             });
 
-            // Add edges from this factor's to all its variables
+            // Add edges from this factor's to all its variables, storing the alignment indexing for each edge
             for variable in factor.variables() {
                 let variable_node = unary_nodes[*variable];
                 debug!(
@@ -180,16 +275,18 @@ impl<'a> ConstructRelaxation<'a, MinimalEdges> for Relaxation<'a> {
                     new_node.index(),
                     variable_node.index()
                 );
-                graph.add_edge(*new_node, variable_node, ());
+                let alignment = AlignmentIndexing::new(
+                    cfn,
+                    &FactorOrigin::NonUnaryFactor(factor_index),
+                    &FactorOrigin::Variable(*variable),
+                );
+                graph.add_edge(*new_node, variable_node, alignment);
             }
         }
 
         debug!("Finished constructing MinimalEdges relaxation.");
 
-        Relaxation {
-            graph,
-            cfn: PhantomData,
-        }
+        Relaxation { graph, cfn }
     }
 }
 
@@ -197,3 +294,125 @@ enum RelaxationTypes {
     MinimalEdges(MinimalEdges),
     // todo: add more relaxation methods
 }
+
+#[cfg(test)]
+mod tests {
+    use petgraph::visit::EdgeRef;
+
+    use crate::cfn::cost_function_network::tests::construct_cfn_example_1;
+
+    use super::*;
+
+    #[test]
+    fn cfn() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        assert!(std::ptr::eq(relaxation.cfn(), &cfn));
+    }
+
+    #[test]
+    fn node_variables() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+
+        for node in relaxation.node_indices() {
+            match relaxation.factor_origin(node) {
+                FactorOrigin::Variable(variable) => {
+                    assert_eq!(*relaxation.node_variables(node), vec![*variable]);
+                }
+                factor_origin => {
+                    let factor = cfn.get_factor(factor_origin).unwrap();
+                    assert_eq!(*relaxation.node_variables(node), *factor.variables());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn topological_order() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        assert!(relaxation.is_dag());
+
+        let order = relaxation.topological_order().unwrap();
+        assert_eq!(order.len(), relaxation.node_count());
+        let mut position = vec![0; relaxation.node_count()];
+        for (index, node) in order.iter().enumerate() {
+            position[node.index()] = index;
+        }
+        for edge in relaxation.edge_references() {
+            assert!(position[edge.source().index()] < position[edge.target().index()]);
+        }
+    }
+
+    #[test]
+    fn single_child_factors() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        assert!(relaxation.single_child_factors().is_empty());
+    }
+
+    #[test]
+    fn isolated_nodes() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+        assert!(relaxation.isolated_nodes().is_empty());
+
+        // Rebuild the relaxation without the edges of the non-unary factor over variables 0 and 2
+        let mut graph = DiGraph::with_capacity(relaxation.node_count(), relaxation.edge_count());
+        for node in relaxation.node_indices() {
+            let factor_origin = match relaxation.factor_origin(node) {
+                FactorOrigin::Variable(variable) => FactorOrigin::Variable(*variable),
+                FactorOrigin::NonUnaryFactor(factor_index) => {
+                    FactorOrigin::NonUnaryFactor(*factor_index)
+                }
+            };
+            graph.add_node(factor_origin);
+        }
+        let isolated_node = relaxation
+            .node_indices()
+            .find(|node| *relaxation.node_variables(*node) == vec![0, 2])
+            .unwrap();
+        for edge in relaxation
+            .edge_references()
+            .filter(|edge| edge.source() != isolated_node)
+        {
+            let (source_origin, target_origin) = relaxation.edge_endpoints_origins(edge.id());
+            let alignment = AlignmentIndexing::new(&cfn, source_origin, target_origin);
+            graph.add_edge(edge.source(), edge.target(), alignment);
+        }
+        let relaxation = Relaxation { graph, cfn: &cfn };
+        assert_eq!(relaxation.isolated_nodes(), vec![isolated_node]);
+    }
+
+    #[test]
+    fn edge_endpoints_origins() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+
+        for edge in relaxation.edge_references() {
+            let (source_origin, target_origin) = relaxation.edge_endpoints_origins(edge.id());
+            assert!(std::ptr::eq(
+                source_origin,
+                relaxation.factor_origin(edge.source())
+            ));
+            assert!(std::ptr::eq(
+                target_origin,
+                relaxation.factor_origin(edge.target())
+            ));
+        }
+    }
+
+    #[test]
+    fn edge_alignment() {
+        let cfn = construct_cfn_example_1();
+        let relaxation = Relaxation::new(&cfn);
+
+        for edge in relaxation.edge_references() {
+            let (source_origin, target_origin) = relaxation.edge_endpoints_origins(edge.id());
+            let alignment = AlignmentIndexing::new(&cfn, source_origin, target_origin);
+            assert_eq!(*relaxation.edge_alignment(edge.id()), alignment);
+            assert_eq!(*edge.weight(), alignment);
+        }
+    }
+}
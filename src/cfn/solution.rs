@@ -1,17 +1,28 @@
 #![allow(dead_code)]
 
 use std::{
+    borrow::Borrow,
     fmt::Display,
+    fs::OpenOptions,
+    hash::{Hash, Hasher},
+    io::{self, Write},
     ops::{Index, IndexMut},
+    path::PathBuf,
 };
 
-use crate::{factors::factor_trait::Factor, CostFunctionNetwork};
+use crate::{
+    cfn::cost_function_network::FactorOrigin, factors::factor_trait::Factor, CostFunctionNetwork,
+};
 
-use super::uai::option_to_string;
+use super::uai::{iter_to_string, option_to_string};
 
 // Stores a solution to a cost function network
+// Solutions are compared and hashed by their labels (unlabeled variables included), e.g., to deduplicate them
+#[derive(Clone)]
 pub struct Solution {
     labels: Vec<Option<usize>>, // indexed by variables, None = variable is unlabeled, Some(usize) = variable's label
+    // the constant term plus the costs of all fully labeled factors (None if not tracked)
+    running_cost: Option<f64>,
 }
 
 impl Solution {
@@ -19,23 +30,136 @@ impl Solution {
     pub fn new(cfn: &CostFunctionNetwork) -> Self {
         Solution {
             labels: vec![None; cfn.num_variables()],
+            running_cost: None,
+        }
+    }
+
+    // Creates a new solution as in new() that tracks its running cost (see update_running_cost())
+    pub fn with_running_cost(cfn: &CostFunctionNetwork) -> Self {
+        Solution {
+            labels: vec![None; cfn.num_variables()],
+            running_cost: Some(cfn.constant()),
         }
     }
 
-    // Checks if every variable in a given Vec is labeled
-    pub fn is_fully_labeled(&self, variables: &Vec<usize>) -> bool {
+    // Returns the running cost, i.e., the constant term plus the costs of all factors whose variables are labeled
+    // (None if not tracked); once the solution is fully labeled, this equals cost()
+    pub fn running_cost(&self) -> Option<f64> {
+        self.running_cost
+    }
+
+    // Updates the running cost (if tracked) after given variables were labeled, adding the costs of factors
+    // that became fully labeled; `variable_factors` lists the factors containing each variable
+    // (see CostFunctionNetwork::variable_factors())
+    // Note: this only visits factors containing the given variables, so the cost of extending a partial solution
+    // is proportional to the size of the extension rather than the size of the network
+    pub fn update_running_cost(
+        &mut self,
+        cfn: &CostFunctionNetwork,
+        variable_factors: &[Vec<usize>],
+        newly_labeled: &[usize],
+    ) {
+        let Some(mut running_cost) = self.running_cost else {
+            return;
+        };
+        for (position, variable) in newly_labeled.iter().enumerate() {
+            for factor_index in variable_factors[*variable].iter() {
+                let factor = cfn.factor(*factor_index);
+                // Count every factor once, for the first of the newly labeled variables in it
+                let is_counted = newly_labeled[..position]
+                    .iter()
+                    .any(|other_variable| factor.variables().contains(other_variable));
+                if !is_counted && self.is_fully_labeled(factor.variables()) {
+                    running_cost += factor.cost(cfn, self);
+                }
+            }
+        }
+        self.running_cost = Some(running_cost);
+    }
+
+    // Checks if every variable in a given slice is labeled
+    pub fn is_fully_labeled(&self, variables: &[usize]) -> bool {
         variables
             .iter()
             .all(|variable| self.labels[*variable].is_some())
     }
 
-    // Returns number of labeled variables in a given Vec
-    pub fn num_labeled(&self, variables: &Vec<usize>) -> usize {
-        variables.iter().fold(0, |num_labeled, variable| {
-            num_labeled + self.labels[*variable].is_some() as usize
+    // Returns number of labeled variables among given ones (e.g., a Vec, a slice, or an iterator over variables)
+    pub fn num_labeled<I>(&self, variables: I) -> usize
+    where
+        I: IntoIterator,
+        I::Item: Borrow<usize>,
+    {
+        variables.into_iter().fold(0, |num_labeled, variable| {
+            num_labeled + self.labels[*variable.borrow()].is_some() as usize
         })
     }
 
+    // Writes the solution to file in the UAI MPE output format, as used in the UAI competitions:
+    // the line `MPE`, followed by a line with the number of variables and the labels of all variables
+    // (see [here](https://uaicompetition.github.io/uci-2022/file-formats/solution-format/))
+    // Note: the solution must be fully labeled (see complete())
+    pub fn write_uai_mpe(&self, path: PathBuf) -> io::Result<()> {
+        assert!(
+            self.labels.iter().all(|label| label.is_some()),
+            "Only fully labeled solutions can be written."
+        );
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        writeln!(file, "MPE")?;
+        writeln!(
+            file,
+            "{} {}",
+            self.labels.len(),
+            iter_to_string(self.labels.iter().flatten())
+        )
+    }
+
+    // Overrides the running cost, e.g., after relabeling variables when the change in cost is known
+    pub(crate) fn set_running_cost(&mut self, running_cost: Option<f64>) {
+        self.running_cost = running_cost;
+    }
+
+    // Labels every unlabeled variable with the smallest label minimizing its unary factor
+    // (or with 0 if the variable has no unary factor), so that the solution becomes fully labeled
+    // Note: this stops tracking the running cost
+    pub fn complete(&mut self, cfn: &CostFunctionNetwork) {
+        self.running_cost = None;
+        for (variable, label) in self.labels.iter_mut().enumerate() {
+            if label.is_some() {
+                continue;
+            }
+            *label = Some(match cfn.get_factor(&FactorOrigin::Variable(variable)) {
+                Some(unary_factor) => {
+                    unary_factor
+                        .entries()
+                        .enumerate()
+                        .fold(
+                            (0, f64::INFINITY),
+                            |(label_min, cost_min), (label, cost)| {
+                                if cost < cost_min {
+                                    (label, cost)
+                                } else {
+                                    (label_min, cost_min)
+                                }
+                            },
+                        )
+                        .0
+                }
+                None => 0,
+            });
+        }
+    }
+
+    // Returns the labels of all variables
+    pub fn labels(&self) -> &Vec<Option<usize>> {
+        &self.labels
+    }
+
     // Returns a Vec of Strings encoding the labels
     fn labels_to_vec_string(&self) -> Vec<String> {
         self.labels
@@ -46,9 +170,25 @@ impl Solution {
 
     // Returns the solution's cost with respect to a given cost function network
     pub fn cost(&self, cfn: &CostFunctionNetwork) -> f64 {
-        cfn.factors_iter()
-            .map(|factor| factor.cost(cfn, self))
-            .sum()
+        cfn.constant()
+            + cfn
+                .factors_iter()
+                .map(|factor| factor.cost(cfn, self))
+                .sum::<f64>()
+    }
+}
+
+impl PartialEq for Solution {
+    fn eq(&self, other: &Self) -> bool {
+        self.labels == other.labels
+    }
+}
+
+impl Eq for Solution {}
+
+impl Hash for Solution {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.labels.hash(state);
     }
 }
 
@@ -60,8 +200,13 @@ impl Index<usize> for Solution {
     }
 }
 
+// Note: relabeling a labeled variable stops tracking the running cost, since the costs of factors containing it
+// are already counted; labeling an unlabeled variable keeps it (see update_running_cost())
 impl IndexMut<usize> for Solution {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        if self.labels[index].is_some() {
+            self.running_cost = None;
+        }
         &mut self.labels[index]
     }
 }
@@ -80,6 +225,266 @@ impl Display for Solution {
 
 impl From<Vec<Option<usize>>> for Solution {
     fn from(value: Vec<Option<usize>>) -> Self {
-        Solution { labels: value }
+        Solution {
+            labels: value,
+            running_cost: None,
+        }
+    }
+}
+
+impl From<&PackedSolution> for Solution {
+    fn from(value: &PackedSolution) -> Self {
+        Solution::from(value.labels().collect::<Vec<_>>())
+    }
+}
+
+// Stores a solution to a cost function network compactly, using a single usize per variable
+// (half the memory of Solution, where each Option<usize> takes 16 bytes), e.g., for networks
+// with millions of variables (see benches/packed_solution.rs)
+// Note: labels are read and written with get() and set() rather than by indexing, since Index and IndexMut
+// return references, and the labels are not stored as Option<usize> values that could be referenced
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PackedSolution {
+    labels: Vec<usize>, // indexed by variables, UNLABELED = variable is unlabeled, otherwise the variable's label
+}
+
+impl PackedSolution {
+    // The sentinel value marking unlabeled variables (not a valid label)
+    pub const UNLABELED: usize = usize::MAX;
+
+    // Creates a new solution for a given cost function network with each variable unassigned
+    pub fn new(cfn: &CostFunctionNetwork) -> Self {
+        PackedSolution {
+            labels: vec![Self::UNLABELED; cfn.num_variables()],
+        }
+    }
+
+    // Returns the label of a given variable (None if the variable is unlabeled)
+    pub fn get(&self, variable: usize) -> Option<usize> {
+        match self.labels[variable] {
+            Self::UNLABELED => None,
+            label => Some(label),
+        }
+    }
+
+    // Sets the label of a given variable (None to unlabel it)
+    pub fn set(&mut self, variable: usize, label: Option<usize>) -> &mut Self {
+        self.labels[variable] = match label {
+            Some(label) => {
+                assert_ne!(label, Self::UNLABELED, "Label {} is reserved.", label);
+                label
+            }
+            None => Self::UNLABELED,
+        };
+        self
+    }
+
+    // Returns an iterator over the labels of all variables (None = variable is unlabeled)
+    pub fn labels(&self) -> impl Iterator<Item = Option<usize>> + '_ {
+        (0..self.labels.len()).map(|variable| self.get(variable))
+    }
+
+    // Checks if every variable in a given slice is labeled
+    pub fn is_fully_labeled(&self, variables: &[usize]) -> bool {
+        variables
+            .iter()
+            .all(|variable| self.labels[*variable] != Self::UNLABELED)
+    }
+
+    // Returns number of labeled variables among given ones (e.g., a Vec, a slice, or an iterator over variables)
+    pub fn num_labeled<I>(&self, variables: I) -> usize
+    where
+        I: IntoIterator,
+        I::Item: Borrow<usize>,
+    {
+        variables.into_iter().fold(0, |num_labeled, variable| {
+            num_labeled + (self.labels[*variable.borrow()] != Self::UNLABELED) as usize
+        })
+    }
+}
+
+impl From<&Solution> for PackedSolution {
+    fn from(value: &Solution) -> Self {
+        let mut packed = PackedSolution {
+            labels: vec![Self::UNLABELED; value.labels().len()],
+        };
+        for (variable, label) in value.labels().iter().enumerate() {
+            packed.set(variable, *label);
+        }
+        packed
+    }
+}
+
+impl std::fmt::Debug for PackedSolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", Solution::from(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        hash::{DefaultHasher, Hash, Hasher},
+    };
+
+    use crate::factors::{factor_type::FactorType, function_table::FunctionTable};
+
+    use super::*;
+
+    #[test]
+    fn num_labeled() {
+        let solution = Solution::from(vec![Some(0), None, Some(2), Some(1)]);
+        let variables = vec![0, 1, 3];
+
+        assert_eq!(solution.num_labeled(&variables), 2);
+        assert_eq!(solution.num_labeled(variables.as_slice()), 2);
+        assert_eq!(solution.num_labeled(variables.clone()), 2);
+        assert_eq!(solution.num_labeled(0..solution.labels().len()), 3);
+        assert_eq!(solution.num_labeled([1]), 0);
+    }
+
+    #[test]
+    fn eq_and_hash() {
+        let solution = Solution::from(vec![Some(0), None, Some(2)]);
+        let same_solution = Solution::from(vec![Some(0), None, Some(2)]);
+        let other_solution = Solution::from(vec![Some(0), Some(0), Some(2)]);
+        assert!(solution == same_solution);
+        assert!(solution != other_solution);
+
+        let hash = |solution: &Solution| {
+            let mut hasher = DefaultHasher::new();
+            solution.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&solution), hash(&same_solution));
+
+        let seen = HashSet::from([solution, other_solution]);
+        assert!(seen.contains(&same_solution));
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn running_cost() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 3, 2], false, 0);
+        cfn.add_constant(0.5);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![1],
+            vec![1., 2., 3.],
+        )));
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            vec![1., 2., 3., 4., 5., 6.],
+        )));
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1, 2],
+            (0..12).map(|value| value as f64).collect(),
+        )));
+        let variable_factors = cfn.variable_factors();
+
+        assert_eq!(Solution::new(&cfn).running_cost(), None);
+        let mut solution = Solution::with_running_cost(&cfn);
+        assert_eq!(solution.running_cost(), Some(0.5));
+
+        // Labeling variable 1 completes its unary factor
+        solution[1] = Some(2);
+        solution.update_running_cost(&cfn, &variable_factors, &[1]);
+        assert_eq!(solution.running_cost(), Some(0.5 + 3.));
+
+        // Labeling variables 0 and 2 at once completes both remaining factors, each counted once
+        solution[0] = Some(1);
+        solution[2] = Some(0);
+        solution.update_running_cost(&cfn, &variable_factors, &[0, 2]);
+        assert_eq!(solution.running_cost(), Some(solution.cost(&cfn)));
+        assert_eq!(solution.running_cost(), Some(0.5 + 3. + 6. + 10.));
+
+        // Relabeling a labeled variable stops tracking
+        solution[1] = Some(0);
+        assert_eq!(solution.running_cost(), None);
+
+        let mut solution = Solution::with_running_cost(&cfn);
+        solution[1] = Some(2);
+        solution.update_running_cost(&cfn, &variable_factors, &[1]);
+        solution.complete(&cfn);
+        assert_eq!(solution.running_cost(), None);
+    }
+
+    #[test]
+    fn packed_solution() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 2, 4, 5], false, 0);
+        let mut solution = Solution::new(&cfn);
+        let mut packed = PackedSolution::new(&cfn);
+
+        let assignments = [
+            (2, Some(3)),
+            (0, Some(0)),
+            (2, None),
+            (3, Some(4)),
+            (1, Some(1)),
+            (2, Some(0)),
+            (0, None),
+            (0, Some(2)),
+        ];
+        let all_variables = (0..cfn.num_variables()).collect::<Vec<_>>();
+        for (variable, label) in assignments {
+            solution[variable] = label;
+            packed.set(variable, label);
+
+            for variable in 0..cfn.num_variables() {
+                assert_eq!(packed.get(variable), solution[variable]);
+            }
+            assert_eq!(
+                packed.num_labeled(&all_variables),
+                solution.num_labeled(&all_variables)
+            );
+            assert_eq!(packed.num_labeled([0, 2]), solution.num_labeled([0, 2]));
+            assert_eq!(
+                packed.is_fully_labeled(&all_variables),
+                solution.is_fully_labeled(&all_variables)
+            );
+            assert_eq!(
+                packed.is_fully_labeled(&[1, 3]),
+                solution.is_fully_labeled(&[1, 3])
+            );
+            assert!(Solution::from(&packed) == solution);
+            assert!(PackedSolution::from(&solution) == packed);
+        }
+        assert!(packed.is_fully_labeled(&all_variables));
+    }
+
+    #[test]
+    fn write_uai_mpe() {
+        let path = std::env::temp_dir().join("mrf_map_write_uai_mpe.MPE");
+        let solution = Solution::from(vec![Some(0), Some(1), Some(2)]);
+        solution.write_uai_mpe(path.clone()).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(content, "MPE\n3 0 1 2\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Only fully labeled solutions can be written.")]
+    fn write_uai_mpe_unlabeled() {
+        let path = std::env::temp_dir().join("mrf_map_write_uai_mpe_unlabeled.MPE");
+        Solution::from(vec![Some(0), None])
+            .write_uai_mpe(path)
+            .unwrap();
+    }
+
+    #[test]
+    fn complete() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 2, 4], true, 0);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![2],
+            vec![3., 1., 2., 1.],
+        )));
+
+        let mut solution = Solution::from(vec![Some(2), None, None]);
+        solution.complete(&cfn);
+        assert_eq!(solution.labels(), &vec![Some(2), Some(0), Some(1)]);
     }
 }
@@ -1,6 +1,16 @@
 #![allow(dead_code)]
 
-use std::{fmt::Debug, io, path::PathBuf, str::FromStr};
+use std::{
+    error::Error,
+    fmt::{Debug, Display},
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[cfg(feature = "gzip")]
+use flate2::read::GzDecoder;
 
 // Interface for reading from and writing to file in UAI format
 // The format specification can be found:
@@ -8,9 +18,189 @@ use std::{fmt::Debug, io, path::PathBuf, str::FromStr};
 // - [here](https://toulbar2.github.io/toulbar2/formats/uailgformat.html)
 // - [here](https://www.cs.huji.ac.il/project/PASCAL/fileFormat.php)
 // If `lg` is set to true, use the LG format, where all probabilities are replaced by their logarithm
+// Reading returns an error if the input can't be read or doesn't follow the format (see UaiParseError)
 pub trait UAI {
-    fn read_uai(path: PathBuf, lg: bool) -> Self;
+    fn read_uai(path: PathBuf, lg: bool) -> Result<Self, UaiParseError>
+    where
+        Self: Sized;
+    // Reads from a given reader (e.g., a file or an in-memory buffer)
+    fn read_uai_from<R: BufRead>(reader: R, lg: bool) -> Result<Self, UaiParseError>
+    where
+        Self: Sized;
     fn write_uai(&self, path: PathBuf, lg: bool) -> io::Result<()>;
+
+    // Reads from file, inferring the `lg` option from the file extension (see is_lg_path())
+    fn read_uai_auto(path: PathBuf) -> Result<Self, UaiParseError>
+    where
+        Self: Sized,
+    {
+        let lg = is_lg_path(&path);
+        Self::read_uai(path, lg)
+    }
+}
+
+// Checks if a given path names a file in the LG format, i.e., if its extension is `.LG` or `.lg`
+// A trailing `.gz` extension is skipped, so `grid4x4.UAI.LG.gz` is detected as well
+pub fn is_lg_path(path: &Path) -> bool {
+    let is_lg = |path: &Path| {
+        path.extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("lg"))
+    };
+    match path.extension() {
+        Some(extension) if extension.eq_ignore_ascii_case("gz") => {
+            path.file_stem().is_some_and(|stem| is_lg(Path::new(stem)))
+        }
+        _ => is_lg(path),
+    }
+}
+
+// Errors reported when reading UAI files
+#[derive(Debug)]
+pub enum UaiParseError {
+    Io(io::Error),                     // the input couldn't be read
+    UnsupportedModelType(String),      // the model type isn't MARKOV
+    InvalidToken(String),              // a token couldn't be parsed as a number
+    NumberOfDomainSizes(usize, usize), // stores the number of variables and of domain sizes
+    ZeroDomainSize(usize),             // stores the variable with an empty domain
+    ScopeLength(usize),                // stores the function with a wrong scope length
+    VariableOutOfBounds(usize, usize), // stores the function and the nonexistent variable
+    DuplicateVariable(usize, usize),   // stores the function and the variable repeated in its scope
+    TableSize(usize, usize, usize), // stores the function, the expected (saturated) and actual table size
+    NaN(usize),                     // stores the function that contains NaN
+    Overflow(usize),                // stores the function with an overflowing value (LG only)
+    UnexpectedEndOfFile,            // the input ended before all tables were read
+}
+
+impl Display for UaiParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UaiParseError::Io(error) => write!(f, "Failed to read UAI input: {}.", error),
+            UaiParseError::UnsupportedModelType(model_type) => write!(
+                f,
+                "Only MARKOV graph type is supported, found {}.",
+                model_type
+            ),
+            UaiParseError::InvalidToken(token) => write!(f, "Invalid number {}.", token),
+            UaiParseError::NumberOfDomainSizes(num_variables, num_domain_sizes) => write!(
+                f,
+                "Expected {} domain sizes, found {}.",
+                num_variables, num_domain_sizes
+            ),
+            UaiParseError::ZeroDomainSize(variable) => {
+                write!(f, "Variable {} has domain size 0.", variable)
+            }
+            UaiParseError::ScopeLength(function) => write!(
+                f,
+                "Scope length of function {} doesn't match the number of its variables.",
+                function
+            ),
+            UaiParseError::VariableOutOfBounds(function, variable) => write!(
+                f,
+                "Function {} contains variable {}, which is out of bounds.",
+                function, variable
+            ),
+            UaiParseError::DuplicateVariable(function, variable) => write!(
+                f,
+                "Function {} contains variable {} more than once.",
+                function, variable
+            ),
+            UaiParseError::TableSize(function, expected, actual) => write!(
+                f,
+                "Function {} must have {} entries, found {}.",
+                function, expected, actual
+            ),
+            UaiParseError::NaN(function) => write!(f, "Function {} contains NaN.", function),
+            UaiParseError::Overflow(function) => write!(
+                f,
+                "Function {} contains a value that overflows when exponentiated.",
+                function
+            ),
+            UaiParseError::UnexpectedEndOfFile => {
+                write!(f, "Unexpected end of file, not all functions were read.")
+            }
+        }
+    }
+}
+
+impl Error for UaiParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            UaiParseError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for UaiParseError {
+    fn from(error: io::Error) -> Self {
+        UaiParseError::Io(error)
+    }
+}
+
+// Parses a single token of a UAI file, returns an error if it isn't a valid number
+pub fn parse_token<T: FromStr>(token: &str) -> Result<T, UaiParseError> {
+    token
+        .parse::<T>()
+        .map_err(|_| UaiParseError::InvalidToken(token.to_string()))
+}
+
+// Parses whitespace-separated tokens of a UAI file, returns an error if some token isn't a valid number
+pub fn parse_tokens<T: FromStr>(string: &str) -> Result<Vec<T>, UaiParseError> {
+    string.split_whitespace().map(parse_token).collect()
+}
+
+// Returns the repeated variable in a given function scope (if any)
+pub fn find_duplicate_variable(scope: &[usize]) -> Option<usize> {
+    let mut sorted_scope = scope.to_vec();
+    sorted_scope.sort_unstable();
+    sorted_scope
+        .windows(2)
+        .find(|pair| pair[0] == pair[1])
+        .map(|pair| pair[0])
+}
+
+// Sorts the variables of a function scope (without repetitions) in increasing order and permutes its table
+// accordingly (tables are listed in row-major order, i.e., the label of the last variable changes the fastest)
+pub fn sort_scope(
+    scope: Vec<usize>,
+    table: Vec<f64>,
+    domain_sizes: &[usize],
+) -> (Vec<usize>, Vec<f64>) {
+    if scope.windows(2).all(|pair| pair[0] < pair[1]) {
+        return (scope, table);
+    }
+
+    // Offsets of the variables in the given table
+    let mut strides = vec![1; scope.len()];
+    for index in (0..scope.len() - 1).rev() {
+        strides[index] = strides[index + 1] * domain_sizes[scope[index + 1]];
+    }
+
+    // Positions of the variables in the given scope, listed in increasing order of the variables
+    let mut order = (0..scope.len()).collect::<Vec<usize>>();
+    order.sort_unstable_by_key(|position| scope[*position]);
+
+    // Enumerate the labelings of the sorted scope in row-major order and look up their values
+    let mut labels = vec![0; scope.len()];
+    let mut sorted_table = Vec::with_capacity(table.len());
+    for _ in 0..table.len() {
+        let index = labels
+            .iter()
+            .zip(order.iter())
+            .map(|(label, position)| label * strides[*position])
+            .sum::<usize>();
+        sorted_table.push(table[index]);
+        for (label, position) in labels.iter_mut().zip(order.iter()).rev() {
+            *label += 1;
+            if *label < domain_sizes[scope[*position]] {
+                break;
+            }
+            *label = 0;
+        }
+    }
+
+    let sorted_scope = order.iter().map(|position| scope[*position]).collect();
+    (sorted_scope, sorted_table)
 }
 
 // States for reading UAI files
@@ -25,6 +215,31 @@ pub enum UAIState {
     EndOfFile,
 }
 
+// The maximum number of table entries reserved before they are read,
+// so that a huge declared table size fails with an error at the end of file instead of aborting on allocation
+pub const MAX_RESERVED_TABLE_ENTRIES: usize = 1 << 20;
+
+// The first two bytes of every gzip file
+const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
+
+// Opens a (possibly gzipped) UAI file for reading line by line
+// Gzipped files are detected by their magic bytes and require the `gzip` feature
+pub fn open_uai(path: PathBuf) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if !reader.fill_buf()?.starts_with(&GZIP_MAGIC_BYTES) {
+        return Ok(Box::new(reader));
+    }
+
+    #[cfg(feature = "gzip")]
+    return Ok(Box::new(BufReader::new(GzDecoder::new(reader))));
+
+    #[cfg(not(feature = "gzip"))]
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Reading gzipped UAI files requires the `gzip` feature.",
+    ))
+}
+
 pub fn string_to_vec<T>(string: &str) -> Vec<T>
 where
     T: FromStr,
@@ -57,9 +272,39 @@ pub fn vec_mapping_to_string<T: ToString>(vec: &Vec<T>, mapping: fn(&T) -> T) ->
         .join(" ")
 }
 
+pub fn iter_to_string<T: ToString>(iter: impl Iterator<Item = T>) -> String {
+    iter.map(|elem| elem.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub fn iter_mapping_to_string<T: ToString>(
+    iter: impl Iterator<Item = T>,
+    mapping: fn(&T) -> T,
+) -> String {
+    iter.map(|elem| mapping(&elem).to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
 pub fn option_to_string<T: ToString>(option: Option<T>) -> String {
     match option {
         Some(value) => value.to_string(),
         None => "None".to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_lg_path_extensions() {
+        assert!(is_lg_path(Path::new("test_instances/grid4x4.UAI.LG")));
+        assert!(is_lg_path(Path::new("grid4x4.uai.lg")));
+        assert!(is_lg_path(Path::new("grid4x4.UAI.LG.gz")));
+        assert!(!is_lg_path(Path::new("grid4x4.uai")));
+        assert!(!is_lg_path(Path::new("grid4x4.uai.gz")));
+        assert!(!is_lg_path(Path::new("lg")));
+    }
+}
@@ -49,6 +49,18 @@ impl<T> JaggedArray2<T> {
         let idx = self.internal_index(index);
         self.data[idx] = value;
     }
+
+    /// Returns the inner array with the given index as a slice.
+    pub fn inner(&self, index: usize) -> &[T] {
+        assert!(index < self.len());
+        &self.data[self.strides[index]..self.strides[index + 1]]
+    }
+
+    /// Returns the inner array with the given index as a mutable slice.
+    pub fn inner_mut(&mut self, index: usize) -> &mut [T] {
+        assert!(index < self.len());
+        &mut self.data[self.strides[index]..self.strides[index + 1]]
+    }
 }
 
 impl<T> Index<[usize; 2]> for JaggedArray2<T> {
@@ -152,5 +164,20 @@ impl From<Vec<Vec<bool>>> for JaggedBitArray2 {
 
 #[cfg(test)]
 mod tests {
-    // todo: tests
+    use super::*;
+
+    #[test]
+    fn inner() {
+        let mut array = JaggedArray2::from(vec![vec![1, 2], vec![], vec![3, 4, 5]]);
+        assert_eq!(array.len(), 3);
+        assert_eq!(array.inner(0), &[1, 2]);
+        assert!(array.inner(1).is_empty());
+        assert_eq!(array.inner(2), &[3, 4, 5]);
+
+        array.inner_mut(2)[1] = 7;
+        assert_eq!(array[[2, 1]], 7);
+        assert_eq!(array.inner(2), &[3, 7, 5]);
+    }
+
+    // todo: more tests
 }
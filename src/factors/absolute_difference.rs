@@ -0,0 +1,154 @@
+#![allow(dead_code)]
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, Write},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cfn::{
+        solution::Solution,
+        uai::{iter_mapping_to_string, iter_to_string},
+    },
+    CostFunctionNetwork,
+};
+
+use super::factor_trait::Factor;
+
+// Stores an absolute difference (L1) factor, i.e., a pairwise factor with cost `weight * |x_0 - x_1|`
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct AbsoluteDifference {
+    variables: Vec<usize>,        // the two variables associated with this factor
+    function_table_len: usize,    // the length of the function table that this factor expands to
+    domain_sizes: (usize, usize), // the domain sizes of this factor's variables
+    weight: f64, // the cost per unit of difference between the labels of the two variables
+}
+
+impl AbsoluteDifference {
+    pub fn new(cfn: &CostFunctionNetwork, variables: Vec<usize>, weight: f64) -> Self {
+        assert_eq!(
+            variables.len(),
+            2,
+            "Absolute difference factor must be defined on exactly 2 variables."
+        );
+        assert!(
+            weight >= 0.,
+            "Absolute difference factor must have a nonnegative weight."
+        );
+        let domain_sizes = (cfn.domain_size(variables[0]), cfn.domain_size(variables[1]));
+        AbsoluteDifference {
+            variables,
+            function_table_len: domain_sizes.0 * domain_sizes.1,
+            domain_sizes,
+            weight,
+        }
+    }
+
+    // Returns the cost per unit of difference between the labels
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    // Computes `min_{x_source} source_costs[x_source] + weight * |x_source - x_target|` for every target label,
+    // where `source_costs` are indexed by the labels of one variable of this factor
+    // and the target has the given domain size
+    pub fn distance_transform(&self, source_costs: &[f64], target_domain_size: usize) -> Vec<f64> {
+        l1_distance_transform(source_costs, target_domain_size, self.weight)
+    }
+
+    // Returns an iterator over the entries of the function table that this factor expands to, without storing it
+    pub fn entries(&self) -> impl Iterator<Item = f64> + '_ {
+        (0..self.domain_sizes.0)
+            .flat_map(move |a| (0..self.domain_sizes.1).map(move |b| a.abs_diff(b) as f64))
+            .map(move |difference| difference * self.weight)
+    }
+}
+
+// Computes `min_{x_source} source_costs[x_source] + weight * |x_source - x_target|` for every target label
+// in linear time using the distance transform (a forward and a backward sweep)
+pub fn l1_distance_transform(
+    source_costs: &[f64],
+    target_domain_size: usize,
+    weight: f64,
+) -> Vec<f64> {
+    let len = source_costs.len().max(target_domain_size);
+    let mut transform = vec![f64::INFINITY; len];
+    transform[..source_costs.len()].copy_from_slice(source_costs);
+
+    for label in 1..len {
+        transform[label] = transform[label].min(transform[label - 1] + weight);
+    }
+    for label in (0..len - 1).rev() {
+        transform[label] = transform[label].min(transform[label + 1] + weight);
+    }
+
+    transform.truncate(target_domain_size);
+    transform
+}
+
+impl Factor for AbsoluteDifference {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn function_table_len(&self) -> usize {
+        self.function_table_len
+    }
+
+    fn variables(&self) -> &Vec<usize> {
+        &self.variables
+    }
+
+    fn clone_function_table(&self) -> Vec<f64> {
+        self.entries().collect()
+    }
+
+    fn map(&self, mapping: fn(f64) -> f64) -> AbsoluteDifference {
+        AbsoluteDifference {
+            variables: self.variables.clone(),
+            function_table_len: self.function_table_len,
+            domain_sizes: self.domain_sizes,
+            weight: mapping(self.weight),
+        }
+    }
+
+    fn map_inplace(&mut self, mapping: fn(&mut f64)) {
+        mapping(&mut self.weight);
+    }
+
+    fn scale(&mut self, multiplier: f64) {
+        self.weight *= multiplier;
+    }
+
+    fn value_at(&self, _cfn: &CostFunctionNetwork, labeling: &[usize]) -> f64 {
+        labeling[0].abs_diff(labeling[1]) as f64 * self.weight
+    }
+
+    fn cost(&self, _cfn: &CostFunctionNetwork, solution: &Solution) -> f64 {
+        let label_0 = solution[self.variables[0]]
+            .expect("Solution is undefined on a variable involved in this factor");
+        let label_1 = solution[self.variables[1]]
+            .expect("Solution is undefined on a variable involved in this factor");
+        label_0.abs_diff(label_1) as f64 * self.weight
+    }
+
+    fn write_uai(&self, file: &mut File, mapping: fn(&f64) -> f64) -> Result<(), io::Error> {
+        write!(
+            file,
+            "\n{}\n{}\n",
+            self.function_table_len(),
+            iter_mapping_to_string(self.entries(), mapping)
+        )
+    }
+}
+
+impl Display for AbsoluteDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", iter_to_string(self.entries()))
+    }
+}
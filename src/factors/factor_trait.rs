@@ -24,6 +24,15 @@ pub trait Factor: Display {
     // Modifies the factor in-place using the given mapping
     fn map_inplace(&mut self, mapping: fn(&mut f64));
 
+    // Multiplies all entries of the function table that this factor expands to by the given value
+    // Note: truncated factors only support nonnegative multipliers, since truncation doesn't commute with negation
+    // (FactorType::scale() supports any multiplier by expanding them into function tables first)
+    fn scale(&mut self, multiplier: f64);
+
+    // Returns the function table entry corresponding to the given labeling of this factor's variables
+    // Assumption: `labeling` contains the labels of the variables returned by variables(), in the same order
+    fn value_at(&self, cfn: &CostFunctionNetwork, labeling: &[usize]) -> f64;
+
     // Returns the cost that this factor incurs in the given cost function network for the given solution
     fn cost(&self, cfn: &CostFunctionNetwork, solution: &Solution) -> f64;
 
@@ -2,18 +2,27 @@
 
 use std::{fmt::Display, fs::File, io};
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 use crate::{cfn::solution::Solution, CostFunctionNetwork};
 
 use super::{
-    factor_trait::Factor, function_table::FunctionTable, potts::Potts,
+    absolute_difference::AbsoluteDifference, factor_trait::Factor, function_table::FunctionTable,
+    potts::Potts, truncated_linear::TruncatedLinear, truncated_quadratic::TruncatedQuadratic,
     uniform_constant::UniformConstant,
 };
 
 // Enumerates all supported factor types
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
 pub enum FactorType {
     FunctionTable(FunctionTable),
     UniformConstant(UniformConstant),
     Potts(Potts),
+    AbsoluteDifference(AbsoluteDifference),
+    TruncatedLinear(TruncatedLinear),
+    TruncatedQuadratic(TruncatedQuadratic),
 }
 
 // todo: generate match arms automatically
@@ -24,6 +33,9 @@ macro_rules! match_factor_action {
             FactorType::FunctionTable($factor_match) => $action,
             FactorType::UniformConstant($factor_match) => $action,
             FactorType::Potts($factor_match) => $action,
+            FactorType::AbsoluteDifference($factor_match) => $action,
+            FactorType::TruncatedLinear($factor_match) => $action,
+            FactorType::TruncatedQuadratic($factor_match) => $action,
         }
     };
 }
@@ -34,10 +46,67 @@ macro_rules! match_factor_wrapped_action {
             FactorType::FunctionTable($factor_match) => FactorType::FunctionTable($action),
             FactorType::UniformConstant($factor_match) => FactorType::UniformConstant($action),
             FactorType::Potts($factor_match) => FactorType::Potts($action),
+            FactorType::AbsoluteDifference($factor_match) => {
+                FactorType::AbsoluteDifference($action)
+            }
+            FactorType::TruncatedLinear($factor_match) => FactorType::TruncatedLinear($action),
+            FactorType::TruncatedQuadratic($factor_match) => {
+                FactorType::TruncatedQuadratic($action)
+            }
         }
     };
 }
 
+impl FactorType {
+    // Returns an iterator over the entries of the function table that this factor expands to,
+    // synthesizing the entries of structured factors lazily instead of storing the complete table
+    pub fn entries(&self) -> impl Iterator<Item = f64> + '_ {
+        match_factor_action!(
+            self,
+            factor,
+            Box::new(factor.entries()) as Box<dyn Iterator<Item = f64>>
+        )
+    }
+
+    // Checks if this factor encodes a hard constraint, i.e., if some entry of its function table is `+inf`
+    // (for structured factors, this happens when the penalty is `+inf`)
+    pub fn is_hard_constraint(&self) -> bool {
+        self.entries().any(|entry| entry == f64::INFINITY)
+    }
+
+    // Checks if messages from this factor can be computed with a distance transform (see distance_transform()),
+    // which requires a nonnegative weight and truncation (e.g., negated factors of maximization problems don't qualify)
+    pub fn has_distance_transform(&self) -> bool {
+        match self {
+            FactorType::AbsoluteDifference(factor) => factor.weight() >= 0.,
+            FactorType::TruncatedLinear(factor) => {
+                factor.weight() >= 0. && factor.truncation() >= 0.
+            }
+            FactorType::TruncatedQuadratic(factor) => {
+                factor.weight() >= 0. && factor.truncation() >= 0.
+            }
+            _ => false,
+        }
+    }
+
+    // For a pairwise factor whose cost only depends on the difference between labels,
+    // computes `min_{x_source} source_costs[x_source] + factor(x_source, x_target)` for every target label
+    pub fn distance_transform(&self, source_costs: &[f64], target_domain_size: usize) -> Vec<f64> {
+        match self {
+            FactorType::AbsoluteDifference(factor) => {
+                factor.distance_transform(source_costs, target_domain_size)
+            }
+            FactorType::TruncatedLinear(factor) => {
+                factor.distance_transform(source_costs, target_domain_size)
+            }
+            FactorType::TruncatedQuadratic(factor) => {
+                factor.distance_transform(source_costs, target_domain_size)
+            }
+            _ => unimplemented!("Distance transform is not supported for this factor type."),
+        }
+    }
+}
+
 impl Factor for FactorType {
     fn arity(&self) -> usize {
         match_factor_action!(self, factor, factor.arity())
@@ -63,6 +132,26 @@ impl Factor for FactorType {
         match_factor_action!(self, factor, factor.map_inplace(mapping))
     }
 
+    fn scale(&mut self, multiplier: f64) {
+        // `-min(w * d, t) != min(-w * d, -t)`, so truncated factors are expanded before negative scaling
+        if multiplier < 0. {
+            match self {
+                FactorType::TruncatedLinear(factor) => {
+                    *self = FactorType::FunctionTable(factor.to_function_table())
+                }
+                FactorType::TruncatedQuadratic(factor) => {
+                    *self = FactorType::FunctionTable(factor.to_function_table())
+                }
+                _ => {}
+            }
+        }
+        match_factor_action!(self, factor, factor.scale(multiplier))
+    }
+
+    fn value_at(&self, cfn: &CostFunctionNetwork, labeling: &[usize]) -> f64 {
+        match_factor_action!(self, factor, factor.value_at(cfn, labeling))
+    }
+
     fn cost(&self, cfn: &CostFunctionNetwork, solution: &Solution) -> f64 {
         match_factor_action!(self, factor, factor.cost(cfn, solution))
     }
@@ -77,3 +166,248 @@ impl Display for FactorType {
         match_factor_action!(self, factor, factor.fmt(f))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{cfn::solution::Solution, CostFunctionNetwork};
+
+    use super::*;
+
+    // Computes `min_{x_source} source_costs[x_source] + factor(x_source, x_target)` from the dense function table
+    fn dense_distance_transform(factor: &FactorType, source_costs: &[f64]) -> Vec<f64> {
+        let table = factor.clone_function_table();
+        let target_domain_size = table.len() / source_costs.len();
+        (0..target_domain_size)
+            .map(|target| {
+                (0..source_costs.len())
+                    .map(|source| {
+                        source_costs[source] + table[source * target_domain_size + target]
+                    })
+                    .fold(f64::INFINITY, f64::min)
+            })
+            .collect()
+    }
+
+    fn difference_factors(cfn: &CostFunctionNetwork) -> Vec<FactorType> {
+        vec![
+            FactorType::AbsoluteDifference(AbsoluteDifference::new(cfn, vec![0, 1], 1.5)),
+            FactorType::TruncatedLinear(TruncatedLinear::new(cfn, vec![0, 1], 1.5, 4.)),
+            FactorType::TruncatedQuadratic(TruncatedQuadratic::new(cfn, vec![0, 1], 0.5, 5.)),
+            FactorType::TruncatedQuadratic(TruncatedQuadratic::new(cfn, vec![0, 1], 0., 5.)),
+        ]
+    }
+
+    #[test]
+    fn clone_function_table_potts() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 3, 2], false, 2);
+        let potts = FactorType::Potts(Potts::new(&cfn, vec![0, 1], 2.5));
+        #[rustfmt::skip]
+        assert_eq!(
+            potts.clone_function_table(),
+            vec![
+                0., 2.5, 2.5,
+                2.5, 0., 2.5,
+                2.5, 2.5, 0.,
+            ]
+        );
+
+        let potts = FactorType::Potts(Potts::new(&cfn, vec![1, 2], 1.));
+        assert_eq!(potts.clone_function_table(), vec![0., 1., 1., 0., 1., 1.]);
+    }
+
+    #[test]
+    fn clone_function_table_matches_cost() {
+        let domain_sizes = vec![3, 4];
+        let cfn = CostFunctionNetwork::from_domain_sizes(&domain_sizes, false, 1);
+        let mut factors = difference_factors(&cfn);
+        factors.push(FactorType::Potts(Potts::new(&cfn, vec![0, 1], 2.5)));
+        factors.push(FactorType::UniformConstant(UniformConstant::new(
+            vec![0, 1],
+            3 * 4,
+            1.5,
+        )));
+        factors.push(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            (0..3 * 4).map(|value| value as f64).collect(),
+        )));
+
+        for factor in factors.iter() {
+            let table = factor.clone_function_table();
+            assert_eq!(table.len(), factor.function_table_len());
+            for label_0 in 0..domain_sizes[0] {
+                for label_1 in 0..domain_sizes[1] {
+                    let solution = Solution::from(vec![Some(label_0), Some(label_1)]);
+                    assert_eq!(
+                        table[label_0 * domain_sizes[1] + label_1],
+                        factor.cost(&cfn, &solution),
+                        "Factor {} at labeling ({}, {})",
+                        factor,
+                        label_0,
+                        label_1
+                    );
+                    assert_eq!(
+                        table[label_0 * domain_sizes[1] + label_1],
+                        factor.value_at(&cfn, &[label_0, label_1])
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn entries_match_clone_function_table() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 4], false, 1);
+        let mut factors = difference_factors(&cfn);
+        factors.push(FactorType::Potts(Potts::new(&cfn, vec![0, 1], 2.5)));
+        factors.push(FactorType::UniformConstant(UniformConstant::new(
+            vec![0, 1],
+            3 * 4,
+            1.5,
+        )));
+        factors.push(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            (0..3 * 4).map(|value| value as f64).collect(),
+        )));
+
+        for factor in factors.iter() {
+            assert_eq!(
+                factor.entries().collect::<Vec<_>>(),
+                factor.clone_function_table(),
+                "Factor {}",
+                factor
+            );
+        }
+    }
+
+    #[test]
+    fn is_hard_constraint() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 3], false, 1);
+        let table = FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            vec![0., 1., f64::INFINITY, 2., 3., 4.],
+        ));
+        assert!(table.is_hard_constraint());
+        let table = FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            vec![0., 1., f64::NEG_INFINITY, 2., 3., 4.],
+        ));
+        assert!(!table.is_hard_constraint());
+
+        let potts = FactorType::Potts(Potts::new(&cfn, vec![0, 1], f64::INFINITY));
+        assert!(potts.is_hard_constraint());
+        let constant =
+            FactorType::UniformConstant(UniformConstant::new(vec![0, 1], 6, f64::INFINITY));
+        assert!(constant.is_hard_constraint());
+        let truncated =
+            FactorType::TruncatedLinear(TruncatedLinear::new(&cfn, vec![0, 1], 1., f64::INFINITY));
+        assert!(!truncated.is_hard_constraint());
+
+        for factor in difference_factors(&cfn) {
+            assert!(!factor.is_hard_constraint(), "Factor {}", factor);
+        }
+        let potts = FactorType::Potts(Potts::new(&cfn, vec![0, 1], 2.5));
+        assert!(!potts.is_hard_constraint());
+    }
+
+    #[test]
+    fn scale_negative() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 4], false, 1);
+        let mut factors = difference_factors(&cfn);
+        factors.push(FactorType::Potts(Potts::new(&cfn, vec![0, 1], 2.5)));
+
+        for mut factor in factors {
+            let table = factor.clone_function_table();
+            factor.scale(-2.);
+            assert_eq!(
+                factor.clone_function_table(),
+                table.iter().map(|value| -2. * value).collect::<Vec<_>>(),
+                "Factor {}",
+                factor
+            );
+        }
+    }
+
+    #[test]
+    fn has_distance_transform() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 4], false, 1);
+        for mut factor in difference_factors(&cfn) {
+            assert!(factor.has_distance_transform(), "Factor {}", factor);
+            factor.scale(-1.);
+            assert!(!factor.has_distance_transform(), "Factor {}", factor);
+        }
+        let potts = FactorType::Potts(Potts::new(&cfn, vec![0, 1], 2.5));
+        assert!(!potts.has_distance_transform());
+    }
+
+    #[test]
+    #[should_panic(expected = "nonnegative multiplier")]
+    fn truncated_scale_negative_panics() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 4], false, 1);
+        TruncatedLinear::new(&cfn, vec![0, 1], 1., 2.).scale(-1.);
+    }
+
+    #[test]
+    fn distance_transform_matches_dense() {
+        let all_source_costs = [
+            vec![7., -2., 3., 0.5, 9., -4., 1.],
+            vec![
+                f64::INFINITY,
+                3.,
+                f64::INFINITY,
+                f64::INFINITY,
+                0.,
+                8.,
+                f64::INFINITY,
+            ],
+            vec![0.; 7],
+        ];
+        for domain_sizes in [vec![7, 7], vec![7, 4], vec![7, 10]] {
+            let cfn = CostFunctionNetwork::from_domain_sizes(&domain_sizes, false, 1);
+            for factor in difference_factors(&cfn) {
+                assert!(factor.has_distance_transform());
+                for source_costs in all_source_costs.iter() {
+                    let fast = factor.distance_transform(source_costs, domain_sizes[1]);
+                    let dense = dense_distance_transform(&factor, source_costs);
+                    assert_eq!(fast.len(), dense.len());
+                    for (fast_value, dense_value) in fast.iter().zip(dense.iter()) {
+                        assert!(
+                            fast_value == dense_value || (fast_value - dense_value).abs() < 1e-9,
+                            "Factor {}: fast {:?}, dense {:?}",
+                            factor,
+                            fast,
+                            dense
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncation_caps_penalty() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![10, 10], false, 1);
+        let linear = FactorType::TruncatedLinear(TruncatedLinear::new(&cfn, vec![0, 1], 1.5, 4.));
+        let quadratic =
+            FactorType::TruncatedQuadratic(TruncatedQuadratic::new(&cfn, vec![0, 1], 0.5, 5.));
+
+        let max = |factor: &FactorType| {
+            factor
+                .clone_function_table()
+                .into_iter()
+                .fold(f64::NEG_INFINITY, f64::max)
+        };
+        assert_eq!(max(&linear), 4.);
+        assert_eq!(max(&quadratic), 5.);
+
+        let solution = Solution::from(vec![Some(1), Some(9)]);
+        assert_eq!(linear.cost(&cfn, &solution), 4.);
+        assert_eq!(quadratic.cost(&cfn, &solution), 5.);
+        let solution = Solution::from(vec![Some(3), Some(1)]);
+        assert_eq!(linear.cost(&cfn, &solution), 3.);
+        assert_eq!(quadratic.cost(&cfn, &solution), 2.);
+    }
+}
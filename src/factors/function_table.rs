@@ -6,6 +6,9 @@ use std::{
     io::{self, Write},
 };
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 use crate::cfn::solution::Solution;
 use crate::{
     cfn::uai::{vec_mapping_to_string, vec_to_string},
@@ -15,6 +18,8 @@ use crate::{
 use super::factor_trait::Factor;
 
 // Stores the factor as a complete function table
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
 pub struct FunctionTable {
     variables: Vec<usize>, // the variables associated with this factor
     strides: Vec<usize>,   // the offsets used for indexing in the function table
@@ -24,9 +29,23 @@ pub struct FunctionTable {
 impl FunctionTable {
     // Initializes
     pub fn new(cfn: &CostFunctionNetwork, variables: Vec<usize>, value: Vec<f64>) -> Self {
+        let domain_sizes = variables
+            .iter()
+            .map(|variable| cfn.domain_size(*variable))
+            .collect::<Vec<_>>();
+        FunctionTable::from_domain_sizes(variables, &domain_sizes, value)
+    }
+
+    // Initializes from given domain sizes of the variables (in the same order as the variables)
+    pub fn from_domain_sizes(
+        variables: Vec<usize>,
+        domain_sizes: &[usize],
+        value: Vec<f64>,
+    ) -> Self {
+        // The function table is stored in row-major order, i.e., the last variable changes the fastest
         let mut strides = vec![1; variables.len()];
-        for index in 1..variables.len() {
-            strides[index] = strides[index - 1] * cfn.domain_size(variables[index]);
+        for index in (0..variables.len().saturating_sub(1)).rev() {
+            strides[index] = strides[index + 1] * domain_sizes[index + 1];
         }
 
         FunctionTable {
@@ -35,6 +54,16 @@ impl FunctionTable {
             value,
         }
     }
+
+    // Returns an iterator over the entries of the function table that this factor expands to, without storing it
+    pub fn entries(&self) -> impl Iterator<Item = f64> + '_ {
+        self.value.iter().copied()
+    }
+
+    // Returns the function table itself (in row-major order)
+    pub fn table(&self) -> &[f64] {
+        &self.value
+    }
 }
 
 impl Factor for FunctionTable {
@@ -66,16 +95,31 @@ impl Factor for FunctionTable {
         self.value.iter_mut().for_each(mapping);
     }
 
-    fn cost(&self, _cfn: &CostFunctionNetwork, solution: &Solution) -> f64 {
-        let mut index = 0;
-        for (variable_index, variable) in self.variables.iter().rev().enumerate() {
-            index += self.strides[variable_index]
-                * solution[*variable]
-                    .expect("Solution is undefined on a variable involved in this factor");
-        }
+    fn scale(&mut self, multiplier: f64) {
+        self.value.iter_mut().for_each(|value| *value *= multiplier);
+    }
+
+    fn value_at(&self, _cfn: &CostFunctionNetwork, labeling: &[usize]) -> f64 {
+        let index = labeling
+            .iter()
+            .zip(self.strides.iter())
+            .map(|(label, stride)| label * stride)
+            .sum::<usize>();
         self.value[index]
     }
 
+    fn cost(&self, cfn: &CostFunctionNetwork, solution: &Solution) -> f64 {
+        let labeling: Vec<usize> = self
+            .variables
+            .iter()
+            .map(|variable| {
+                solution[*variable]
+                    .expect("Solution is undefined on a variable involved in this factor")
+            })
+            .collect();
+        self.value_at(cfn, &labeling)
+    }
+
     fn write_uai(&self, file: &mut File, mapping: fn(&f64) -> f64) -> Result<(), io::Error> {
         write!(
             file,
@@ -91,3 +135,26 @@ impl Display for FunctionTable {
         write!(f, "{}", vec_to_string(&self.value))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_at_triplet() {
+        let cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 3, 4], false, 1);
+        let factor = FunctionTable::new(
+            &cfn,
+            vec![0, 1, 2],
+            (0..2 * 3 * 4).map(|value| value as f64).collect(),
+        );
+
+        assert_eq!(factor.value_at(&cfn, &[0, 0, 0]), 0.);
+        assert_eq!(factor.value_at(&cfn, &[1, 2, 3]), 23.);
+        assert_eq!(factor.value_at(&cfn, &[1, 0, 2]), 14.);
+        assert_eq!(factor.value_at(&cfn, &[0, 2, 1]), 9.);
+
+        let solution = Solution::from(vec![Some(0), Some(2), Some(1)]);
+        assert_eq!(factor.cost(&cfn, &solution), 9.);
+    }
+}
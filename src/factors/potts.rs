@@ -6,10 +6,13 @@ use std::{
     io::{self, Write},
 };
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 use crate::{
     cfn::{
         solution::Solution,
-        uai::{vec_mapping_to_string, vec_to_string},
+        uai::{iter_mapping_to_string, iter_to_string},
     },
     CostFunctionNetwork,
 };
@@ -17,11 +20,13 @@ use crate::{
 use super::factor_trait::Factor;
 
 // Stores a Potts factor
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
 pub struct Potts {
     variables: Vec<usize>,        // the two variables associated with this factor
     function_table_len: usize,    // the length of the function table that this factor expands to
     domain_sizes: (usize, usize), // the domain sizes of this factor's variables
-    value: f64, // the value of the Potts factor whenever the labels of this factor's variables match
+    value: f64, // the value of the Potts factor whenever the labels of this factor's variables differ (penalty)
 }
 
 impl Potts {
@@ -39,6 +44,13 @@ impl Potts {
             value,
         }
     }
+
+    // Returns an iterator over the entries of the function table that this factor expands to, without storing it
+    pub fn entries(&self) -> impl Iterator<Item = f64> + '_ {
+        (0..self.domain_sizes.0)
+            .flat_map(move |a| (0..self.domain_sizes.1).map(move |b| (a != b) as usize as f64))
+            .map(move |differ| differ * self.value)
+    }
 }
 
 impl Factor for Potts {
@@ -55,10 +67,7 @@ impl Factor for Potts {
     }
 
     fn clone_function_table(&self) -> Vec<f64> {
-        (0..self.domain_sizes.0)
-            .zip(0..self.domain_sizes.1)
-            .map(|(a, b)| (a == b) as usize as f64 * self.value)
-            .collect()
+        self.entries().collect()
     }
 
     fn map(&self, mapping: fn(f64) -> f64) -> Potts {
@@ -74,11 +83,20 @@ impl Factor for Potts {
         mapping(&mut self.value);
     }
 
+    fn scale(&mut self, multiplier: f64) {
+        self.value *= multiplier;
+    }
+
+    fn value_at(&self, _cfn: &CostFunctionNetwork, labeling: &[usize]) -> f64 {
+        (labeling[0] != labeling[1]) as usize as f64 * self.value
+    }
+
     fn cost(&self, _cfn: &CostFunctionNetwork, solution: &Solution) -> f64 {
-        solution[self.variables[0]]
-            .is_some_and(|solution_0| solution[self.variables[1]] == Some(solution_0))
-            as usize as f64
-            * self.value
+        let label_0 = solution[self.variables[0]]
+            .expect("Solution is undefined on a variable involved in this factor");
+        let label_1 = solution[self.variables[1]]
+            .expect("Solution is undefined on a variable involved in this factor");
+        (label_0 != label_1) as usize as f64 * self.value
     }
 
     fn write_uai(&self, file: &mut File, mapping: fn(&f64) -> f64) -> Result<(), io::Error> {
@@ -86,13 +104,13 @@ impl Factor for Potts {
             file,
             "\n{}\n{}\n",
             self.function_table_len(),
-            vec_mapping_to_string(&self.clone_function_table(), mapping)
+            iter_mapping_to_string(self.entries(), mapping)
         )
     }
 }
 
 impl Display for Potts {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", vec_to_string(&self.clone_function_table()))
+        write!(f, "{}", iter_to_string(self.entries()))
     }
 }
@@ -0,0 +1,171 @@
+#![allow(dead_code)]
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, Write},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cfn::{
+        solution::Solution,
+        uai::{iter_mapping_to_string, iter_to_string},
+    },
+    CostFunctionNetwork,
+};
+
+use super::{
+    absolute_difference::l1_distance_transform, factor_trait::Factor, function_table::FunctionTable,
+};
+
+// Stores a truncated linear factor, i.e., a pairwise factor with cost `min(weight * |x_0 - x_1|, truncation)`
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct TruncatedLinear {
+    variables: Vec<usize>,        // the two variables associated with this factor
+    function_table_len: usize,    // the length of the function table that this factor expands to
+    domain_sizes: (usize, usize), // the domain sizes of this factor's variables
+    weight: f64, // the cost per unit of difference between the labels of the two variables
+    truncation: f64, // the largest cost of this factor
+}
+
+impl TruncatedLinear {
+    pub fn new(
+        cfn: &CostFunctionNetwork,
+        variables: Vec<usize>,
+        weight: f64,
+        truncation: f64,
+    ) -> Self {
+        assert_eq!(
+            variables.len(),
+            2,
+            "Truncated linear factor must be defined on exactly 2 variables."
+        );
+        assert!(
+            weight >= 0. && truncation >= 0.,
+            "Truncated linear factor must have a nonnegative weight and truncation."
+        );
+        let domain_sizes = (cfn.domain_size(variables[0]), cfn.domain_size(variables[1]));
+        TruncatedLinear {
+            variables,
+            function_table_len: domain_sizes.0 * domain_sizes.1,
+            domain_sizes,
+            weight,
+            truncation,
+        }
+    }
+
+    // Returns the cost of assigning labels with the given difference to the two variables
+    fn value(&self, difference: usize) -> f64 {
+        (self.weight * difference as f64).min(self.truncation)
+    }
+
+    // Computes `min_{x_source} source_costs[x_source] + min(weight * |x_source - x_target|, truncation)`
+    // for every target label in linear time: the minimum of the L1 distance transform and the truncated cost
+    pub fn distance_transform(&self, source_costs: &[f64], target_domain_size: usize) -> Vec<f64> {
+        let source_min = source_costs.iter().copied().fold(f64::INFINITY, f64::min);
+        let mut transform = l1_distance_transform(source_costs, target_domain_size, self.weight);
+        for value in transform.iter_mut() {
+            *value = value.min(source_min + self.truncation);
+        }
+        transform
+    }
+
+    // Returns the cost per unit of difference between the labels
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    // Returns the largest cost of this factor
+    pub fn truncation(&self) -> f64 {
+        self.truncation
+    }
+
+    // Expands this factor into a function table
+    pub fn to_function_table(&self) -> FunctionTable {
+        FunctionTable::from_domain_sizes(
+            self.variables.clone(),
+            &[self.domain_sizes.0, self.domain_sizes.1],
+            self.clone_function_table(),
+        )
+    }
+
+    // Returns an iterator over the entries of the function table that this factor expands to, without storing it
+    pub fn entries(&self) -> impl Iterator<Item = f64> + '_ {
+        (0..self.domain_sizes.0)
+            .flat_map(move |a| (0..self.domain_sizes.1).map(move |b| a.abs_diff(b)))
+            .map(move |difference| self.value(difference))
+    }
+}
+
+impl Factor for TruncatedLinear {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn function_table_len(&self) -> usize {
+        self.function_table_len
+    }
+
+    fn variables(&self) -> &Vec<usize> {
+        &self.variables
+    }
+
+    fn clone_function_table(&self) -> Vec<f64> {
+        self.entries().collect()
+    }
+
+    fn map(&self, mapping: fn(f64) -> f64) -> TruncatedLinear {
+        TruncatedLinear {
+            variables: self.variables.clone(),
+            function_table_len: self.function_table_len,
+            domain_sizes: self.domain_sizes,
+            weight: mapping(self.weight),
+            truncation: mapping(self.truncation),
+        }
+    }
+
+    fn map_inplace(&mut self, mapping: fn(&mut f64)) {
+        mapping(&mut self.weight);
+        mapping(&mut self.truncation);
+    }
+
+    fn scale(&mut self, multiplier: f64) {
+        assert!(
+            multiplier >= 0.,
+            "Truncated linear factor can only be scaled by a nonnegative multiplier, use FactorType::scale() instead."
+        );
+        self.weight *= multiplier;
+        self.truncation *= multiplier;
+    }
+
+    fn value_at(&self, _cfn: &CostFunctionNetwork, labeling: &[usize]) -> f64 {
+        self.value(labeling[0].abs_diff(labeling[1]))
+    }
+
+    fn cost(&self, _cfn: &CostFunctionNetwork, solution: &Solution) -> f64 {
+        let label_0 = solution[self.variables[0]]
+            .expect("Solution is undefined on a variable involved in this factor");
+        let label_1 = solution[self.variables[1]]
+            .expect("Solution is undefined on a variable involved in this factor");
+        self.value(label_0.abs_diff(label_1))
+    }
+
+    fn write_uai(&self, file: &mut File, mapping: fn(&f64) -> f64) -> Result<(), io::Error> {
+        write!(
+            file,
+            "\n{}\n{}\n",
+            self.function_table_len(),
+            iter_mapping_to_string(self.entries(), mapping)
+        )
+    }
+}
+
+impl Display for TruncatedLinear {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", iter_to_string(self.entries()))
+    }
+}
@@ -4,8 +4,12 @@ use std::{
     fmt::Display,
     fs::File,
     io::{self, Write},
+    iter::repeat_n,
 };
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 use crate::{
     cfn::{solution::Solution, uai::repeat_float_to_string},
     CostFunctionNetwork,
@@ -14,6 +18,8 @@ use crate::{
 use super::factor_trait::Factor;
 
 // Stores a uniform constant factor
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
 pub struct UniformConstant {
     variables: Vec<usize>,     // the variables associated with this factor
     function_table_len: usize, // the length of the function table that this factor expands to
@@ -28,6 +34,11 @@ impl UniformConstant {
             value,
         }
     }
+
+    // Returns an iterator over the entries of the function table that this factor expands to, without storing it
+    pub fn entries(&self) -> impl Iterator<Item = f64> + '_ {
+        repeat_n(self.value, self.function_table_len)
+    }
 }
 
 impl Factor for UniformConstant {
@@ -59,6 +70,14 @@ impl Factor for UniformConstant {
         mapping(&mut self.value);
     }
 
+    fn scale(&mut self, multiplier: f64) {
+        self.value *= multiplier;
+    }
+
+    fn value_at(&self, _cfn: &CostFunctionNetwork, _labeling: &[usize]) -> f64 {
+        self.value
+    }
+
     fn cost(&self, _cfn: &CostFunctionNetwork, solution: &Solution) -> f64 {
         for variable in &self.variables {
             solution[*variable]
@@ -68,13 +87,12 @@ impl Factor for UniformConstant {
     }
 
     fn write_uai(&self, file: &mut File, mapping: fn(&f64) -> f64) -> Result<(), io::Error> {
-        let var_name = write!(
+        write!(
             file,
             "\n{}\n{}\n",
             self.function_table_len,
             repeat_float_to_string(self.function_table_len, mapping(&self.value))
-        );
-        var_name
+        )
     }
 }
 
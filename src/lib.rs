@@ -0,0 +1,49 @@
+// Library target with all modules, shared by the binary (main.rs) and the benchmarks (benches/)
+
+pub mod data_structures {
+    pub mod jagged_arrays;
+}
+
+pub mod factors {
+    pub mod absolute_difference;
+    pub mod factor_trait;
+    pub mod factor_type;
+    pub mod function_table;
+    pub mod potts;
+    pub mod truncated_linear;
+    pub mod truncated_quadratic;
+    pub mod uniform_constant;
+}
+
+pub mod messages {
+    pub mod message_nd;
+    pub mod message_trait;
+}
+
+pub mod alg {
+    pub mod batch;
+    pub mod exact;
+    pub mod loopy_bp;
+    #[cfg(feature = "lp")]
+    pub mod lp_exact;
+    pub mod solver;
+    pub mod srmp;
+    pub mod srmp_new;
+    pub mod trws;
+}
+
+pub mod cfn {
+    pub mod cost_function_network;
+    pub mod factor_sequence;
+    pub mod linear_program;
+    pub mod relaxation;
+    pub mod solution;
+    pub mod uai;
+}
+
+pub mod csp {
+    pub mod ac3;
+    pub mod binary_csp;
+}
+
+use cfn::cost_function_network::*;
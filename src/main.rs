@@ -1,55 +1,22 @@
-mod data_structures {
-    pub mod jagged_arrays;
-}
-
-mod factors {
-    pub mod factor_trait;
-    pub mod factor_type;
-    pub mod function_table;
-    pub mod potts;
-    pub mod uniform_constant;
-}
-
-mod messages {
-    pub mod message_nd;
-    pub mod message_trait;
-}
-
-mod alg {
-    pub mod solver;
-    pub mod srmp;
-    pub mod srmp_new;
-}
-
-mod cfn {
-    pub mod cost_function_network;
-    pub mod factor_sequence;
-    pub mod relaxation;
-    pub mod solution;
-    pub mod uai;
-}
-
-mod csp {
-    pub mod ac3;
-    pub mod binary_csp;
-}
-
 use std::time::Instant;
 
-use alg::{
-    solver::{Solver, SolverOptions},
-    srmp::SRMP,
-};
-use cfn::{
-    cost_function_network::*,
-    relaxation::{ConstructRelaxation, Relaxation},
-    uai::UAI,
+use log::{info, warn};
+use mrf_map::{
+    alg::{
+        solver::{Solver, SolverOptions},
+        srmp::SRMP,
+    },
+    cfn::{
+        cost_function_network::*,
+        relaxation::{ConstructRelaxation, Relaxation},
+        uai::UAI,
+    },
 };
-use log::info;
 
 fn main() {
-    std::env::set_var("RUST_LOG", "info"); // change "info" to "debug" for debug-level logging, etc.
-    env_logger::init();
+    // The library only uses the log facade, so the logger is set up here; RUST_LOG overrides the default level
+    // (e.g., RUST_LOG=debug for debug-level logging)
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
     let test_instance_files = std::fs::read_dir("test_instances/").unwrap();
 
@@ -65,7 +32,13 @@ fn main() {
         info!("Processing instance {}.", filename);
 
         let time_start = Instant::now();
-        let cfn = CostFunctionNetwork::read_uai(input_file, false);
+        let cfn = match CostFunctionNetwork::read_uai(input_file, false) {
+            Ok(cfn) => cfn,
+            Err(error) => {
+                warn!("Skipped instance {}: {}", filename, error);
+                continue;
+            }
+        };
         info!(
             "UAI import complete. Elapsed time {:?}.",
             time_start.elapsed()
@@ -78,7 +51,13 @@ fn main() {
             time_start.elapsed()
         );
 
-        let srmp = SRMP::init(&cfn, &relaxation);
+        let srmp = match SRMP::init(&relaxation) {
+            Ok(srmp) => srmp,
+            Err(error) => {
+                warn!("Skipped instance {}: {}", filename, error);
+                continue;
+            }
+        };
         let options = SolverOptions::default();
         srmp.run(&options);
 
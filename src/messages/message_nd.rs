@@ -6,7 +6,9 @@ use std::{
 };
 
 use crate::{
-    cfn::solution::Solution, factors::factor_trait::Factor, CostFunctionNetwork, FactorOrigin,
+    cfn::solution::Solution,
+    factors::{factor_trait::Factor, factor_type::FactorType},
+    CostFunctionNetwork, FactorOrigin,
 };
 
 use super::message_trait::Message;
@@ -14,6 +16,7 @@ use super::message_trait::Message;
 // Stores the complete reindexing information for performing binary operations on messages of different dimensions
 // See MessageND::add_assign_outgoing() and sub_assign_outgoing() on how the indices are used
 // todo: better desc
+#[derive(Debug, PartialEq)]
 pub struct AlignmentIndexing {
     index_first: Vec<usize>,
     index_second: Vec<usize>,
@@ -93,10 +96,27 @@ impl AlignmentIndexing {
     // Initializes the alignment structure for the given cost function network,
     // with `alpha` as the source factor and `beta` as the target factor
     pub fn new(cfn: &CostFunctionNetwork, alpha: &FactorOrigin, beta: &FactorOrigin) -> Self {
-        // Assumption: alpha strictly contains all variables in beta
+        // Assumption: alpha strictly contains all variables in beta, or alpha and beta have the same scope
 
         let alpha_vars = cfn.factor_variables(alpha);
         let beta_vars = cfn.factor_variables(beta);
+        if alpha_vars == beta_vars {
+            // Identity alignment: every entry of beta is aligned with the same entry of alpha
+            // (compute_indexing() doesn't support the empty set of remaining variables)
+            return AlignmentIndexing {
+                index_first: (0..cfn.function_table_len(beta)).collect(),
+                index_second: vec![0],
+            };
+        }
+        debug_assert!(
+            alpha_vars.len() > beta_vars.len()
+                && beta_vars
+                    .iter()
+                    .all(|variable| alpha_vars.contains(variable)),
+            "alpha must strictly contain beta, got alpha scope {:?} and beta scope {:?}",
+            alpha_vars,
+            beta_vars
+        );
         let diff_vars = cfn.get_variables_difference(alpha, beta);
         let alpha_ft_len = cfn.function_table_len(alpha);
         let beta_ft_len = cfn.function_table_len(beta);
@@ -126,6 +146,48 @@ impl Message for MessageND {
         Self::OutgoingAlignment::new(cfn, alpha, beta)
     }
 
+    fn zero(cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin) -> Self {
+        MessageND {
+            value: vec![0.; cfn.function_table_len(factor_origin)],
+        }
+    }
+
+    fn inf(cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin) -> Self {
+        MessageND {
+            value: vec![f64::INFINITY; cfn.function_table_len(factor_origin)],
+        }
+    }
+
+    fn clone_factor(cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin) -> Self {
+        match cfn.get_factor(factor_origin) {
+            Some(factor) => MessageND {
+                value: factor.clone_function_table(),
+            },
+            None => MessageND::zero(cfn, factor_origin),
+        }
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        MessageND {
+            value: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn clone_factor_into(&mut self, cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin) {
+        self.value.clear();
+        match cfn.get_factor(factor_origin) {
+            // function tables are copied directly, avoiding the dynamic dispatch in FactorType::entries()
+            Some(FactorType::FunctionTable(factor)) => self.value.extend(factor.entries()),
+            Some(factor) => self.value.extend(factor.entries()),
+            None => self.value.resize(cfn.function_table_len(factor_origin), 0.),
+        }
+    }
+
+    fn zero_into(&mut self, cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin) {
+        self.value.clear();
+        self.value.resize(cfn.function_table_len(factor_origin), 0.);
+    }
+
     fn iter(&self) -> Iter<f64> {
         self.value.iter()
     }
@@ -134,8 +196,30 @@ impl Message for MessageND {
         self.value.iter_mut()
     }
 
+    fn as_slice(&self) -> &[f64] {
+        &self.value
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [f64] {
+        &mut self.value
+    }
+
+    fn len(&self) -> usize {
+        self.value.len()
+    }
+
     fn min(&self) -> &f64 {
-        self.value.iter().min_by(|a, b| a.total_cmp(b)).unwrap()
+        self.value
+            .iter()
+            .min_by(|a, b| a.total_cmp(b))
+            .unwrap_or(&f64::INFINITY)
+    }
+
+    fn max(&self) -> &f64 {
+        self.value
+            .iter()
+            .max_by(|a, b| a.total_cmp(b))
+            .unwrap_or(&f64::NEG_INFINITY)
     }
 
     fn index_min(&self) -> usize {
@@ -152,19 +236,19 @@ impl Message for MessageND {
             .0
     }
 
-    fn add_assign_incoming(&mut self, rhs: &Self) {
+    fn add_assign_incoming(&mut self, rhs: &[f64]) {
         for (val, rhs_val) in self.iter_mut().zip(rhs.iter()) {
             *val += rhs_val;
         }
     }
 
-    fn sub_assign_incoming(&mut self, rhs: &Self) {
+    fn sub_assign_incoming(&mut self, rhs: &[f64]) {
         for (val, rhs_val) in self.iter_mut().zip(rhs.iter()) {
             *val -= rhs_val;
         }
     }
 
-    fn add_assign_outgoing(&mut self, rhs: &Self, outgoing_alignment: &Self::OutgoingAlignment) {
+    fn add_assign_outgoing(&mut self, rhs: &[f64], outgoing_alignment: &Self::OutgoingAlignment) {
         for (first_index, first) in outgoing_alignment.index_first.iter().enumerate() {
             for second in outgoing_alignment.index_second.iter() {
                 self.value[*first + *second] += rhs[first_index];
@@ -172,7 +256,7 @@ impl Message for MessageND {
         }
     }
 
-    fn sub_assign_outgoing(&mut self, rhs: &Self, outgoing_alignment: &Self::OutgoingAlignment) {
+    fn sub_assign_outgoing(&mut self, rhs: &[f64], outgoing_alignment: &Self::OutgoingAlignment) {
         for (first_index, first) in outgoing_alignment.index_first.iter().enumerate() {
             for second in outgoing_alignment.index_second.iter() {
                 self.value[*first + *second] -= rhs[first_index];
@@ -192,25 +276,25 @@ impl Message for MessageND {
         }
     }
 
-    fn set_to_reparam_min(
-        &mut self,
-        rhs: &Self,
+    fn reparam_min_into(
+        &self,
+        message: &mut [f64],
         outgoing_alignment: &Self::OutgoingAlignment,
     ) -> f64 {
         // todo: describe implementation details
 
-        let mut rhs_min = f64::INFINITY;
+        let mut reparam_min = f64::INFINITY;
         for (first_index, first) in outgoing_alignment.index_first.iter().enumerate() {
             let tmp_min = outgoing_alignment
                 .index_second
                 .iter()
-                .map(|second| rhs.value[*first + *second])
+                .map(|second| self.value[*first + *second])
                 .min_by(|a, b| a.total_cmp(b))
                 .unwrap();
-            self.value[first_index] = tmp_min;
-            rhs_min = rhs_min.min(tmp_min);
+            message[first_index] = tmp_min;
+            reparam_min = reparam_min.min(tmp_min);
         }
-        rhs_min
+        reparam_min
     }
 
     fn restricted_min(
@@ -333,6 +417,11 @@ impl Message for MessageND {
 
         let num_unlabeled = labeling.len();
 
+        if num_unlabeled == 0 {
+            // Everything is already labeled
+            return;
+        }
+
         if num_unlabeled == arity {
             // Everything is unlabeled
             let mut index_min = self.index_min();
@@ -372,37 +461,53 @@ impl Index<usize> for MessageND {
     type Output = f64;
 
     fn index(&self, index: usize) -> &Self::Output {
+        self.debug_assert_in_bounds(index);
         &self.value[index]
     }
 }
 
 impl IndexMut<usize> for MessageND {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.debug_assert_in_bounds(index);
         &mut self.value[index]
     }
 }
 
-// todo: match on factortype, return corresponding messagetype, individual implementations
 impl MessageND {
-    pub fn zero(cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin) -> Self {
-        MessageND {
-            value: vec![0.; cfn.function_table_len(factor_origin)],
-        }
+    /// Creates a message with the given entries.
+    ///
+    /// ```
+    /// use mrf_map::messages::{message_nd::MessageND, message_trait::Message};
+    ///
+    /// let message = MessageND::from_vec(vec![3., -1., 7.]);
+    /// assert_eq!(message.len(), 3);
+    /// assert_eq!(*message.min(), -1.);
+    /// ```
+    pub fn from_vec(values: Vec<f64>) -> Self {
+        MessageND { value: values }
     }
 
-    pub fn inf(cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin) -> Self {
-        MessageND {
-            value: vec![f64::INFINITY; cfn.function_table_len(factor_origin)],
-        }
+    // Checks that the given index is within the message (in debug builds only),
+    // reporting the message length on failure to help with debugging alignment issues
+    #[inline]
+    fn debug_assert_in_bounds(&self, index: usize) {
+        debug_assert!(
+            index < self.value.len(),
+            "index {} out of bounds for message of length {}",
+            index,
+            self.value.len()
+        );
     }
 
-    pub fn clone_factor(cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin) -> Self {
-        match cfn.get_factor(factor_origin) {
-            Some(factor) => MessageND {
-                value: factor.clone_function_table(),
-            },
-            None => MessageND::zero(cfn, factor_origin),
-        }
+    // Checks if this message has the same length as `other` and all their entries differ by at most `eps`
+    // (infinite entries are considered equal if they are the same)
+    pub fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        self.value.len() == other.value.len()
+            && self
+                .value
+                .iter()
+                .zip(other.value.iter())
+                .all(|(lhs, rhs)| lhs == rhs || (lhs - rhs).abs() <= eps)
     }
 }
 
@@ -446,27 +551,341 @@ mod tests {
         assert_eq!(alignment.index_second, expected.index_second);
     }
 
+    #[test]
+    fn identity_alignment() {
+        // Two factors with the same scope, e.g., added by a relaxation with edges between equal-scope factors
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 4], false, 2);
+        for _ in 0..2 {
+            cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn,
+                vec![0, 1],
+                vec![0.; 3 * 4],
+            )));
+        }
+        let alpha_origin = FactorOrigin::NonUnaryFactor(0);
+        let beta_origin = FactorOrigin::NonUnaryFactor(1);
+
+        let alignment = AlignmentIndexing::new(&cfn, &alpha_origin, &beta_origin);
+        assert_eq!(alignment.index_first, (0..12).collect::<Vec<_>>());
+        assert_eq!(alignment.index_second, vec![0]);
+
+        // Messages pass through the identity alignment unchanged
+        let rhs = (0..12)
+            .map(|value| (value * value) as f64 - 7.5)
+            .collect::<Vec<_>>();
+        let mut outgoing = MessageND::from_vec((0..12).map(|value| value as f64).collect());
+        let mut incoming = MessageND::from_vec((0..12).map(|value| value as f64).collect());
+        outgoing.add_assign_outgoing(&rhs, &alignment);
+        incoming.add_assign_incoming(&rhs);
+        assert_eq!(outgoing, incoming);
+        outgoing.sub_assign_outgoing(&rhs, &alignment);
+        outgoing.sub_assign_outgoing(&rhs, &alignment);
+        incoming.sub_assign_incoming(&rhs);
+        incoming.sub_assign_incoming(&rhs);
+        assert_eq!(outgoing, incoming);
+
+        let mut message = vec![0.; 12];
+        let reparam_min = outgoing.reparam_min_into(&mut message, &alignment);
+        assert_eq!(message, outgoing.as_slice());
+        assert_eq!(reparam_min, *outgoing.min());
+    }
+
+    // Labels of variables, each paired with a flag showing if the variable belongs to beta
+    type FlaggedLabels = Vec<(usize, bool)>;
+
+    // Computes the expected alignment indexing by enumerating all labelings of alpha in row-major order:
+    // index_first maps each labeling of beta (with other variables labeled 0) to its index in alpha,
+    // and index_second does the same for labelings of the remaining variables
+    fn brute_force_indexing(
+        domain_sizes: &[usize],
+        alpha_variables: &[usize],
+        beta_variables: &[usize],
+    ) -> (Vec<usize>, Vec<usize>) {
+        let row_major_index = |labels: &[usize], variables: &[usize]| {
+            labels
+                .iter()
+                .zip(variables.iter())
+                .fold(0, |index, (label, variable)| {
+                    index * domain_sizes[*variable] + label
+                })
+        };
+        let in_beta: Vec<bool> = alpha_variables
+            .iter()
+            .map(|variable| beta_variables.contains(variable))
+            .collect();
+        let diff_variables: Vec<usize> = alpha_variables
+            .iter()
+            .copied()
+            .filter(|variable| !beta_variables.contains(variable))
+            .collect();
+
+        let len = |variables: &[usize]| -> usize {
+            variables
+                .iter()
+                .map(|variable| domain_sizes[*variable])
+                .product()
+        };
+        let mut index_first = vec![usize::MAX; len(beta_variables)];
+        let mut index_second = vec![usize::MAX; len(&diff_variables)];
+
+        let mut labels = vec![0; alpha_variables.len()];
+        for alpha_index in 0..len(alpha_variables) {
+            let (beta_labels, diff_labels): (FlaggedLabels, FlaggedLabels) = labels
+                .iter()
+                .copied()
+                .zip(in_beta.iter().copied())
+                .partition(|(_label, is_beta)| *is_beta);
+            let beta_labels: Vec<usize> = beta_labels.into_iter().map(|(label, _)| label).collect();
+            let diff_labels: Vec<usize> = diff_labels.into_iter().map(|(label, _)| label).collect();
+
+            if diff_labels.iter().all(|label| *label == 0) {
+                index_first[row_major_index(&beta_labels, beta_variables)] = alpha_index;
+            }
+            if beta_labels.iter().all(|label| *label == 0) {
+                index_second[row_major_index(&diff_labels, &diff_variables)] = alpha_index;
+            }
+
+            // Advance to the next labeling of alpha
+            for position in (0..labels.len()).rev() {
+                labels[position] += 1;
+                if labels[position] < domain_sizes[alpha_variables[position]] {
+                    break;
+                }
+                labels[position] = 0;
+            }
+        }
+
+        (index_first, index_second)
+    }
+
+    #[test]
+    fn alignment_indexing_mixed_domains() {
+        let domain_sizes = vec![2, 7, 3, 4];
+        let scopes: Vec<(Vec<usize>, Vec<usize>)> = vec![
+            (vec![0, 1, 2], vec![1]),
+            (vec![0, 1, 2], vec![0]),
+            (vec![0, 1, 2], vec![2]),
+            (vec![0, 1, 2], vec![0, 2]),
+            (vec![0, 1, 2], vec![1, 2]),
+            (vec![0, 1, 2, 3], vec![1, 3]),
+            (vec![0, 1, 2, 3], vec![0, 1, 2]),
+            (vec![1, 3], vec![3]),
+            (vec![0, 3], vec![0]),
+        ];
+
+        for (alpha_variables, beta_variables) in scopes {
+            let mut cfn = CostFunctionNetwork::from_domain_sizes(&domain_sizes, false, 2);
+            let alpha_len = alpha_variables
+                .iter()
+                .map(|variable| domain_sizes[*variable])
+                .product();
+            cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                &cfn,
+                alpha_variables.clone(),
+                vec![0.; alpha_len],
+            )));
+            let alpha_origin = FactorOrigin::NonUnaryFactor(0);
+            let beta_origin = match beta_variables.len() {
+                1 => FactorOrigin::Variable(beta_variables[0]),
+                _ => {
+                    let beta_len = beta_variables
+                        .iter()
+                        .map(|variable| domain_sizes[*variable])
+                        .product();
+                    cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+                        &cfn,
+                        beta_variables.clone(),
+                        vec![0.; beta_len],
+                    )));
+                    FactorOrigin::NonUnaryFactor(1)
+                }
+            };
+
+            let alignment = AlignmentIndexing::new(&cfn, &alpha_origin, &beta_origin);
+            let (index_first, index_second) =
+                brute_force_indexing(&domain_sizes, &alpha_variables, &beta_variables);
+
+            assert_eq!(
+                alignment.index_first, index_first,
+                "index_first for alpha {:?}, beta {:?}",
+                alpha_variables, beta_variables
+            );
+            assert_eq!(
+                alignment.index_second, index_second,
+                "index_second for alpha {:?}, beta {:?}",
+                alpha_variables, beta_variables
+            );
+        }
+    }
+
     #[test]
     fn restricted_min() {
         // todo: create instance by hand for independence
         let cfn = CostFunctionNetwork::read_uai(
             "test_instances/frustrated_cycle_5_sym.uai".into(),
             false,
-        );
+        )
+        .unwrap();
 
         let alpha = FactorOrigin::NonUnaryFactor(1);
         let beta = FactorOrigin::Variable(2);
         let solution = vec![Some(0), Some(1), None, None, None].into();
-        let message = MessageND {
-            value: vec![3., 4., 0., 1.],
-        };
+        let message = MessageND::from_vec(vec![3., 4., 0., 1.]);
 
         let restricted_min = message.restricted_min(&cfn, &solution, &alpha, &beta);
-        let expected = MessageND {
-            value: vec![0., 1.],
-        };
+        let expected = MessageND::from_vec(vec![0., 1.]);
+
+        assert!(
+            restricted_min.approx_eq(&expected, 1e-9),
+            "{:?} != {:?}",
+            restricted_min,
+            expected
+        );
+    }
+
+    #[test]
+    fn single_label_variable() {
+        // Variable 0 has a single label, so its "advance to next label" step is always skipped
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![1, 3], false, 1);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            vec![2., 0., 1.],
+        )));
+        let alpha = FactorOrigin::NonUnaryFactor(0);
+        let message = MessageND::clone_factor(&cfn, &alpha);
+
+        let solution = vec![None, None].into();
+        let restricted_min =
+            message.restricted_min(&cfn, &solution, &alpha, &FactorOrigin::Variable(0));
+        assert_eq!(restricted_min, MessageND::from_vec(vec![0.]));
+
+        let solution = vec![Some(0), None].into();
+        let restricted_min =
+            message.restricted_min(&cfn, &solution, &alpha, &FactorOrigin::Variable(1));
+        assert_eq!(restricted_min, MessageND::from_vec(vec![2., 0., 1.]));
+
+        let mut solution = vec![None, Some(2)].into();
+        message.update_solution_restricted_min(&cfn, &alpha, &mut solution);
+        assert_eq!((solution[0], solution[1]), (Some(0), Some(2)));
+
+        let mut solution = vec![Some(0), None].into();
+        message.update_solution_restricted_min(&cfn, &alpha, &mut solution);
+        assert_eq!((solution[0], solution[1]), (Some(0), Some(1)));
+
+        let mut solution = vec![None, None].into();
+        message.update_solution_restricted_min(&cfn, &alpha, &mut solution);
+        assert_eq!((solution[0], solution[1]), (Some(0), Some(1)));
+
+        let mut solution = vec![Some(0), Some(0)].into();
+        message.update_solution_restricted_min(&cfn, &alpha, &mut solution);
+        assert_eq!((solution[0], solution[1]), (Some(0), Some(0)));
+    }
+
+    #[test]
+    fn min_max() {
+        let message = MessageND::from_vec(vec![3., -1., 7., 0.5]);
+        assert_eq!(*message.min(), -1.);
+        assert_eq!(*message.max(), 7.);
+    }
+
+    #[test]
+    fn min_max_empty() {
+        let message = MessageND::from_vec(Vec::new());
+        assert_eq!(*message.min(), f64::INFINITY);
+        assert_eq!(*message.max(), f64::NEG_INFINITY);
+    }
 
-        assert_eq!(restricted_min, expected);
+    #[test]
+    fn zero_len() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 4, 5], false, 0);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 2],
+            vec![0.; 3 * 5],
+        )));
+
+        for factor_origin in [FactorOrigin::Variable(1), FactorOrigin::NonUnaryFactor(0)] {
+            let message = MessageND::zero(&cfn, &factor_origin);
+            assert_eq!(message.len(), cfn.function_table_len(&factor_origin));
+            assert!(!message.is_empty());
+        }
+    }
+
+    #[test]
+    fn zero_into() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 4, 5], false, 0);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 2],
+            vec![1.; 3 * 5],
+        )));
+
+        let mut message = MessageND::from_vec(vec![7.; 20]);
+        let capacity = message.value.capacity();
+        for factor_origin in [FactorOrigin::NonUnaryFactor(0), FactorOrigin::Variable(1)] {
+            message.zero_into(&cfn, &factor_origin);
+            assert_eq!(message, MessageND::zero(&cfn, &factor_origin));
+            assert_eq!(message.value.capacity(), capacity);
+        }
+    }
+
+    #[test]
+    fn clone_factor_into() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![3, 4, 5], false, 0);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 2],
+            (0..3 * 5).map(|value| value as f64).collect(),
+        )));
+
+        let mut message = MessageND::with_capacity(cfn.max_function_table_len());
+        let buffer = message.as_slice().as_ptr();
+        for factor_origin in [FactorOrigin::NonUnaryFactor(0), FactorOrigin::Variable(1)] {
+            message.clone_factor_into(&cfn, &factor_origin);
+            assert_eq!(message, MessageND::clone_factor(&cfn, &factor_origin));
+            assert_eq!(message.as_slice().as_ptr(), buffer);
+        }
+    }
+
+    #[test]
+    fn approx_eq() {
+        let message = MessageND::from_vec(vec![1., -2., f64::INFINITY]);
+        let other = MessageND::from_vec(vec![1. + 1e-12, -2., f64::INFINITY]);
+        assert_ne!(message, other);
+        assert!(message.approx_eq(&other, 1e-9));
+        assert!(!message.approx_eq(&other, 1e-13));
+
+        let shorter = MessageND::from_vec(vec![1., -2.]);
+        assert!(!message.approx_eq(&shorter, 1e-9));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "alpha must strictly contain beta, got alpha scope [0, 1] and beta scope [2]"
+    )]
+    fn alignment_indexing_not_contained() {
+        let mut cfn = CostFunctionNetwork::from_domain_sizes(&vec![2, 3, 4], false, 1);
+        cfn.add_factor(FactorType::FunctionTable(FunctionTable::new(
+            &cfn,
+            vec![0, 1],
+            vec![0.; 2 * 3],
+        )));
+        AlignmentIndexing::new(
+            &cfn,
+            &FactorOrigin::NonUnaryFactor(0),
+            &FactorOrigin::Variable(2),
+        );
+    }
+
+    // The message with the length is reported by a debug assertion (release builds panic with Vec's message)
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "index 3 out of bounds for message of length 3")]
+    fn index_out_of_bounds() {
+        let message = MessageND::from_vec(vec![0.; 3]);
+        let _ = message[3];
     }
 
     // todo: add tests for remaining functions
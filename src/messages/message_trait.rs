@@ -27,31 +27,76 @@ pub trait Message: Index<usize> + IndexMut<usize> {
         beta: &FactorOrigin,
     ) -> Self::OutgoingAlignment;
 
+    // Creates a message for a given factor with all entries equal to zero
+    fn zero(cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin) -> Self;
+
+    // Creates a message for a given factor with all entries equal to infinity
+    fn inf(cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin) -> Self;
+
+    // Creates a message for a given factor and initializes it with the factor's function table
+    // (or with zeros if the factor is not present in the cost function network)
+    fn clone_factor(cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin) -> Self;
+
+    // Creates an empty message with reserved capacity for the given number of entries
+    // (to be used as a reusable buffer, see clone_factor_into())
+    fn with_capacity(capacity: usize) -> Self;
+
+    // Overwrites this message with the function table of a given factor as in clone_factor(),
+    // reusing the allocated memory if it is large enough
+    fn clone_factor_into(&mut self, cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin);
+
+    // Overwrites this message with zeros for a given factor as in zero(),
+    // reusing the allocated memory if it is large enough
+    fn zero_into(&mut self, cfn: &CostFunctionNetwork, factor_origin: &FactorOrigin);
+
     // Returns an iterator over the entries of this message
     fn iter(&self) -> Iter<f64>;
 
     // Returns a mutable iterator over the entries of this message
     fn iter_mut(&mut self) -> IterMut<f64>;
 
+    // Returns the entries of this message as a slice
+    fn as_slice(&self) -> &[f64];
+
+    // Returns the entries of this message as a mutable slice
+    fn as_mut_slice(&mut self) -> &mut [f64];
+
+    // Returns the number of entries in this message
+    fn len(&self) -> usize;
+
+    // Returns true if this message has no entries
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     // Returns the smallest entry in the message
+    // Note: costs are minimized, so lower bound updates (e.g., the contribution of a reparametrized factor)
+    // always use the smallest entry of a reparametrization
+    // An empty message (e.g., an unfilled buffer created with with_capacity()) has no labelings,
+    // so its smallest entry is infinity, the identity of the minimum
     fn min(&self) -> &f64;
 
+    // Returns the largest entry in the message (negative infinity for an empty message)
+    fn max(&self) -> &f64;
+
     // Returns the index of the smallest entry in the message
     fn index_min(&self) -> usize;
 
+    // Note: operations with other messages take their entries as slices
+
     // Adds an incoming message to this message
-    fn add_assign_incoming(&mut self, rhs: &Self);
+    fn add_assign_incoming(&mut self, rhs: &[f64]);
 
     // Subtracts an incoming message from this message
-    fn sub_assign_incoming(&mut self, rhs: &Self);
+    fn sub_assign_incoming(&mut self, rhs: &[f64]);
 
     // Adds an outgoing message to this message (with the help of the given alignment struct)
     // Assumption: `self` and `rhs` are aligned using `outgoing_alignment`
-    fn add_assign_outgoing(&mut self, rhs: &Self, outgoing_alignment: &Self::OutgoingAlignment);
+    fn add_assign_outgoing(&mut self, rhs: &[f64], outgoing_alignment: &Self::OutgoingAlignment);
 
     // Subtracts an outgoing message from this message (with the help of the given alignment struct)
     // Assumption: `self` and `rhs` are aligned using `outgoing_alignment`
-    fn sub_assign_outgoing(&mut self, rhs: &Self, outgoing_alignment: &Self::OutgoingAlignment);
+    fn sub_assign_outgoing(&mut self, rhs: &[f64], outgoing_alignment: &Self::OutgoingAlignment);
 
     // Multiplies all entries of this message by the given value
     fn mul_assign_scalar(&mut self, rhs: f64);
@@ -59,12 +104,12 @@ pub trait Message: Index<usize> + IndexMut<usize> {
     // Adds the given value to all entries of this message
     fn add_assign_scalar(&mut self, rhs: f64);
 
-    // Computes the minimum from equation (17) in the SRMP paper over a given reparametrization,
-    // assigns the result to this message, and returns the smallest value (for normalization purposes)
-    // Assumption: `self` and `rhs` are aligned using `outgoing_alignment`
-    fn set_to_reparam_min(
-        &mut self,
-        rhs: &Self,
+    // Computes the minimum from equation (17) in the SRMP paper over this reparametrization,
+    // assigns the result to a given message, and returns the smallest value (for normalization purposes)
+    // Assumption: `message` and `self` are aligned using `outgoing_alignment`
+    fn reparam_min_into(
+        &self,
+        message: &mut [f64],
         outgoing_alignment: &Self::OutgoingAlignment,
     ) -> f64;
 
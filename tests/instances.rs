@@ -0,0 +1,55 @@
+// Regression tests running SRMP on the instances from test_instances/
+
+use mrf_map::{
+    alg::{
+        solver::{Solver, SolverOptions},
+        srmp::SRMP,
+    },
+    cfn::{
+        cost_function_network::CostFunctionNetwork,
+        relaxation::{ConstructRelaxation, Relaxation},
+        uai::UAI,
+    },
+};
+
+// Number of SRMP iterations used in regression tests
+const MAX_ITERATIONS: usize = 100;
+
+// Lower bounds reached by SRMP on instances from test_instances/ within MAX_ITERATIONS iterations
+// (these agree with the bounds of the reference C++ implementation in the *.srmp_cpp.ans files)
+// Note: grid4x4.UAI.LG overflows when exponentiated, so it is read with the lg option off, as in the reference
+const EXPECTED_LOWER_BOUNDS: [(&str, f64); 11] = [
+    ("example_1.uai", -40.),
+    ("frustrated_cycle_3.uai", 0.),
+    ("frustrated_cycle_3_sym.uai", 0.),
+    ("frustrated_cycle_4.uai", 0.),
+    ("frustrated_cycle_5.uai", 0.),
+    ("frustrated_cycle_5_sym.uai", 0.),
+    ("frustrated_tangle.uai", 0.),
+    ("grid4x4.UAI.LG", -27432.609180010517),
+    ("one_variable_inconsistent.uai", 1.),
+    ("three_variable_consistent.uai", 0.),
+    ("two_variable_inconsistent.uai", 1.),
+];
+
+#[test]
+fn test_instances_lower_bounds() {
+    let mut options = SolverOptions::default();
+    options.set_max_iterations(MAX_ITERATIONS);
+
+    for (filename, expected_lower_bound) in EXPECTED_LOWER_BOUNDS {
+        let cfn =
+            CostFunctionNetwork::read_uai(format!("test_instances/{}", filename).into(), false)
+                .unwrap();
+        let relaxation = Relaxation::new(&cfn);
+        let result = SRMP::solve(&relaxation, &options).unwrap();
+        assert!(
+            (result.lower_bound() - expected_lower_bound).abs() < 1e-6,
+            "instance {}: lower bound {} != {}",
+            filename,
+            result.lower_bound(),
+            expected_lower_bound
+        );
+        assert!(result.best_cost().unwrap() >= result.lower_bound() - 1e-6);
+    }
+}
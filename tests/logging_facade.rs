@@ -0,0 +1,49 @@
+// Checks that the solvers log through the `log` facade, so the embedding application chooses the logger
+// This test installs a global logger, so it lives in its own test binary to avoid affecting other tests
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use mrf_map::{
+    alg::{
+        solver::{Solver, SolverOptions},
+        srmp::SRMP,
+    },
+    cfn::{
+        cost_function_network::CostFunctionNetwork,
+        relaxation::{ConstructRelaxation, Relaxation},
+        uai::UAI,
+    },
+};
+
+// Counts log records instead of printing them
+struct CountingLogger(AtomicUsize);
+
+impl Log for CountingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, _record: &Record) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CountingLogger = CountingLogger(AtomicUsize::new(0));
+
+#[test]
+fn logging_uses_facade() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Info);
+
+    let cfn = CostFunctionNetwork::read_uai("test_instances/example_1.uai".into(), false).unwrap();
+    let relaxation = Relaxation::new(&cfn);
+    let mut options = SolverOptions::default();
+    options.set_max_iterations(2);
+    SRMP::solve(&relaxation, &options).unwrap();
+
+    // The records of the run went to the logger chosen by the embedding application
+    assert!(LOGGER.0.load(Ordering::Relaxed) > 0);
+}